@@ -0,0 +1,131 @@
+/// Largest number of decimals whose scale fits into `u64`.
+pub const MAX_DECIMALS: u32 = 19;
+
+/// How amounts are shown to the user. Amounts are always integer base units
+/// on chain, the denomination only moves the decimal point for display.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Denomination {
+    pub decimals: u32,
+}
+
+impl Denomination {
+    pub fn new(decimals: u32) -> Self {
+        assert!(decimals <= MAX_DECIMALS, "too many decimals");
+        Self { decimals }
+    }
+
+    /// Number of base units in a whole coin.
+    fn scale(&self) -> u64 {
+        10u64.pow(self.decimals)
+    }
+
+    /// Formats base units as a decimal string with all the decimals, like
+    /// `1.50000000` for 8 decimals.
+    pub fn format(&self, amount: u64) -> String {
+        if self.decimals == 0 {
+            return amount.to_string();
+        }
+        let scale = self.scale();
+        format!(
+            "{}.{:0width$}",
+            amount / scale,
+            amount % scale,
+            width = self.decimals as usize
+        )
+    }
+
+    /// Parses a decimal string like `1.5` into base units. Fails if the
+    /// amount is more precise than the decimals allow or doesn't fit.
+    pub fn parse(&self, s: &str) -> Result<u64, String> {
+        let s = s.trim();
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        if whole.is_empty() && fraction.is_empty() {
+            return Err("not a decimal number".to_string());
+        }
+        if !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+        {
+            return Err("not a decimal number".to_string());
+        }
+
+        // Trailing zeros add no precision.
+        let fraction = fraction.trim_end_matches('0');
+        if fraction.len() > self.decimals as usize {
+            return Err(format!("amount has more than {} decimals", self.decimals));
+        }
+
+        const TOO_LARGE: &str = "amount is too large";
+        // Only the digits are left, so parsing fails just on overflow.
+        let whole = match whole {
+            "" => 0,
+            digits => digits.parse::<u64>().map_err(|_| TOO_LARGE)?,
+        };
+        let fraction = match fraction {
+            "" => 0,
+            digits => {
+                let padding = 10u64.pow(self.decimals - digits.len() as u32);
+                digits.parse::<u64>().map_err(|_| TOO_LARGE)? * padding
+            }
+        };
+        whole
+            .checked_mul(self.scale())
+            .and_then(|units| units.checked_add(fraction))
+            .ok_or_else(|| TOO_LARGE.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Denomination, MAX_DECIMALS};
+
+    #[test]
+    fn format_amounts() {
+        let coins = Denomination::new(8);
+        assert_eq!(coins.format(150_000_000), "1.50000000");
+        assert_eq!(coins.format(1), "0.00000001");
+        assert_eq!(coins.format(0), "0.00000000");
+        assert_eq!(coins.format(u64::MAX), "184467440737.09551615");
+        assert_eq!(Denomination::new(0).format(1500), "1500");
+        assert_eq!(
+            Denomination::new(MAX_DECIMALS).format(u64::MAX),
+            "1.8446744073709551615"
+        );
+    }
+
+    #[test]
+    fn parse_amounts() {
+        let coins = Denomination::new(8);
+        assert_eq!(coins.parse("1.5"), Ok(150_000_000));
+        assert_eq!(coins.parse(" 2 "), Ok(200_000_000));
+        assert_eq!(coins.parse("2."), Ok(200_000_000));
+        assert_eq!(coins.parse(".00000001"), Ok(1));
+        // Trailing zeros past the decimals lose nothing.
+        assert_eq!(coins.parse("1.5000000000"), Ok(150_000_000));
+        assert_eq!(coins.parse("184467440737.09551615"), Ok(u64::MAX));
+
+        assert!(coins.parse("0.000000001").is_err());
+        assert!(coins.parse("184467440737.09551616").is_err());
+        assert!(coins.parse("99999999999999999999").is_err());
+        for invalid in ["", ".", "-1", "+1", "1.2.3", "1,5", "1e8"] {
+            assert!(coins.parse(invalid).is_err(), "{:?} is parsed", invalid);
+        }
+
+        let units = Denomination::new(0);
+        assert_eq!(units.parse("1500"), Ok(1500));
+        assert_eq!(units.parse("1500.000"), Ok(1500));
+        assert!(units.parse("1500.5").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        for decimals in [0, 1, 8, MAX_DECIMALS] {
+            let denomination = Denomination::new(decimals);
+            for amount in [0, 1, 10, 100_000_000, 123_456_789, u64::MAX] {
+                let formatted = denomination.format(amount);
+                assert_eq!(denomination.parse(&formatted), Ok(amount), "{}", formatted);
+            }
+        }
+    }
+}
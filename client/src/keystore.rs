@@ -0,0 +1,145 @@
+use aes_gcm::aead::Aead;
+use aes_gcm::{Aes256Gcm, Key, KeyInit, Nonce};
+use k256::ecdsa::SigningKey;
+use ledger_types::B256;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+
+/// Password-encrypted signing key, stored as JSON.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    /// Address of the stored key, kept in plain text for identification.
+    pub address: String,
+    pub kdf: KdfParams,
+    pub cipher: CipherParams,
+}
+
+/// Parameters of the scrypt key derivation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub salt: String,
+    pub log_n: u8,
+    pub r: u32,
+    pub p: u32,
+}
+
+impl KdfParams {
+    /// Creates parameters with a fresh random salt.
+    fn new(log_n: u8, r: u32, p: u32) -> Self {
+        let mut salt = [0; 32];
+        rand::thread_rng().fill_bytes(&mut salt);
+        Self {
+            salt: hex::encode(salt),
+            log_n,
+            r,
+            p,
+        }
+    }
+
+    /// Derives the encryption key from the password.
+    fn derive(&self, password: &str) -> Option<[u8; 32]> {
+        let salt = hex::decode(&self.salt).ok()?;
+        let params = scrypt::Params::new(self.log_n, self.r, self.p, 32).ok()?;
+        let mut key = [0; 32];
+        scrypt::scrypt(password.as_bytes(), &salt, &params, &mut key).ok()?;
+        Some(key)
+    }
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self::new(15, 8, 1)
+    }
+}
+
+/// Parameters and output of the AES-256-GCM encryption.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CipherParams {
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+impl Keystore {
+    /// Encrypts the signing key with the password.
+    pub fn encrypt(signer: &SigningKey, password: &str) -> Self {
+        Self::encrypt_with(signer, password, KdfParams::default())
+    }
+
+    fn encrypt_with(signer: &SigningKey, password: &str, kdf: KdfParams) -> Self {
+        let key = kdf.derive(password).expect("kdf params should be valid");
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+
+        let mut nonce = [0; 12];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), signer.to_bytes().as_slice())
+            .expect("signing key should be encrypted");
+
+        Self {
+            address: B256::address_of(signer.verifying_key()).to_string(),
+            kdf,
+            cipher: CipherParams {
+                nonce: hex::encode(nonce),
+                ciphertext: hex::encode(ciphertext),
+            },
+        }
+    }
+
+    /// Decrypts the signing key. Returns `None` if the password is wrong.
+    pub fn decrypt(&self, password: &str) -> Option<SigningKey> {
+        let key = self.kdf.derive(password)?;
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key));
+
+        let nonce = hex::decode(&self.cipher.nonce).ok()?;
+        if nonce.len() != 12 {
+            return None;
+        }
+        let ciphertext = hex::decode(&self.cipher.ciphertext).ok()?;
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+            .ok()?;
+
+        SigningKey::from_slice(&plaintext).ok()
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("keystore should be serialized")
+    }
+
+    pub fn from_json(s: &str) -> Option<Self> {
+        serde_json::from_str(s).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::SigningKey;
+
+    use super::{KdfParams, Keystore};
+
+    fn fast_kdf() -> KdfParams {
+        KdfParams::new(4, 8, 1)
+    }
+
+    #[test]
+    fn encrypt_and_decrypt() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let keystore = Keystore::encrypt_with(&signer, "secret", fast_kdf());
+
+        let json = keystore.to_json();
+        let decrypted = Keystore::from_json(&json)
+            .unwrap()
+            .decrypt("secret")
+            .unwrap();
+
+        assert_eq!(decrypted.to_bytes(), signer.to_bytes());
+    }
+
+    #[test]
+    fn wrong_password() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let keystore = Keystore::encrypt_with(&signer, "secret", fast_kdf());
+
+        assert!(keystore.decrypt("not a secret").is_none());
+    }
+}
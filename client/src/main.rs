@@ -1,89 +1,1016 @@
+mod amount;
+mod keystore;
+
 use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
-use clap::Parser;
+use amount::{Denomination, MAX_DECIMALS};
+use clap::{Args, Parser, Subcommand};
 use k256::ecdsa::SigningKey;
-use ledger_transport::Transport;
-use ledger_types::{Message, Transaction, TransactionData, B256};
+use keystore::Keystore;
+use ledger_transport::{network_key, Transport, TransportConfig};
+use ledger_types::{
+    AckStatus, Block, Message, Reply, ReplyTo, Response, Signature, Transaction,
+    TransactionBuilder, B256, MAX_MEMO_LEN,
+};
 
-/// Command line parameters of the simple-ledger node.
+/// Command line parameters of the simple-ledger client.
 #[derive(Debug, Parser)]
 struct Params {
-    /// Socket address of the client.
-    #[clap(short, long)]
-    socket: Option<SocketAddr>,
+    #[command(subcommand)]
+    command: Command,
 
-    /// Hex representation of a signing key.
-    #[clap(short, long)]
-    key: Option<String>,
+    /// Number of decimals amounts are shown and entered with. Balances stay
+    /// integer base units on chain.
+    #[clap(long, global = true, default_value_t = 0,
+        value_parser = clap::value_parser!(u32).range(0..=MAX_DECIMALS as i64))]
+    decimals: u32,
+}
 
-    /// Socket address of the node to communicate.
-    #[clap(short, long)]
-    node: Option<SocketAddr>,
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Manage accounts.
+    #[command(subcommand)]
+    Account(AccountCommand),
+
+    /// Get balance.
+    Balance {
+        #[command(flatten)]
+        connection: Connection,
+
+        #[command(flatten)]
+        key: KeyArgs,
+
+        /// Number of the block to get the balance right after. The tip by default.
+        #[clap(long)]
+        at_height: Option<u64>,
+    },
 
     /// Perform transfer.
-    #[clap(short, long)]
-    transfer_to: Option<String>,
+    Transfer {
+        #[command(flatten)]
+        connection: Connection,
+
+        #[command(flatten)]
+        key: KeyArgs,
+
+        /// Address of the recipient.
+        #[clap(short, long)]
+        to: String,
+
+        /// Amount to transfer, with the decimals.
+        #[clap(short, long)]
+        amount: String,
+
+        /// Fee paid to the proposer of the block including the transfer.
+        #[clap(long, default_value = "0")]
+        fee: String,
+
+        /// Nonce distinguishing equal transfers. Random if not set.
+        #[clap(long)]
+        nonce: Option<u64>,
+
+        /// Note attached to the transfer, at most 64 bytes.
+        #[clap(long)]
+        memo: Option<String>,
+    },
+
+    /// Perform the transfers listed in a CSV file of `to,amount[,memo]` lines.
+    TransferBatch {
+        #[command(flatten)]
+        connection: Connection,
+
+        #[command(flatten)]
+        key: KeyArgs,
+
+        /// Path of the CSV file. Empty lines and lines starting with `#` are
+        /// skipped.
+        #[clap(long)]
+        file: PathBuf,
+
+        /// Fee paid for each transfer.
+        #[clap(long, default_value = "0")]
+        fee: String,
+    },
+
+    /// Export the chain of the node to a JSON file.
+    Export {
+        #[command(flatten)]
+        connection: Connection,
+
+        /// Path of the file to write.
+        #[clap(short, long)]
+        out: PathBuf,
+    },
+
+    /// Fetch the signed state snapshot of the node to bootstrap another node.
+    Snapshot {
+        #[command(flatten)]
+        connection: Connection,
 
-    /// Amount to transfer.
+        /// Number of the block the state is taken after.
+        #[clap(long)]
+        height: u64,
+
+        /// Path of the file to write.
+        #[clap(short, long)]
+        out: PathBuf,
+    },
+
+    /// Print the mined transaction with the hash.
+    Tx {
+        #[command(flatten)]
+        connection: Connection,
+
+        /// Hash of the transaction.
+        hash: String,
+    },
+
+    /// Print the block with the number and its proposer as JSON.
+    Block {
+        #[command(flatten)]
+        connection: Connection,
+
+        /// Number of the block.
+        number: u64,
+    },
+
+    /// Check that the node is alive, printing its height, peers and sync state.
+    Health {
+        #[command(flatten)]
+        connection: Connection,
+    },
+
+    /// Print the hash and number of the last block of the node.
+    Tip {
+        #[command(flatten)]
+        connection: Connection,
+    },
+
+    /// Check whether a transfer with the fee would make it into the next block.
+    Estimate {
+        #[command(flatten)]
+        connection: Connection,
+
+        /// Fee of the transfer.
+        #[clap(long)]
+        fee: String,
+    },
+
+    /// Print the accounts with a nonzero balance and their balances.
+    Accounts {
+        #[command(flatten)]
+        connection: Connection,
+    },
+
+    /// Print the peers known to the node.
+    Peers {
+        #[command(flatten)]
+        connection: Connection,
+    },
+
+    /// Check offline whether the signature of the hash is made by the address.
+    Verify {
+        /// Hex hash of the signed data.
+        #[clap(long)]
+        hash: String,
+
+        /// Hex signature: `r`, `s` and the recovery id.
+        #[clap(long)]
+        signature: String,
+
+        /// Hex address of the expected signer.
+        #[clap(long)]
+        address: String,
+    },
+
+    /// Stream new blocks and print transactions involving the address.
+    Watch {
+        #[command(flatten)]
+        connection: Connection,
+
+        /// Address to watch.
+        #[clap(short, long)]
+        address: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum AccountCommand {
+    /// Generate a new account.
+    New {
+        /// Path of the keystore file to write. Prints the raw key if not specified.
+        #[clap(short, long)]
+        out: Option<PathBuf>,
+    },
+}
+
+/// Sockets used to communicate with a node.
+#[derive(Debug, Args)]
+struct Connection {
+    /// Socket address of the client, as `host:port`.
     #[clap(short, long)]
-    amount: Option<u64>,
+    socket: String,
 
-    /// Get balance.
+    /// Socket address of the node to communicate, as `host:port`.
     #[clap(short, long)]
-    balance: bool,
+    node: String,
 
-    /// Generate a new account.
+    /// Prefer IPv6 addresses when a host name resolves to both versions.
+    #[clap(long)]
+    prefer_ipv6: bool,
+
+    /// Milliseconds to wait for a response before resending a request.
+    #[clap(long, default_value_t = 1000)]
+    timeout: u64,
+
+    /// Number of times a request is resent if no response arrives.
+    #[clap(long, default_value_t = 3)]
+    retries: u32,
+
+    /// Secret of the private network the node is in.
+    #[clap(long)]
+    network_secret: Option<String>,
+}
+
+impl Connection {
+    /// Resolved socket address of the client.
+    fn socket(&self) -> SocketAddr {
+        resolve_socket(&self.socket, self.prefer_ipv6)
+    }
+
+    /// Resolved socket address of the node.
+    fn node(&self) -> SocketAddr {
+        resolve_socket(&self.node, self.prefer_ipv6)
+    }
+}
+
+/// Resolves a `host:port` string, exiting if it can't be resolved.
+fn resolve_socket(s: &str, prefer_ipv6: bool) -> SocketAddr {
+    ledger_transport::resolve(s, prefer_ipv6)
+        .unwrap_or_else(|e| exit_with_error(&format!("can't resolve {}: {}", s, e)))
+}
+
+/// Source of the signing key.
+#[derive(Debug, Args)]
+#[group(required = true, multiple = false)]
+struct KeyArgs {
+    /// Hex representation of a signing key.
     #[clap(short, long)]
-    crate_account: bool,
+    key: Option<String>,
+
+    /// Path of a password-encrypted keystore file.
+    #[clap(long)]
+    keystore: Option<PathBuf>,
+}
+
+impl KeyArgs {
+    /// Loads the signing key, prompting for a password if a keystore is used.
+    fn signer(&self) -> SigningKey {
+        if let Some(path) = &self.keystore {
+            let json = std::fs::read_to_string(path)
+                .unwrap_or_else(|e| exit_with_error(&format!("can't read keystore: {}", e)));
+            let keystore = Keystore::from_json(&json)
+                .unwrap_or_else(|| exit_with_error("invalid keystore file"));
+            let password =
+                rpassword::prompt_password("Password: ").expect("password should be read");
+            return keystore
+                .decrypt(&password)
+                .unwrap_or_else(|| exit_with_error("wrong keystore password"));
+        }
+
+        let key = self.key.as_ref().expect("client key should be specified");
+        load_signer(key).unwrap_or_else(|e| exit_with_error(&e))
+    }
+}
+
+/// Parses a hex representation of a signing key.
+fn load_signer(key: &str) -> Result<SigningKey, String> {
+    const INVALID_KEY: &str = "invalid key: expected 64 hex characters";
+    let bytes = hex::decode(key.trim()).map_err(|_| INVALID_KEY.to_string())?;
+    if bytes.len() != 32 {
+        return Err(INVALID_KEY.to_string());
+    }
+    SigningKey::from_slice(&bytes)
+        .map_err(|_| "invalid key: not a valid secp256k1 scalar".to_string())
 }
 
+/// How often the watching client renews its subscription.
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(10);
+
 fn main() {
     let params = Params::parse();
+    let denomination = Denomination::new(params.decimals);
+    let parse_amount = |s: &str| {
+        denomination
+            .parse(s)
+            .unwrap_or_else(|e| exit_with_error(&format!("invalid amount {:?}: {}", s, e)))
+    };
 
-    if params.crate_account {
-        let key = SigningKey::random(&mut rand::thread_rng());
+    match params.command {
+        Command::Account(AccountCommand::New { out }) => new_account(out),
+        Command::Balance {
+            connection,
+            key,
+            at_height,
+        } => balance(connection, key.signer(), at_height, denomination),
+        Command::Transfer {
+            connection,
+            key,
+            to,
+            amount,
+            fee,
+            nonce,
+            memo,
+        } => {
+            let memo = memo.unwrap_or_default();
+            if memo.len() > MAX_MEMO_LEN {
+                exit_with_error(&format!("memo is longer than {} bytes", MAX_MEMO_LEN));
+            }
+            let builder = TransactionBuilder::new()
+                .amount(parse_amount(&amount))
+                .fee(parse_amount(&fee))
+                .nonce(nonce.unwrap_or_else(rand::random))
+                .memo(memo);
+            transfer(connection, key.signer(), to, builder)
+        }
+        Command::TransferBatch {
+            connection,
+            key,
+            file,
+            fee,
+        } => transfer_batch(
+            connection,
+            key.signer(),
+            file,
+            parse_amount(&fee),
+            denomination,
+        ),
+        Command::Export { connection, out } => export(connection, out),
+        Command::Snapshot {
+            connection,
+            height,
+            out,
+        } => snapshot(connection, height, out),
+        Command::Tx { connection, hash } => transaction(connection, hash, denomination),
+        Command::Block { connection, number } => block(connection, number),
+        Command::Health { connection } => health(connection),
+        Command::Tip { connection } => tip(connection),
+        Command::Estimate { connection, fee } => {
+            estimate(connection, parse_amount(&fee), denomination)
+        }
+        Command::Accounts { connection } => accounts(connection, denomination),
+        Command::Peers { connection } => peers(connection),
+        Command::Verify {
+            hash,
+            signature,
+            address,
+        } => match verify_signature(&hash, &signature, &address) {
+            Ok(()) => println!("Signature is valid"),
+            Err(e) => exit_with_error(&e),
+        },
+        Command::Watch {
+            connection,
+            address,
+        } => watch(connection, address, denomination),
+    }
+}
+
+fn new_account(out: Option<PathBuf>) {
+    let key = SigningKey::random(&mut rand::thread_rng());
+    println!("Address: {}", B256::address_of(key.verifying_key()));
+
+    let Some(path) = out else {
         let hex_repr = hex::encode(key.to_bytes().as_slice());
         println!("Generated key: {}", hex_repr);
+        return;
     };
 
-    if params.balance {
-        let socket = params.socket.expect("client socket should be specified");
-        let key = params.key.expect("client key should be specified");
-        let node_socket = params.node.expect("node socket should be specified");
+    let password = rpassword::prompt_password("New password: ").expect("password should be read");
+    let confirmation =
+        rpassword::prompt_password("Repeat password: ").expect("password should be read");
+    assert_eq!(password, confirmation, "passwords should match");
+
+    let keystore = Keystore::encrypt(&key, &password);
+    std::fs::write(&path, keystore.to_json()).expect("keystore file should be written");
+    println!("Keystore written to {}", path.display());
+}
+
+fn balance(
+    connection: Connection,
+    signer: SigningKey,
+    at_height: Option<u64>,
+    denomination: Denomination,
+) {
+    let address = B256::address_of(signer.verifying_key());
+    println!("Address: {}", address);
 
-        let key_bytes = hex::decode(key).expect("client key should be a valid hex string");
-        let signer = SigningKey::from_bytes(key_bytes.as_slice().into()).unwrap();
-        let address = B256::address_of(signer.verifying_key());
-        println!("Address: {}", address);
+    let transport = bind(&connection);
+    let response = request(&transport, &connection, |reply_to| match at_height {
+        Some(height) => Message::BalanceOfAt(reply_to, address, height),
+        None => Message::BalanceOf(reply_to, address),
+    })
+    .unwrap_or_else(|| exit_with_error("no balance response from the node"));
+    let (balance, final_balance) = match response {
+        Response::Balance(balance, final_balance) => (balance, final_balance),
+        Response::BalancePruned(height) => exit_with_error(&format!(
+            "the node no longer keeps the state at height {}",
+            height
+        )),
+        _ => exit_with_error("unexpected response from the node"),
+    };
+    println!(
+        "Balance: {} (final: {})",
+        denomination.format(balance),
+        denomination.format(final_balance)
+    );
+}
+
+/// Sends a request built for a fresh request id and waits for the matching reply,
+/// resending the request on timeout. Datagrams that don't answer this request are
+/// discarded.
+fn request(
+    transport: &Transport,
+    connection: &Connection,
+    build: impl FnOnce(ReplyTo) -> Message,
+) -> Option<Response> {
+    let reply_to = ReplyTo {
+        socket: connection.socket(),
+        request_id: rand::random(),
+    };
+    let message = build(reply_to);
+    let timeout = Duration::from_millis(connection.timeout.max(1));
 
-        let transport = Transport::new(socket).expect("client transport should be initialized");
+    for _ in 0..=connection.retries {
         transport
-            .send(node_socket, &Message::BalanceOf(socket, address))
-            .expect("balance request should be sent");
-        let balance = transport
-            .receive::<u64>()
-            .expect("balance response should be received");
-        println!("Balance: {}", balance);
-        return;
+            .send(connection.node(), &message)
+            .expect("request should be sent");
+
+        let sent_at = Instant::now();
+        while let Some(remaining) = timeout.checked_sub(sent_at.elapsed()) {
+            if remaining.is_zero() {
+                break;
+            }
+            transport
+                .set_read_timeout(Some(remaining))
+                .expect("read timeout should be set");
+
+            match transport.receive::<Reply>() {
+                Ok(reply) if reply.request_id == reply_to.request_id => {
+                    return Some(reply.response)
+                }
+                Ok(_) => {}
+                Err(e) if e.is_timeout() => {}
+                Err(e) => eprintln!("warning: {}", e),
+            }
+        }
     }
 
-    if let Some(to) = params.transfer_to {
-        let socket = params.socket.expect("client socket should be specified");
-        let key = params.key.expect("client key should be specified");
-        let node_socket = params.node.expect("node socket should be specified");
-        let amount = params.amount.expect("transfer amount should be specified");
+    None
+}
 
-        let key_bytes = hex::decode(key).expect("client key should be a valid hex string");
-        let signer = SigningKey::from_bytes(key_bytes.as_slice().into()).unwrap();
-        let address = B256::address_of(signer.verifying_key());
-        println!("Address: {}", address);
+/// Binds the client's socket, exiting if it can't be bound.
+fn bind(connection: &Connection) -> Transport {
+    let config = TransportConfig {
+        network_key: connection.network_secret.as_deref().map(network_key),
+        ..Default::default()
+    };
+    Transport::with_config(connection.socket(), config)
+        .unwrap_or_else(|e| exit_with_error(&format!("can't bind {}: {}", connection.socket(), e)))
+}
 
-        let transport = Transport::new(socket).expect("client transport should be initialized");
-        let to = B256::from_hex_string(&to).unwrap();
-        let data = TransactionData { to, amount };
-        let transaction = Transaction::new(data, &signer);
-        transport
-            .send(node_socket, &Message::Transaction(transaction))
-            .expect("transaction request should be sent");
+/// Prints the error and exits with a non-zero code.
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(1)
+}
+
+fn transfer(connection: Connection, signer: SigningKey, to: String, builder: TransactionBuilder) {
+    let address = B256::address_of(signer.verifying_key());
+    println!("Address: {}", address);
+
+    let transport = bind(&connection);
+    let to = B256::from_hex_string(&to)
+        .unwrap_or_else(|e| exit_with_error(&format!("invalid recipient: {}", e)));
+    let transaction = builder.to(to).sign(&signer);
+    println!("Transaction: {}", transaction.hash);
+
+    let response = request(&transport, &connection, |reply_to| {
+        Message::SubmitTransaction(reply_to, transaction)
+    })
+    .unwrap_or_else(|| exit_with_error("no transaction ack from the node"));
+    match response {
+        Response::TransactionAck(_, AckStatus::Accepted) => println!("Accepted"),
+        Response::TransactionAck(_, AckStatus::Rejected(reason)) => {
+            exit_with_error(&format!("transaction rejected: {}", reason))
+        }
+        _ => exit_with_error("unexpected response from the node"),
+    }
+}
+
+fn transfer_batch(
+    connection: Connection,
+    signer: SigningKey,
+    file: PathBuf,
+    fee: u64,
+    denomination: Denomination,
+) {
+    let csv = std::fs::read_to_string(&file)
+        .unwrap_or_else(|e| exit_with_error(&format!("failed to read {}: {}", file.display(), e)));
+    println!("Address: {}", B256::address_of(signer.verifying_key()));
+
+    let transport = bind(&connection);
+    let mut failed = 0;
+    for (line, transaction) in batch_transactions(&csv, &signer, fee, rand::random(), denomination)
+    {
+        let transaction = match transaction {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                println!("Line {}: invalid: {}", line, e);
+                failed += 1;
+                continue;
+            }
+        };
+        let hash = transaction.hash;
+        let response = request(&transport, &connection, |reply_to| {
+            Message::SubmitTransaction(reply_to, transaction)
+        });
+        match response {
+            Some(Response::TransactionAck(_, AckStatus::Accepted)) => {
+                println!("Line {}: accepted {}", line, hash);
+                continue;
+            }
+            Some(Response::TransactionAck(_, AckStatus::Rejected(reason))) => {
+                println!("Line {}: rejected: {}", line, reason)
+            }
+            Some(_) => println!("Line {}: unexpected response from the node", line),
+            None => println!("Line {}: no transaction ack from the node", line),
+        }
+        failed += 1;
+    }
+
+    if failed > 0 {
+        exit_with_error(&format!("{} transfers failed", failed));
+    }
+}
+
+/// Signs a transaction for each `to,amount[,memo]` line of the CSV, along
+/// with the line number. As nonces only distinguish equal transfers, they
+/// count up from `first_nonce`. Amounts are in the denomination.
+fn batch_transactions(
+    csv: &str,
+    signer: &SigningKey,
+    fee: u64,
+    first_nonce: u64,
+    denomination: Denomination,
+) -> Vec<(usize, Result<Transaction, String>)> {
+    let lines = csv
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+    lines
+        .zip(first_nonce..)
+        .map(|((number, line), nonce)| {
+            let transaction = parse_batch_line(line, denomination)
+                .map(|builder| builder.fee(fee).nonce(nonce).sign(signer));
+            (number, transaction)
+        })
+        .collect()
+}
+
+/// Parses a `to,amount[,memo]` line into a transaction builder.
+fn parse_batch_line(line: &str, denomination: Denomination) -> Result<TransactionBuilder, String> {
+    let mut fields = line.splitn(3, ',');
+    let to = fields.next().unwrap_or_default().trim();
+    let to = B256::from_hex_string(to).map_err(|e| format!("invalid recipient: {}", e))?;
+    let amount = denomination
+        .parse(fields.next().ok_or("missing amount")?)
+        .map_err(|e| format!("invalid amount: {}", e))?;
+    let memo = fields.next().unwrap_or_default();
+    if memo.len() > MAX_MEMO_LEN {
+        return Err(format!("memo is longer than {} bytes", MAX_MEMO_LEN));
+    }
+    Ok(TransactionBuilder::new().to(to).amount(amount).memo(memo))
+}
+
+fn export(connection: Connection, out: PathBuf) {
+    let transport = bind(&connection);
+    let response = request(&transport, &connection, Message::GetChain)
+        .unwrap_or_else(|| exit_with_error("no chain response from the node"));
+    let Response::Chain(json) = response else {
+        exit_with_error("unexpected response from the node");
+    };
+
+    std::fs::write(&out, json).expect("chain file should be written");
+    println!("Exported chain to {}", out.display());
+}
+
+/// Checks that the signature of the hash is made by the address.
+fn verify_signature(hash: &str, signature: &str, address: &str) -> Result<(), String> {
+    let hash = B256::from_hex_string(hash).map_err(|e| format!("invalid hash: {}", e))?;
+    let signature =
+        Signature::from_hex(signature).map_err(|e| format!("invalid signature: {}", e))?;
+    let address = B256::from_hex_string(address).map_err(|e| format!("invalid address: {}", e))?;
+
+    match signature.recover(hash) {
+        Some(signer) if signer == address => Ok(()),
+        Some(signer) => Err(format!("signature is made by {}", signer)),
+        None => Err("signature is malformed".to_string()),
+    }
+}
+
+fn snapshot(connection: Connection, height: u64, out: PathBuf) {
+    let transport = bind(&connection);
+    let response = request(&transport, &connection, |reply_to| {
+        Message::GetSnapshot(reply_to, height)
+    })
+    .unwrap_or_else(|| exit_with_error("no snapshot response from the node"));
+    let Response::Snapshot(found) = response else {
+        exit_with_error("unexpected response from the node");
+    };
+    let Some((snapshot, signature)) = found else {
+        exit_with_error("the node has no block at the height");
+    };
+    let Some(signer) = signature.recover(snapshot.hash()) else {
+        exit_with_error("invalid snapshot signature");
+    };
+
+    let json =
+        serde_json::to_string(&(snapshot, signature)).expect("snapshot should be serialized");
+    std::fs::write(&out, json).expect("snapshot file should be written");
+    println!(
+        "Exported snapshot signed by {} to {}",
+        signer,
+        out.display()
+    );
+}
+
+fn block(connection: Connection, number: u64) {
+    let transport = bind(&connection);
+    let response = request(&transport, &connection, |reply_to| {
+        Message::GetBlockView(reply_to, number)
+    })
+    .unwrap_or_else(|| exit_with_error("no block response from the node"));
+    let Response::BlockView(view) = response else {
+        exit_with_error("unexpected response from the node");
+    };
+    let Some(view) = view else {
+        exit_with_error("block not found");
+    };
+
+    let json = serde_json::to_string_pretty(&view).expect("block view should be serialized");
+    println!("{}", json);
+}
+
+fn health(connection: Connection) {
+    let transport = bind(&connection);
+    let response = request(&transport, &connection, Message::HealthCheck)
+        .unwrap_or_else(|| exit_with_error("no health response from the node"));
+    let Response::Health(status) = response else {
+        exit_with_error("unexpected response from the node");
+    };
+
+    println!("Height: {}", status.height);
+    println!("Peers: {}", status.peers);
+    println!("Syncing: {}", status.syncing);
+}
+
+fn tip(connection: Connection) {
+    let transport = bind(&connection);
+    let response = request(&transport, &connection, Message::GetTip)
+        .unwrap_or_else(|| exit_with_error("no tip response from the node"));
+    let Response::Tip(tip) = response else {
+        exit_with_error("unexpected response from the node");
+    };
+    let Some((hash, height)) = tip else {
+        exit_with_error("the chain is empty");
+    };
+
+    println!("Tip: {} at height {}", hash, height);
+}
+
+fn estimate(connection: Connection, fee: u64, denomination: Denomination) {
+    let transport = bind(&connection);
+    let response = request(&transport, &connection, |reply_to| {
+        Message::EstimateInclusion(reply_to, fee)
+    })
+    .unwrap_or_else(|| exit_with_error("no estimate response from the node"));
+    let Response::Inclusion(included) = response else {
+        exit_with_error("unexpected response from the node");
+    };
+
+    let fee = denomination.format(fee);
+    if included {
+        println!(
+            "A transfer with fee {} would be included in the next block",
+            fee
+        );
+    } else {
+        println!("A transfer with fee {} would wait for a later block", fee);
+    }
+}
+
+fn transaction(connection: Connection, hash: String, denomination: Denomination) {
+    let hash = B256::from_hex_string(&hash)
+        .unwrap_or_else(|e| exit_with_error(&format!("invalid transaction hash: {}", e)));
+
+    let transport = bind(&connection);
+    let response = request(&transport, &connection, |reply_to| {
+        Message::GetTransaction(reply_to, hash)
+    })
+    .unwrap_or_else(|| exit_with_error("no transaction response from the node"));
+    let Response::Transaction(found) = response else {
+        exit_with_error("unexpected response from the node");
+    };
+    let Some((tx, number)) = found else {
+        exit_with_error("transaction not found");
+    };
+
+    println!("Transaction: {}", tx.hash);
+    println!("Block: {}", number);
+    println!("From: {}", tx.from);
+    println!("To: {}", tx.data.to);
+    println!("Amount: {}", denomination.format(tx.data.amount));
+    println!("Fee: {}", denomination.format(tx.data.fee));
+    println!("Nonce: {}", tx.data.nonce);
+    if let Some(text) = tx.data.memo_text() {
+        println!("Memo: {}", text);
+    } else {
+        println!("Memo: 0x{}", hex::encode(&tx.data.memo));
+    }
+}
+
+fn accounts(connection: Connection, denomination: Denomination) {
+    let transport = bind(&connection);
+
+    // The node returns the accounts in pages.
+    let mut accounts = Vec::new();
+    loop {
+        let start = accounts.len() as u64;
+        let response = request(&transport, &connection, |reply_to| {
+            Message::GetAccounts(reply_to, start)
+        })
+        .unwrap_or_else(|| exit_with_error("no accounts response from the node"));
+        let Response::Accounts(total, page) = response else {
+            exit_with_error("unexpected response from the node");
+        };
+        if page.is_empty() {
+            break;
+        }
+        accounts.extend(page);
+        if accounts.len() as u64 >= total {
+            break;
+        }
+    }
+
+    println!("{:<64} balance", "address");
+    for (address, balance) in accounts {
+        println!(
+            "{:<64} {}",
+            address.to_string(),
+            denomination.format(balance)
+        );
+    }
+}
+
+fn peers(connection: Connection) {
+    let transport = bind(&connection);
+
+    // The node returns the table in pages.
+    let mut peers = Vec::new();
+    loop {
+        let start = peers.len() as u64;
+        let response = request(&transport, &connection, |reply_to| {
+            Message::GetPeerTable(reply_to, start)
+        })
+        .unwrap_or_else(|| exit_with_error("no peer table response from the node"));
+        let Response::PeerTable(total, page) = response else {
+            exit_with_error("unexpected response from the node");
+        };
+        if page.is_empty() {
+            break;
+        }
+        peers.extend(page);
+        if peers.len() as u64 >= total {
+            break;
+        }
+    }
+
+    println!("{:<24} {:<64} {:<24} observer", "name", "address", "socket");
+    for peer in peers {
+        println!(
+            "{:<24} {:<64} {:<24} {}",
+            peer.name,
+            peer.address,
+            peer.socket.to_string(),
+            peer.observer
+        );
+    }
+}
+
+fn watch(connection: Connection, address: String, denomination: Denomination) {
+    let address = B256::from_hex_string(&address)
+        .unwrap_or_else(|e| exit_with_error(&format!("invalid address: {}", e)));
+
+    let transport = bind(&connection);
+    transport
+        .set_read_timeout(Some(RESUBSCRIBE_INTERVAL))
+        .expect("read timeout should be set");
+
+    let mut subscribed_at: Option<Instant> = None;
+    loop {
+        // Renew the subscription before the node forgets about us.
+        if subscribed_at.is_none_or(|at| at.elapsed() >= RESUBSCRIBE_INTERVAL) {
+            transport
+                .send(connection.node(), &Message::Subscribe(connection.socket()))
+                .expect("subscribe request should be sent");
+            subscribed_at = Some(Instant::now());
+        }
+
+        match transport.receive::<Message>() {
+            Ok(Message::Block(block)) => print_block_transactions(&block, address, denomination),
+            Ok(_) => {}
+            Err(e) if e.is_timeout() => {}
+            Err(e) => eprintln!("warning: {}", e),
+        }
+    }
+}
+
+fn print_block_transactions(block: &Block, address: B256, denomination: Denomination) {
+    let involved = block
+        .data
+        .transactions
+        .iter()
+        .filter(|tx| tx.from == address || tx.data.to == address);
+    for tx in involved {
+        let memo = match tx.data.memo_text() {
+            Some("") => String::new(),
+            Some(text) => format!(" memo {:?}", text),
+            None => format!(" memo 0x{}", hex::encode(&tx.data.memo)),
+        };
+        println!(
+            "Block {}: tx {} {} -> {} amount {}{}",
+            block.data.number,
+            tx.hash,
+            tx.from,
+            tx.data.to,
+            denomination.format(tx.data.amount),
+            memo
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::thread;
+
+    use ledger_transport::Transport;
+    use ledger_types::{Message, Reply, Response, TransactionBuilder, B256};
+
+    use crate::amount::Denomination;
+    use crate::{batch_transactions, load_signer, request, verify_signature, Connection};
+
+    #[test]
+    fn verify_valid_signature() {
+        let signer = load_signer(&"11".repeat(32)).unwrap();
+        let hash = B256::hash_of("data");
+        let tx = TransactionBuilder::new().amount(5).sign(&signer);
+        let address = tx.from.to_string();
+
+        let signature = tx.signature.to_hex();
+        assert_eq!(
+            verify_signature(&tx.hash.to_string(), &signature, &address),
+            Ok(())
+        );
+        assert!(verify_signature(&hash.to_string(), &signature, &address).is_err());
+    }
+
+    #[test]
+    fn verify_wrong_address() {
+        let signer = load_signer(&"11".repeat(32)).unwrap();
+        let tx = TransactionBuilder::new().amount(5).sign(&signer);
+        let other = B256::hash_of("other").to_string();
+
+        let result = verify_signature(&tx.hash.to_string(), &tx.signature.to_hex(), &other);
+        assert_eq!(result, Err(format!("signature is made by {}", tx.from)));
+    }
+
+    #[test]
+    fn batch_from_csv() {
+        let signer = load_signer(&"11".repeat(32)).unwrap();
+        let (first, second) = (B256::hash_of("first"), B256::hash_of("second"));
+        let csv = format!(
+            "# to,amount,memo\n{},10\n\n{}, 20 ,rent, march\nnot an address,5\n{}\n",
+            first, second, first
+        );
+
+        let txs = batch_transactions(&csv, &signer, 1, 7, Denomination::default());
+        let lines: Vec<usize> = txs.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [2, 4, 5, 6]);
+
+        let expected = [
+            TransactionBuilder::new()
+                .to(first)
+                .amount(10)
+                .fee(1)
+                .nonce(7),
+            TransactionBuilder::new()
+                .to(second)
+                .amount(20)
+                .fee(1)
+                .nonce(8)
+                .memo("rent, march"),
+        ]
+        .map(|builder| builder.sign(&signer).hash);
+        let signed: Vec<_> = txs[..2]
+            .iter()
+            .map(|(_, tx)| {
+                let tx = tx.as_ref().unwrap();
+                tx.verify().unwrap();
+                tx.hash
+            })
+            .collect();
+        assert_eq!(signed, expected);
+
+        assert!(txs[2]
+            .1
+            .as_ref()
+            .unwrap_err()
+            .starts_with("invalid recipient"));
+        assert_eq!(txs[3].1.as_ref().unwrap_err(), "missing amount");
+    }
+
+    #[test]
+    fn load_valid_signer() {
+        let key = "2f0177270629ec8840dc5b9fc424f611a84259f72e4769f26f88fb69bf855e64";
+        let signer = load_signer(key).unwrap();
+        assert_eq!(hex::encode(signer.to_bytes()), key);
+    }
+
+    #[test]
+    fn load_invalid_signer() {
+        let expected = Err("invalid key: expected 64 hex characters".to_string());
+        assert_eq!(load_signer("not a hex string").map(|_| ()), expected);
+        assert_eq!(load_signer("2f01").map(|_| ()), expected);
+        assert_eq!(load_signer(&"2f".repeat(33)).map(|_| ()), expected);
+        assert!(load_signer(&"00".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn request_retried_after_dropped_response() {
+        let node = Transport::new("127.0.0.1:0").unwrap();
+        let connection = Connection {
+            socket: "127.0.0.1:0".to_string(),
+            node: node.local_addr().unwrap().to_string(),
+            prefer_ipv6: false,
+            timeout: 200,
+            retries: 2,
+            network_secret: None,
+        };
+
+        let node_thread = thread::spawn(move || {
+            // Drop the first request.
+            node.receive::<Message>().unwrap();
+
+            let Ok((Message::BalanceOf(reply_to, _), source)) = node.receive_from() else {
+                panic!("unexpected request");
+            };
+            let reply = Reply {
+                request_id: reply_to.request_id,
+                response: Response::Balance(42, 42),
+            };
+            node.send(source, &reply).unwrap();
+        });
+
+        let transport = Transport::new("127.0.0.1:0").unwrap();
+        let response = request(&transport, &connection, |reply_to| {
+            Message::BalanceOf(reply_to, B256::ZERO)
+        });
+        node_thread.join().unwrap();
+
+        assert!(matches!(response, Some(Response::Balance(42, 42))));
+    }
+
+    #[test]
+    fn request_gives_up_without_response() {
+        let node = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let connection = Connection {
+            socket: "127.0.0.1:0".to_string(),
+            node: node.local_addr().unwrap().to_string(),
+            prefer_ipv6: false,
+            timeout: 50,
+            retries: 1,
+            network_secret: None,
+        };
+
+        let transport = Transport::new("127.0.0.1:0").unwrap();
+        let response = request(&transport, &connection, |reply_to| {
+            Message::BalanceOf(reply_to, B256::ZERO)
+        });
+
+        assert!(response.is_none());
     }
 }
@@ -3,10 +3,112 @@
 # Run node
 cargo run -p node -- --socket "127.0.0.1:50000"
 
+# Create account
+cargo run -p client -- account new --out wallet.json
+
 # Transfer
-cargo run -p client -- --socket "127.0.0.1:60000" --key "2f0177270629ec8840dc5b9fc424f611a84259f72e4769f26f88fb69bf855e64" --node "127.0.0.1:50000" --amount 100 --transfer-to "5e5c107330e6ab97ff2abc15b5c8ba14dd92709823e669b1ed4884e69a76fd60"
+cargo run -p client -- transfer --socket "127.0.0.1:60000" --key "2f0177270629ec8840dc5b9fc424f611a84259f72e4769f26f88fb69bf855e64" --node "127.0.0.1:50000" --amount 100 --to "5e5c107330e6ab97ff2abc15b5c8ba14dd92709823e669b1ed4884e69a76fd60"
 
 # Balance
-cargo run -p client -- --socket "127.0.0.1:60000" --key "2f0177270629ec8840dc5b9fc424f611a84259f72e4769f26f88fb69bf855e64" --node "127.0.0.1:50000" --balance
+cargo run -p client -- balance --socket "127.0.0.1:60000" --key "2f0177270629ec8840dc5b9fc424f611a84259f72e4769f26f88fb69bf855e64" --node "127.0.0.1:50000"
+
+# Balance with a keystore
+cargo run -p client -- balance --socket "127.0.0.1:60000" --keystore wallet.json --node "127.0.0.1:50000"
+
+# Perform the transfers listed in a CSV file of to,amount[,memo] lines
+cargo run -p client -- transfer-batch --socket "127.0.0.1:60000" --node "127.0.0.1:50000" --key "2f0177270629ec8840dc5b9fc424f611a84259f72e4769f26f88fb69bf855e64" --file transfers.csv
+
+# Export the chain
+cargo run -p client -- export --socket "127.0.0.1:60000" --node "127.0.0.1:50000" --out chain.json
+
+# Run node from an exported chain
+cargo run -p node -- --socket "127.0.0.1:50001" --import chain.json
+
+# Run node on the tokio runtime
+cargo run -p node --features tokio -- --socket "127.0.0.1:50002" --async-runtime
+
+# Peers known to the node
+cargo run -p client -- peers --socket "127.0.0.1:60000" --node "127.0.0.1:50000"
+
+# Find a mined transaction
+cargo run -p client -- tx --socket "127.0.0.1:60000" --node "127.0.0.1:50000" "5e5c107330e6ab97ff2abc15b5c8ba14dd92709823e669b1ed4884e69a76fd60"
+
+# Show a block and its proposer
+cargo run -p client -- block --socket "127.0.0.1:60000" --node "127.0.0.1:50000" 1
+
+# Check that the node is alive
+cargo run -p client -- health --socket "127.0.0.1:60000" --node "127.0.0.1:50000"
+
+# Show the tip of the chain
+cargo run -p client -- tip --socket "127.0.0.1:60000" --node "127.0.0.1:50000"
+
+# List the accounts with a nonzero balance
+cargo run -p client -- accounts --socket "127.0.0.1:60000" --node "127.0.0.1:50000"
+
+# Check if a fee is enough for the next block
+cargo run -p client -- estimate --socket "127.0.0.1:60000" --node "127.0.0.1:50000" --fee 5
+
+# Fetch a signed state snapshot
+cargo run -p client -- snapshot --socket "127.0.0.1:60000" --node "127.0.0.1:50000" --height 10 --out snapshot.json
+
+# Run node from a snapshot signed by a trusted node
+cargo run -p node -- --socket "127.0.0.1:50003" --other-node "127.0.0.1:50000" --snapshot snapshot.json --snapshot-signer "5e5c107330e6ab97ff2abc15b5c8ba14dd92709823e669b1ed4884e69a76fd60"
+
+# Run node with a persisted key, keeping its address and name across restarts
+cargo run -p node -- --socket "127.0.0.1:50004" --key "2f0177270629ec8840dc5b9fc424f611a84259f72e4769f26f88fb69bf855e64"
+
+# Run node with a key derived from a seed
+cargo run -p node -- --socket "127.0.0.1:50008" --seed 1
+
+# Run node serving Prometheus metrics
+cargo run -p node -- --socket "127.0.0.1:50005" --metrics-addr "127.0.0.1:9100"
+curl "http://127.0.0.1:9100/metrics"
+
+# Run node logging rejected transactions
+cargo run -p node -- --socket "127.0.0.1:50006" --reject-log rejected.jsonl
+
+# Verify a signature offline
+cargo run -p client -- verify --hash "<hash>" --signature "<130 hex characters>" --address "5e5c107330e6ab97ff2abc15b5c8ba14dd92709823e669b1ed4884e69a76fd60"
+
+# Build node and client hashing with Keccak-256, both must use the same hash
+cargo run -p node --features keccak -- --socket "127.0.0.1:50007"
+
+# Run node including pending transactions in the order of arrival
+cargo run -p node -- --socket "127.0.0.1:50009" --tx-order fifo
+
+# Run node from a genesis config declaring the allocations and the validators,
+# e.g. {"allocations": {"<address>": 500}, "validators": ["<address>"]}
+cargo run -p node -- --socket "127.0.0.1:50010" --genesis genesis.json
+
+# Run node compressing large messages like sync responses
+cargo run -p node -- --socket "127.0.0.1:50011" --compress
+
+# Run node reverting an imported chain to block 10
+cargo run -p node -- --socket "127.0.0.1:50012" --import chain.json --rollback-to 10
+
+# Run node sending at most 50 broadcast messages per second to each peer
+cargo run -p node -- --socket "127.0.0.1:50013" --send-rate 50
+
+# Run node recording the received messages, then replay them offline
+cargo run -p node -- --socket "127.0.0.1:50014" --seed 1 --record messages.jsonl
+cargo run -p node -- --socket "127.0.0.1:50014" --seed 1 --replay messages.jsonl
+
+# Run node warning about every sent message exceeding the MTU
+cargo run -p node -- --socket "127.0.0.1:50015" --warn-over-mtu
+
+# Run node bridging browser clients over WebSocket
+cargo run -p node --features websocket -- --socket "127.0.0.1:50016" --websocket-addr "127.0.0.1:50017"
+
+# Transfer 1.5 coins of 8 decimals and print the balance with the decimals
+cargo run -p client -- transfer --decimals 8 --socket "127.0.0.1:60000" --key "2f0177270629ec8840dc5b9fc424f611a84259f72e4769f26f88fb69bf855e64" --node "127.0.0.1:50000" --amount 1.5 --to "5e5c107330e6ab97ff2abc15b5c8ba14dd92709823e669b1ed4884e69a76fd60"
+cargo run -p client -- balance --decimals 8 --socket "127.0.0.1:60000" --key "2f0177270629ec8840dc5b9fc424f611a84259f72e4769f26f88fb69bf855e64" --node "127.0.0.1:50000"
+
+# Run nodes of a private network rejecting messages not tagged by its secret
+cargo run -p node -- --socket "127.0.0.1:50018" --network-secret "correct horse battery staple"
+cargo run -p client -- tip --socket "127.0.0.1:60000" --node "127.0.0.1:50018" --network-secret "correct horse battery staple"
+
+# Run node keeping its pending transactions across restarts
+cargo run -p node -- --socket "127.0.0.1:50019" --seed 1 --mempool mempool.json
 
-# https://otus.pw/NwJ9/
\ No newline at end of file
+# Verify an exported chain offline
+cargo run -p node -- --verify-chain chain.json
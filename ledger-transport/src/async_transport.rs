@@ -0,0 +1,174 @@
+use std::cell::{Cell, RefCell};
+use std::net::SocketAddr;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use socket2::SockRef;
+use tokio::net::{ToSocketAddrs, UdpSocket};
+
+use crate::chunk::Reassembly;
+use crate::{accept_payload, encode, set_buffer_sizes, TransportConfig, TransportError};
+
+/// Async counterpart of `Transport` running on tokio. Both use the same wire
+/// format, so they can talk to each other.
+pub struct AsyncTransport {
+    socket: UdpSocket,
+    config: TransportConfig,
+    next_message_id: Cell<u64>,
+    reassembly: RefCell<Reassembly>,
+}
+
+impl AsyncTransport {
+    pub async fn new(addr: impl ToSocketAddrs) -> Result<Self, TransportError> {
+        Self::with_config(addr, TransportConfig::default()).await
+    }
+
+    pub async fn with_config(
+        addr: impl ToSocketAddrs,
+        config: TransportConfig,
+    ) -> Result<Self, TransportError> {
+        let socket = UdpSocket::bind(addr).await.map_err(TransportError::Bind)?;
+        set_buffer_sizes(SockRef::from(&socket), &config).map_err(TransportError::Bind)?;
+        Ok(Self::from_socket(socket, config))
+    }
+
+    /// Wraps a bound std socket. Must be called within a tokio runtime.
+    pub fn from_std(
+        socket: std::net::UdpSocket,
+        config: TransportConfig,
+    ) -> Result<Self, TransportError> {
+        socket.set_nonblocking(true).map_err(TransportError::Io)?;
+        let socket = UdpSocket::from_std(socket).map_err(TransportError::Io)?;
+        Ok(Self::from_socket(socket, config))
+    }
+
+    fn from_socket(socket: UdpSocket, config: TransportConfig) -> Self {
+        Self {
+            socket,
+            config,
+            next_message_id: Cell::new(rand::random()),
+            reassembly: RefCell::new(Reassembly::new(config.mtu)),
+        }
+    }
+
+    /// Socket address the transport is bound to.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.socket.local_addr().ok()
+    }
+
+    /// Sends a message to the given address, splitting it into chunks if it
+    /// doesn't fit into the MTU.
+    pub async fn send(
+        &self,
+        to: SocketAddr,
+        msg: &impl Serialize,
+    ) -> Result<usize, TransportError> {
+        let mut buf = Vec::new();
+        let mut compressed = Vec::new();
+        let Some(chunks) = encode(
+            msg,
+            &self.config,
+            &self.next_message_id,
+            &mut buf,
+            &mut compressed,
+        )?
+        else {
+            return self
+                .socket
+                .send_to(&buf, to)
+                .await
+                .map_err(TransportError::Io);
+        };
+        let mut sent = 0;
+        for datagram in chunks {
+            sent += self
+                .socket
+                .send_to(&datagram, to)
+                .await
+                .map_err(TransportError::Io)?;
+        }
+        Ok(sent)
+    }
+
+    /// Receives a message.
+    pub async fn receive<T: DeserializeOwned>(&self) -> Result<T, TransportError> {
+        self.receive_from().await.map(|(msg, _)| msg)
+    }
+
+    /// Receives a message along with the socket address it was sent from.
+    /// A malformed message fails with `TransportError::Deserialize`, after
+    /// which the next message can be received.
+    ///
+    /// Cancel safe: chunks received before cancellation are kept for the
+    /// next call.
+    pub async fn receive_from<T: DeserializeOwned>(
+        &self,
+    ) -> Result<(T, SocketAddr), TransportError> {
+        let mut buf = vec![0; self.config.mtu];
+        loop {
+            let (len, source) = self
+                .socket
+                .recv_from(&mut buf)
+                .await
+                .map_err(TransportError::Io)?;
+            let Some(payload) = self.reassembly.borrow_mut().accept(source, &buf[..len]) else {
+                continue;
+            };
+            return accept_payload(&payload, source, &self.config);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AsyncTransport, DecodeError, Transport, TransportConfig, TransportError};
+
+    #[tokio::test]
+    async fn talks_to_sync_transport() {
+        let config = TransportConfig {
+            mtu: 100,
+            ..Default::default()
+        };
+        let sync = Transport::with_config("127.0.0.1:0", config).unwrap();
+        let transport = AsyncTransport::with_config("127.0.0.1:0", config)
+            .await
+            .unwrap();
+
+        // Large enough to be chunked.
+        let payload = "a".repeat(1000);
+        sync.send(transport.local_addr().unwrap(), &payload)
+            .unwrap();
+        let (received, source): (String, _) = transport.receive_from().await.unwrap();
+        assert_eq!(received, payload);
+        assert_eq!(source, sync.local_addr().unwrap());
+
+        transport.send(source, &payload).await.unwrap();
+        let received: String = sync.receive().unwrap();
+        assert_eq!(received, payload);
+    }
+
+    #[tokio::test]
+    async fn malformed_message_reported() {
+        let sync = Transport::new("127.0.0.1:0").unwrap();
+        let transport = AsyncTransport::with_config("127.0.0.1:0", TransportConfig::default())
+            .await
+            .unwrap();
+        let to = transport.local_addr().unwrap();
+
+        sync.send(to, &"first").unwrap();
+        std::net::UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .send_to(b"garbage", to)
+            .unwrap();
+        sync.send(to, &"second").unwrap();
+
+        let first: String = transport.receive().await.unwrap();
+        let malformed = transport.receive::<String>().await;
+        assert!(matches!(
+            malformed,
+            Err(TransportError::Deserialize(_, DecodeError::Invalid))
+        ));
+        let second: String = transport.receive().await.unwrap();
+        assert_eq!((first.as_str(), second.as_str()), ("first", "second"));
+    }
+}
@@ -0,0 +1,34 @@
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Length of the tag appended to authenticated payloads.
+pub const TAG_LEN: usize = 32;
+
+/// Derives the key authenticating messages from a secret shared by the
+/// nodes of a private network.
+pub fn network_key(secret: &str) -> [u8; 32] {
+    Sha256::digest(secret.as_bytes()).into()
+}
+
+fn mac(key: &[u8; 32], data: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC should take any key length");
+    mac.update(data);
+    mac
+}
+
+/// Appends the tag of the payload in the buffer.
+pub fn append_tag(buf: &mut Vec<u8>, key: &[u8; 32]) {
+    let tag = mac(key, buf).finalize().into_bytes();
+    buf.extend_from_slice(&tag);
+}
+
+/// Checks the tag at the end of the payload in constant time and strips it.
+/// `None` if the tag is absent or wrong.
+pub fn strip_tag<'a>(payload: &'a [u8], key: &[u8; 32]) -> Option<&'a [u8]> {
+    let split = payload.len().checked_sub(TAG_LEN)?;
+    let (data, tag) = payload.split_at(split);
+    mac(key, data).verify_slice(tag).ok()?;
+    Some(data)
+}
@@ -0,0 +1,188 @@
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+/// First byte of a chunk datagram. It can't start a JSON document.
+pub const CHUNK_MAGIC: u8 = 0xff;
+
+/// Size of the chunk header: magic, message id, chunk index and chunk count.
+pub const HEADER_LEN: usize = 1 + 8 + 2 + 2;
+
+/// Maximal number of partially received messages kept at once.
+const MAX_PARTIAL: usize = 64;
+
+/// Maximal size of the chunks of a message, so a peer can't make the
+/// receiver buffer more than this per message.
+pub const MAX_MESSAGE_LEN: usize = 16 * 1024 * 1024;
+
+/// Checks if `count` chunks of up to `chunk_len` bytes fit into `MAX_MESSAGE_LEN`.
+fn fits(count: usize, chunk_len: usize) -> bool {
+    count
+        .checked_mul(chunk_len)
+        .is_some_and(|len| len <= MAX_MESSAGE_LEN)
+}
+
+/// Splits the payload into datagrams of at most `mtu` bytes.
+pub fn split(payload: &[u8], message_id: u64, mtu: usize) -> Option<Vec<Vec<u8>>> {
+    let chunk_len = mtu.checked_sub(HEADER_LEN).filter(|len| *len > 0)?;
+    let chunks: Vec<&[u8]> = payload.chunks(chunk_len).collect();
+    let count = u16::try_from(chunks.len()).ok()?;
+    if !fits(chunks.len(), chunk_len) {
+        return None;
+    }
+
+    let datagrams = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut datagram = Vec::with_capacity(HEADER_LEN + chunk.len());
+            datagram.push(CHUNK_MAGIC);
+            datagram.extend_from_slice(&message_id.to_be_bytes());
+            datagram.extend_from_slice(&(index as u16).to_be_bytes());
+            datagram.extend_from_slice(&count.to_be_bytes());
+            datagram.extend_from_slice(chunk);
+            datagram
+        })
+        .collect();
+    Some(datagrams)
+}
+
+/// Chunks of a message that is not fully received yet.
+struct Partial {
+    chunks: Vec<Option<Vec<u8>>>,
+    received: usize,
+    /// Order of the first received chunk, for evicting the oldest message.
+    started: u64,
+}
+
+/// Reassembles chunked messages.
+pub struct Reassembly {
+    partial: HashMap<(SocketAddr, u64), Partial>,
+    /// Maximal size of a received datagram.
+    mtu: usize,
+    next_started: u64,
+}
+
+impl Reassembly {
+    pub fn new(mtu: usize) -> Self {
+        Self {
+            partial: HashMap::new(),
+            mtu,
+            next_started: 0,
+        }
+    }
+
+    /// Accepts a received datagram. Returns the message payload if the
+    /// datagram isn't a chunk or completes a chunked message.
+    pub fn accept<'a>(&mut self, source: SocketAddr, datagram: &'a [u8]) -> Option<Cow<'a, [u8]>> {
+        if datagram.first() != Some(&CHUNK_MAGIC) {
+            return Some(Cow::Borrowed(datagram));
+        }
+        self.add(source, datagram).map(Cow::Owned)
+    }
+
+    /// Adds a chunk datagram. Returns the whole payload once all its chunks arrived.
+    pub fn add(&mut self, source: SocketAddr, datagram: &[u8]) -> Option<Vec<u8>> {
+        if datagram.len() < HEADER_LEN || datagram[0] != CHUNK_MAGIC {
+            return None;
+        }
+        let message_id = u64::from_be_bytes(datagram[1..9].try_into().unwrap());
+        let index = u16::from_be_bytes(datagram[9..11].try_into().unwrap()) as usize;
+        let count = u16::from_be_bytes(datagram[11..13].try_into().unwrap()) as usize;
+        let chunk_len = self.mtu.saturating_sub(HEADER_LEN);
+        if index >= count || datagram.len() > self.mtu || !fits(count, chunk_len) {
+            return None;
+        }
+
+        let key = (source, message_id);
+        if !self.partial.contains_key(&key) && self.partial.len() >= MAX_PARTIAL {
+            self.evict_oldest();
+        }
+        let partial = match self.partial.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                self.next_started += 1;
+                entry.insert(Partial {
+                    chunks: vec![None; count],
+                    received: 0,
+                    started: self.next_started,
+                })
+            }
+        };
+        if partial.chunks.len() != count {
+            return None;
+        }
+        if partial.chunks[index].is_none() {
+            partial.chunks[index] = Some(datagram[HEADER_LEN..].to_vec());
+            partial.received += 1;
+        }
+        if partial.received < count {
+            return None;
+        }
+
+        let partial = self.partial.remove(&key)?;
+        Some(partial.chunks.into_iter().flatten().flatten().collect())
+    }
+
+    /// Drops the message whose first chunk arrived the longest ago.
+    fn evict_oldest(&mut self) {
+        let oldest = self
+            .partial
+            .iter()
+            .min_by_key(|(_, partial)| partial.started)
+            .map(|(key, _)| *key);
+        if let Some(key) = oldest {
+            self.partial.remove(&key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use super::{split, Reassembly, HEADER_LEN, MAX_MESSAGE_LEN, MAX_PARTIAL};
+
+    const MTU: usize = 100;
+
+    fn source() -> SocketAddr {
+        "127.0.0.1:9000".parse().unwrap()
+    }
+
+    #[test]
+    fn eviction_keeps_newer_messages() {
+        let mut reassembly = Reassembly::new(MTU);
+        let messages: Vec<Vec<Vec<u8>>> = (0..=MAX_PARTIAL as u64)
+            .map(|id| split(&[id as u8; 2 * MTU], id, MTU).unwrap())
+            .collect();
+        for datagrams in &messages {
+            assert_eq!(reassembly.add(source(), &datagrams[0]), None);
+        }
+
+        // Only the oldest message is evicted to make room for the last one.
+        for (id, datagrams) in messages.iter().enumerate().skip(1) {
+            let payload = datagrams[1..]
+                .iter()
+                .find_map(|datagram| reassembly.add(source(), datagram));
+            assert_eq!(payload, Some(vec![id as u8; 2 * MTU]));
+        }
+        for datagram in &messages[0][1..] {
+            assert_eq!(reassembly.add(source(), datagram), None);
+        }
+    }
+
+    #[test]
+    fn oversized_message_rejected() {
+        let mtu = 1024;
+        let count = MAX_MESSAGE_LEN / (mtu - HEADER_LEN) + 1;
+        let mut datagram = split(&[0; 2 * 1024], 1, mtu).unwrap().remove(0);
+        datagram[11..13].copy_from_slice(&u16::try_from(count).unwrap().to_be_bytes());
+        let mut reassembly = Reassembly::new(mtu);
+        assert_eq!(reassembly.add(source(), &datagram), None);
+        assert!(reassembly.partial.is_empty());
+
+        let payload = vec![0; MAX_MESSAGE_LEN + 1];
+        assert!(split(&payload, 1, 1 << 16).is_none());
+    }
+}
@@ -0,0 +1,68 @@
+use std::borrow::Cow;
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+/// First byte of a compressed payload. It can't start a JSON document, and
+/// differs from `CHUNK_MAGIC`, so compressed and plain payloads coexist.
+pub const COMPRESSED_MAGIC: u8 = 0xfe;
+
+/// Payloads shorter than this aren't worth compressing.
+pub const MIN_LEN: usize = 256;
+
+/// Maximal size of a decompressed payload, protecting from deflate bombs.
+const MAX_INFLATED_LEN: usize = 16 * 1024 * 1024;
+
+/// Compresses the payload with deflate behind the magic byte into `out`,
+/// reusing its allocation. `None` if it's too short or doesn't shrink.
+pub fn deflate(payload: &[u8], out: &mut Vec<u8>) -> Option<()> {
+    if payload.len() < MIN_LEN {
+        return None;
+    }
+    out.clear();
+    out.push(COMPRESSED_MAGIC);
+    let mut encoder = DeflateEncoder::new(&mut *out, Compression::default());
+    encoder.write_all(payload).ok()?;
+    encoder.finish().ok()?;
+    (out.len() < payload.len()).then_some(())
+}
+
+/// Decompresses the payload if it starts with the magic byte, otherwise
+/// returns it as is. `None` if it's corrupted or too large.
+pub fn inflate(payload: &[u8]) -> Option<Cow<'_, [u8]>> {
+    let Some((&COMPRESSED_MAGIC, compressed)) = payload.split_first() else {
+        return Some(Cow::Borrowed(payload));
+    };
+    let mut inflated = Vec::new();
+    DeflateDecoder::new(compressed)
+        .take(MAX_INFLATED_LEN as u64 + 1)
+        .read_to_end(&mut inflated)
+        .ok()?;
+    (inflated.len() <= MAX_INFLATED_LEN).then_some(Cow::Owned(inflated))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compress::{deflate, inflate, COMPRESSED_MAGIC, MIN_LEN};
+
+    #[test]
+    fn round_trip() {
+        let payload = br#"{"hash":"00ff00ff"}"#.repeat(100);
+        let mut compressed = Vec::new();
+        deflate(&payload, &mut compressed).unwrap();
+        assert_eq!(compressed[0], COMPRESSED_MAGIC);
+        assert!(compressed.len() < payload.len());
+        assert_eq!(inflate(&compressed).unwrap(), payload);
+
+        // Plain payloads pass through.
+        assert_eq!(inflate(&payload).unwrap(), payload);
+    }
+
+    #[test]
+    fn short_payload_not_compressed() {
+        assert!(deflate(&[b'a'; MIN_LEN - 1], &mut Vec::new()).is_none());
+        assert!(inflate(&[COMPRESSED_MAGIC, 1, 2, 3]).is_none());
+    }
+}
@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+/// Version of the wire protocol. Peers with different major versions can't
+/// understand each other, while minor versions only add compatible changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolVersion {
+    pub major: u16,
+    pub minor: u16,
+}
+
+impl ProtocolVersion {
+    pub fn is_compatible(&self, other: &ProtocolVersion) -> bool {
+        self.major == other.major
+    }
+}
+
+impl std::fmt::Display for ProtocolVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Version of the protocol spoken by this transport.
+pub const PROTOCOL_VERSION: ProtocolVersion = ProtocolVersion { major: 1, minor: 0 };
+
+/// Wire form of every message: the payload tagged with the protocol version.
+#[derive(Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub version: ProtocolVersion,
+    pub payload: T,
+}
+
+/// Version part of an envelope, parsed before the payload is.
+#[derive(Deserialize)]
+pub struct Header {
+    pub version: ProtocolVersion,
+}
@@ -1,35 +1,682 @@
-use std::net::{ToSocketAddrs, UdpSocket};
+#[cfg(feature = "tokio")]
+mod async_transport;
+mod auth;
+mod chunk;
+mod compress;
+mod envelope;
 
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+use chunk::Reassembly;
+use envelope::{Envelope, Header};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
+use socket2::SockRef;
+
+#[cfg(feature = "tokio")]
+pub use async_transport::AsyncTransport;
+pub use auth::network_key;
+pub use envelope::{ProtocolVersion, PROTOCOL_VERSION};
+
+/// Tunable parameters of the transport.
+#[derive(Debug, Clone, Copy)]
+pub struct TransportConfig {
+    /// Maximal size of a datagram. Larger messages are sent in chunks.
+    /// Communicating transports should use the same value.
+    pub mtu: usize,
+    /// Serializes messages as pretty JSON and logs every sent and received
+    /// message. Meant for inspecting traffic during development only.
+    pub debug_wire: bool,
+    /// Size of the OS receive buffer of the socket. The OS default if `None`.
+    pub recv_buffer: Option<usize>,
+    /// Size of the OS send buffer of the socket. The OS default if `None`.
+    pub send_buffer: Option<usize>,
+    /// Compresses large messages with deflate. Compressed messages are always
+    /// accepted, so peers with and without compression can be mixed.
+    pub compress: bool,
+    /// Logs a warning naming the message variant whenever a message exceeds
+    /// the MTU and has to be split into chunks. Meant for catching protocol
+    /// size regressions during development.
+    pub warn_over_mtu: bool,
+    /// Key of a private network, see `network_key`. If set, every message is
+    /// tagged with an HMAC by the key and messages without a valid tag are
+    /// rejected, so only holders of the key can inject messages.
+    pub network_key: Option<[u8; 32]>,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            mtu: 1536,
+            debug_wire: false,
+            recv_buffer: None,
+            send_buffer: None,
+            compress: false,
+            warn_over_mtu: false,
+            network_key: None,
+        }
+    }
+}
+
+/// Error of creating a transport, sending or receiving a message.
+#[derive(Debug)]
+pub enum TransportError {
+    /// The socket can't be bound to the address or configured.
+    Bind(io::Error),
+    /// The message can't be serialized.
+    Serialize(serde_json::Error),
+    /// The message received from the address can't be parsed. The transport
+    /// stays usable, so the next message may be received.
+    Deserialize(SocketAddr, DecodeError),
+    /// The socket failed to send or receive, including read timeouts.
+    Io(io::Error),
+}
+
+impl TransportError {
+    /// Checks if the read timed out without a message.
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::Io(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut))
+    }
+}
+
+impl std::fmt::Display for TransportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bind(e) => write!(f, "failed to bind socket: {}", e),
+            Self::Serialize(e) => write!(f, "failed to serialize message: {}", e),
+            Self::Deserialize(source, DecodeError::Incompatible(version)) => write!(
+                f,
+                "message of protocol version {} from {}, expected {}",
+                version, source, PROTOCOL_VERSION
+            ),
+            Self::Deserialize(source, DecodeError::Invalid) => {
+                write!(f, "malformed message from {}", source)
+            }
+            Self::Deserialize(source, DecodeError::Unauthenticated) => {
+                write!(f, "message without a valid network tag from {}", source)
+            }
+            Self::Io(e) => write!(f, "socket failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+/// Failure of parsing a received message.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The message is sent by a peer speaking an incompatible protocol.
+    Incompatible(ProtocolVersion),
+    /// The message is corrupted, isn't JSON or isn't of the expected type.
+    Invalid,
+    /// The network key is set, but the message isn't tagged by it.
+    Unauthenticated,
+}
 
 /// Transport for sending and receiving messages.
 pub struct Transport {
     socket: UdpSocket,
+    config: TransportConfig,
+    next_message_id: Cell<u64>,
+    reassembly: RefCell<Reassembly>,
+    /// Buffers reused by every message to avoid allocations.
+    send_buffer: RefCell<Vec<u8>>,
+    compress_buffer: RefCell<Vec<u8>>,
+    receive_buffer: RefCell<Vec<u8>>,
 }
 
 impl Transport {
-    pub fn new(addr: impl ToSocketAddrs) -> Option<Self> {
-        let socket = UdpSocket::bind(addr).ok()?;
-        Some(Self { socket })
+    pub fn new(addr: impl ToSocketAddrs) -> Result<Self, TransportError> {
+        Self::with_config(addr, TransportConfig::default())
+    }
+
+    pub fn with_config(
+        addr: impl ToSocketAddrs,
+        config: TransportConfig,
+    ) -> Result<Self, TransportError> {
+        let socket = UdpSocket::bind(addr).map_err(TransportError::Bind)?;
+        set_buffer_sizes(SockRef::from(&socket), &config).map_err(TransportError::Bind)?;
+        Ok(Self {
+            socket,
+            config,
+            next_message_id: Cell::new(rand::random()),
+            reassembly: RefCell::new(Reassembly::new(config.mtu)),
+            send_buffer: RefCell::default(),
+            compress_buffer: RefCell::default(),
+            receive_buffer: RefCell::new(vec![0; config.mtu]),
+        })
     }
 
-    /// Sends a message to the given address.
-    pub fn send(&self, to: impl ToSocketAddrs, msg: &impl Serialize) -> Option<usize> {
-        let string = serde_json::to_string(msg).ok()?;
-        self.socket.send_to(string.as_bytes(), to).ok()
+    /// Socket address the transport is bound to.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.socket.local_addr().ok()
+    }
+
+    /// Sets the timeout of `receive`. `None` blocks until a message arrives.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Option<()> {
+        self.socket.set_read_timeout(timeout).ok()
+    }
+
+    /// Sends a message to the given address, splitting it into chunks if it
+    /// doesn't fit into the MTU.
+    pub fn send(
+        &self,
+        to: impl ToSocketAddrs,
+        msg: &impl Serialize,
+    ) -> Result<usize, TransportError> {
+        let to = to
+            .to_socket_addrs()
+            .map_err(TransportError::Io)?
+            .next()
+            .ok_or_else(|| invalid_input("no address to send to"))?;
+        let mut buf = self.send_buffer.borrow_mut();
+        let mut compressed = self.compress_buffer.borrow_mut();
+        let Some(chunks) = encode(
+            msg,
+            &self.config,
+            &self.next_message_id,
+            &mut buf,
+            &mut compressed,
+        )?
+        else {
+            return self.socket.send_to(&buf, to).map_err(TransportError::Io);
+        };
+        let mut sent = 0;
+        for datagram in chunks {
+            sent += self
+                .socket
+                .send_to(&datagram, to)
+                .map_err(TransportError::Io)?;
+        }
+        Ok(sent)
     }
 
     /// Receives a message.
-    pub fn receive<T: DeserializeOwned>(&self) -> Option<T> {
+    pub fn receive<T: DeserializeOwned>(&self) -> Result<T, TransportError> {
+        self.receive_from().map(|(msg, _)| msg)
+    }
+
+    /// Receives a message along with the socket address it was sent from.
+    /// A malformed message fails with `TransportError::Deserialize`, after
+    /// which the next message can be received.
+    pub fn receive_from<T: DeserializeOwned>(&self) -> Result<(T, SocketAddr), TransportError> {
+        let mut buf = self.receive_buffer.borrow_mut();
+        loop {
+            let (len, source) = self
+                .socket
+                .recv_from(&mut buf)
+                .map_err(TransportError::Io)?;
+            let Some(payload) = self.reassembly.borrow_mut().accept(source, &buf[..len]) else {
+                continue;
+            };
+            return accept_payload(&payload, source, &self.config);
+        }
+    }
+
+    /// Creates an async transport sharing the socket. The shared socket is
+    /// switched to non-blocking mode, so `receive` of this transport shouldn't
+    /// be used afterwards.
+    #[cfg(feature = "tokio")]
+    pub fn to_async(&self) -> Result<AsyncTransport, TransportError> {
+        let socket = self.socket.try_clone().map_err(TransportError::Io)?;
+        AsyncTransport::from_std(socket, self.config)
+    }
+}
+
+/// Sets the socket buffer sizes requested by the config and logs the sizes
+/// granted by the OS, which may differ.
+fn set_buffer_sizes(socket: SockRef<'_>, config: &TransportConfig) -> io::Result<()> {
+    if let Some(size) = config.recv_buffer {
+        socket.set_recv_buffer_size(size)?;
+        println!(
+            "Receive buffer of {} bytes requested, {} granted",
+            size,
+            socket.recv_buffer_size()?
+        );
+    }
+    if let Some(size) = config.send_buffer {
+        socket.set_send_buffer_size(size)?;
+        println!(
+            "Send buffer of {} bytes requested, {} granted",
+            size,
+            socket.send_buffer_size()?
+        );
+    }
+    Ok(())
+}
+
+/// Resolves a `host:port` string, e.g. `example.com:9000`, `127.0.0.1:9000`
+/// or `[::1]:9000`. If the host has several addresses, one of the preferred
+/// IP version is picked when available.
+pub fn resolve(addr: &str, prefer_ipv6: bool) -> io::Result<SocketAddr> {
+    let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+    addrs
+        .iter()
+        .find(|addr| addr.is_ipv6() == prefer_ipv6)
+        .or(addrs.first())
+        .copied()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "host has no addresses"))
+}
+
+/// Serializes the message into the buffer. Returns the chunks to send if it
+/// doesn't fit into the MTU, otherwise the buffer is the only datagram.
+/// Compression goes through the second buffer, which is swapped in.
+fn encode(
+    msg: &impl Serialize,
+    config: &TransportConfig,
+    next_message_id: &Cell<u64>,
+    buf: &mut Vec<u8>,
+    compressed: &mut Vec<u8>,
+) -> Result<Option<Vec<Vec<u8>>>, TransportError> {
+    let envelope = Envelope {
+        version: PROTOCOL_VERSION,
+        payload: msg,
+    };
+    buf.clear();
+    if config.debug_wire {
+        serde_json::to_writer_pretty(&mut *buf, &envelope)
+    } else {
+        serde_json::to_writer(&mut *buf, &envelope)
+    }
+    .map_err(TransportError::Serialize)?;
+    if config.debug_wire {
+        println!("Sending message:\n{}", String::from_utf8_lossy(buf));
+    }
+    if config.compress && compress::deflate(buf, compressed).is_some() {
+        std::mem::swap(buf, compressed);
+    }
+    if let Some(key) = &config.network_key {
+        auth::append_tag(buf, key);
+    }
+    if buf.len() <= config.mtu {
+        return Ok(None);
+    }
+    if config.warn_over_mtu {
+        println!("{}", over_mtu_warning(msg, buf.len(), config.mtu));
+    }
+
+    let message_id = next_message_id.get();
+    next_message_id.set(message_id.wrapping_add(1));
+
+    chunk::split(buf, message_id, config.mtu)
+        .map(Some)
+        .ok_or_else(|| invalid_input("message is too large"))
+}
+
+/// Warning about the message of `len` bytes exceeding the MTU.
+fn over_mtu_warning(msg: &impl Serialize, len: usize, mtu: usize) -> String {
+    // Enum variants serialize as their name or an object keyed by it.
+    let variant = match serde_json::to_value(msg) {
+        Ok(serde_json::Value::Object(object)) if object.len() == 1 => object.keys().next().cloned(),
+        Ok(serde_json::Value::String(name)) => Some(name),
+        _ => None,
+    };
+    format!(
+        "Warning: {} message of {} bytes exceeds the MTU of {} bytes",
+        variant.as_deref().unwrap_or("unnamed"),
+        len,
+        mtu
+    )
+}
+
+/// Parses a reassembled and decompressed payload.
+fn decode<T: DeserializeOwned>(payload: &[u8]) -> Result<T, DecodeError> {
+    let string = std::str::from_utf8(payload).map_err(|_| DecodeError::Invalid)?;
+
+    let header = serde_json::from_str::<Header>(string).map_err(|_| DecodeError::Invalid)?;
+    if !PROTOCOL_VERSION.is_compatible(&header.version) {
+        return Err(DecodeError::Incompatible(header.version));
+    }
+    let envelope = serde_json::from_str::<Envelope<T>>(string).map_err(|_| DecodeError::Invalid)?;
+    Ok(envelope.payload)
+}
+
+/// Decodes a received payload. Malformed messages and messages of
+/// incompatible protocol versions fail with `TransportError::Deserialize`.
+fn accept_payload<T: DeserializeOwned>(
+    payload: &[u8],
+    source: SocketAddr,
+    config: &TransportConfig,
+) -> Result<(T, SocketAddr), TransportError> {
+    let payload = match &config.network_key {
+        Some(key) => auth::strip_tag(payload, key).ok_or(TransportError::Deserialize(
+            source,
+            DecodeError::Unauthenticated,
+        ))?,
+        None => payload,
+    };
+    let payload = compress::inflate(payload)
+        .ok_or(TransportError::Deserialize(source, DecodeError::Invalid))?;
+    if config.debug_wire {
+        println!(
+            "Received message from {}:\n{}",
+            source,
+            String::from_utf8_lossy(&payload)
+        );
+    }
+    decode(&payload)
+        .map(|msg| (msg, source))
+        .map_err(|e| TransportError::Deserialize(source, e))
+}
+
+fn invalid_input(message: &str) -> TransportError {
+    TransportError::Io(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::time::Duration;
+
+    use socket2::SockRef;
+
+    use crate::chunk::CHUNK_MAGIC;
+    use crate::envelope::Envelope;
+    use crate::{
+        network_key, over_mtu_warning, resolve, DecodeError, ProtocolVersion, Transport,
+        TransportConfig, TransportError, PROTOCOL_VERSION,
+    };
+
+    const MTU: usize = 100;
+
+    fn envelope_json(payload: &str, version: ProtocolVersion) -> String {
+        serde_json::to_string(&Envelope { version, payload }).unwrap()
+    }
+
+    /// Sends a string whose envelope serializes to exactly `len` bytes and
+    /// returns the first datagram and the received message.
+    fn send_payload(len: usize) -> (Vec<u8>, String) {
+        let config = TransportConfig {
+            mtu: MTU,
+            ..Default::default()
+        };
+        let sender = Transport::with_config("127.0.0.1:0", config).unwrap();
+        let receiver = Transport::with_config("127.0.0.1:0", config).unwrap();
+        let raw = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let overhead = envelope_json("", PROTOCOL_VERSION).len();
+        let payload = "a".repeat(len - overhead);
+        assert_eq!(envelope_json(&payload, PROTOCOL_VERSION).len(), len);
+
+        sender.send(raw.local_addr().unwrap(), &payload).unwrap();
+        let mut buf = [0; 2 * MTU];
+        let (first_len, _) = raw.recv_from(&mut buf).unwrap();
+
+        sender
+            .send(receiver.local_addr().unwrap(), &payload)
+            .unwrap();
+        let received: String = receiver.receive().unwrap();
+        assert_eq!(received, payload);
+
+        (buf[..first_len].to_vec(), received)
+    }
+
+    #[test]
+    fn payload_under_mtu() {
+        let (datagram, payload) = send_payload(MTU);
+        assert_eq!(
+            datagram,
+            envelope_json(&payload, PROTOCOL_VERSION).as_bytes()
+        );
+    }
+
+    #[test]
+    fn payload_over_mtu() {
+        let (datagram, _) = send_payload(MTU + 1);
+        assert_eq!(datagram[0], CHUNK_MAGIC);
+        assert_eq!(datagram.len(), MTU);
+    }
+
+    #[test]
+    fn warning_over_mtu() {
+        #[derive(serde::Serialize)]
+        enum Probe {
+            Block(String),
+        }
+
+        let config = TransportConfig {
+            mtu: MTU,
+            warn_over_mtu: true,
+            ..Default::default()
+        };
+        let sender = Transport::with_config("127.0.0.1:0", config).unwrap();
+        let receiver = Transport::with_config("127.0.0.1:0", config).unwrap();
+        let msg = Probe::Block("a".repeat(2 * MTU));
+        sender.send(receiver.local_addr().unwrap(), &msg).unwrap();
+        let received: serde_json::Value = receiver.receive().unwrap();
+        assert!(received.get("Block").is_some());
+
+        assert_eq!(
+            over_mtu_warning(&msg, 250, MTU),
+            "Warning: Block message of 250 bytes exceeds the MTU of 100 bytes"
+        );
+        assert!(over_mtu_warning(&[1, 2], 250, MTU).contains("unnamed message"));
+    }
+
+    #[test]
+    fn large_payload() {
+        let (_, payload) = send_payload(10 * MTU);
+        let overhead = envelope_json("", PROTOCOL_VERSION).len();
+        assert_eq!(payload.len(), 10 * MTU - overhead);
+    }
+
+    #[test]
+    fn incompatible_version_reported() {
+        let receiver = Transport::new("127.0.0.1:0").unwrap();
+        let raw = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to = receiver.local_addr().unwrap();
+
+        let newer = ProtocolVersion {
+            major: PROTOCOL_VERSION.major + 1,
+            minor: 0,
+        };
+        raw.send_to(envelope_json("newer", newer).as_bytes(), to)
+            .unwrap();
+        let compatible = ProtocolVersion {
+            minor: PROTOCOL_VERSION.minor + 1,
+            ..PROTOCOL_VERSION
+        };
+        raw.send_to(envelope_json("compatible", compatible).as_bytes(), to)
+            .unwrap();
+
+        let rejected = receiver.receive::<String>();
+        assert!(matches!(
+            rejected,
+            Err(TransportError::Deserialize(_, DecodeError::Incompatible(version))) if version == newer
+        ));
+        let received: String = receiver.receive().unwrap();
+        assert_eq!(received, "compatible");
+    }
+
+    #[test]
+    fn malformed_message_reported() {
+        let receiver = Transport::new("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(1)));
+        let raw = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let to = receiver.local_addr().unwrap();
+
+        raw.send_to(envelope_json("first", PROTOCOL_VERSION).as_bytes(), to)
+            .unwrap();
+        raw.send_to(b"{not json", to).unwrap();
+        raw.send_to(&[0xc3, 0x28], to).unwrap();
+        raw.send_to(envelope_json("second", PROTOCOL_VERSION).as_bytes(), to)
+            .unwrap();
+
+        assert_eq!(receiver.receive::<String>().unwrap(), "first");
+        for _ in 0..2 {
+            let result = receiver.receive::<String>();
+            assert!(matches!(
+                result,
+                Err(TransportError::Deserialize(source, DecodeError::Invalid))
+                    if source == raw.local_addr().unwrap()
+            ));
+        }
+        assert_eq!(receiver.receive::<String>().unwrap(), "second");
+    }
+
+    #[test]
+    fn unexpected_type_reported() {
+        let sender = Transport::new("127.0.0.1:0").unwrap();
+        let receiver = Transport::new("127.0.0.1:0").unwrap();
+        sender
+            .send(receiver.local_addr().unwrap(), &"not a number")
+            .unwrap();
+
+        let result = receiver.receive::<u64>();
+        assert!(matches!(
+            result,
+            Err(TransportError::Deserialize(_, DecodeError::Invalid))
+        ));
+    }
+
+    #[test]
+    fn network_key_required() {
+        let config = TransportConfig {
+            network_key: Some(network_key("private")),
+            ..Default::default()
+        };
+        let receiver = Transport::with_config("127.0.0.1:0", config).unwrap();
+        receiver.set_read_timeout(Some(Duration::from_secs(1)));
+        let to = receiver.local_addr().unwrap();
+
+        let untagged = Transport::new("127.0.0.1:0").unwrap();
+        let wrong_key = Transport::with_config(
+            "127.0.0.1:0",
+            TransportConfig {
+                network_key: Some(network_key("guessed")),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let member = Transport::with_config("127.0.0.1:0", config).unwrap();
+        untagged.send(to, &"spoofed").unwrap();
+        wrong_key.send(to, &"forged").unwrap();
+        member.send(to, &"authentic").unwrap();
+        // Chunked messages carry a single tag after reassembly.
+        let large = "a".repeat(4 * config.mtu);
+        member.send(to, &large).unwrap();
+
+        for _ in 0..2 {
+            let result = receiver.receive::<String>();
+            assert!(matches!(
+                result,
+                Err(TransportError::Deserialize(_, DecodeError::Unauthenticated))
+            ));
+        }
+        assert_eq!(receiver.receive::<String>().unwrap(), "authentic");
+        assert_eq!(receiver.receive::<String>().unwrap(), large);
+    }
+
+    #[test]
+    fn bind_failure() {
+        let bound = Transport::new("127.0.0.1:0").unwrap();
+        let result = Transport::new(bound.local_addr().unwrap());
+        assert!(matches!(result, Err(TransportError::Bind(_))));
+    }
+
+    #[test]
+    fn receive_timeout() {
+        let receiver = Transport::new("127.0.0.1:0").unwrap();
+        receiver.set_read_timeout(Some(Duration::from_millis(10)));
+
+        let error = receiver.receive::<String>().unwrap_err();
+        assert!(error.is_timeout());
+    }
+
+    #[test]
+    fn pretty_round_trip() {
+        let config = TransportConfig {
+            debug_wire: true,
+            ..Default::default()
+        };
+        let sender = Transport::with_config("127.0.0.1:0", config).unwrap();
+        let receiver = Transport::new("127.0.0.1:0").unwrap();
+        let raw = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let msg = vec!["pretty".to_string(), "json".to_string()];
+
+        sender.send(raw.local_addr().unwrap(), &msg).unwrap();
         let mut buf = [0; 1536];
-        let (len, _) = self.socket.recv_from(&mut buf).ok()?;
+        let (len, _) = raw.recv_from(&mut buf).unwrap();
+        assert!(buf[..len].contains(&b'\n'));
 
-        let Ok(string) = String::from_utf8(buf[..len].to_vec()) else {
-            println!("failed to decode request");
-            return None;
+        sender.send(receiver.local_addr().unwrap(), &msg).unwrap();
+        let received: Vec<String> = receiver.receive().unwrap();
+        assert_eq!(received, msg);
+    }
+
+    #[test]
+    fn compressed_round_trip() {
+        let config = TransportConfig {
+            mtu: MTU,
+            compress: true,
+            ..Default::default()
+        };
+        let sender = Transport::with_config("127.0.0.1:0", config).unwrap();
+        let receiver = Transport::with_config(
+            "127.0.0.1:0",
+            TransportConfig {
+                mtu: MTU,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let payload = "repetitive ".repeat(100);
+
+        let sent = sender
+            .send(receiver.local_addr().unwrap(), &payload)
+            .unwrap();
+        assert!(sent < envelope_json(&payload, PROTOCOL_VERSION).len() / 2);
+        let received: String = receiver.receive().unwrap();
+        assert_eq!(received, payload);
+
+        // Short messages are sent as is.
+        sender
+            .send(receiver.local_addr().unwrap(), &"short")
+            .unwrap();
+        assert_eq!(receiver.receive::<String>().unwrap(), "short");
+    }
+
+    #[test]
+    fn buffer_sizes() {
+        let config = TransportConfig {
+            recv_buffer: Some(64 * 1024),
+            send_buffer: Some(32 * 1024),
+            ..Default::default()
         };
+        let transport = Transport::with_config("127.0.0.1:0", config).unwrap();
+
+        let socket = SockRef::from(&transport.socket);
+        assert!(socket.recv_buffer_size().unwrap() >= 64 * 1024);
+        assert!(socket.send_buffer_size().unwrap() >= 32 * 1024);
+    }
+
+    #[test]
+    fn resolve_ipv6_literal() {
+        let addr = resolve("[::1]:9000", false).unwrap();
+        assert_eq!(addr, "[::1]:9000".parse().unwrap());
+
+        let addr = resolve("[2001:db8::1]:9000", false).unwrap();
+        assert!(addr.is_ipv6());
+    }
+
+    #[test]
+    fn resolve_hostname() {
+        let addr = resolve("localhost:9000", false).unwrap();
+        assert!(addr.ip().is_loopback());
+        assert_eq!(addr.port(), 9000);
+
+        assert!(resolve("localhost", false).is_err());
+    }
+
+    #[test]
+    fn send_unserializable() {
+        let sender = Transport::new("127.0.0.1:0").unwrap();
+        let msg = std::collections::HashMap::from([(vec![1u8], 1u8)]);
 
-        serde_json::from_str::<T>(&string).ok()
+        let result = sender.send(sender.local_addr().unwrap(), &msg);
+        assert!(matches!(result, Err(TransportError::Serialize(_))));
     }
 }
@@ -1,3 +1,4 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::net::SocketAddr;
 
 use k256::ecdsa::{RecoveryId, Signature as K256Signature, SigningKey, VerifyingKey};
@@ -8,18 +9,53 @@ use k256::schnorr::signature::hazmat::PrehashSigner;
 use k256::sha2::Digest;
 use k256::U256;
 use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+
+/// Digest of all hashes and addresses: Keccak-256 with the `keccak` feature,
+/// SHA-256 otherwise. Nodes and clients must be built with the same one.
+#[cfg(not(feature = "keccak"))]
+pub type Hasher = k256::sha2::Sha256;
+#[cfg(feature = "keccak")]
+pub type Hasher = sha3::Keccak256;
+
+/// `prev_hash` of the genesis block.
+pub const GENESIS_PREV_HASH: B256 = B256::ZERO;
+
+/// Sender of coinbase transactions, meaning the protocol itself.
+pub const COINBASE: B256 = B256::ZERO;
+
+/// Maximal size of a transaction memo in bytes.
+pub const MAX_MEMO_LEN: usize = 64;
+
+/// Maximal size of a node name in bytes.
+pub const MAX_NAME_LEN: usize = 64;
+
+/// Maximal number of transactions in a block.
+pub const MAX_BLOCK_TXS: usize = 100;
+
+/// Maximal serialized size of a block in bytes.
+pub const MAX_BLOCK_SIZE: usize = 64 * 1024;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BlockData {
     pub prev_hash: B256,
     pub number: u64,
+    pub nonce: u64,
+    /// Milliseconds since the UNIX epoch by the proposer's clock.
+    #[serde(default)]
+    pub timestamp: u64,
     pub transactions: Vec<Transaction>,
 }
 
 impl BlockData {
-    pub fn hash(&self) -> B256 {
-        let mut hasher = k256::sha2::Sha256::new();
+    /// Hash of the block proposed by the address. Committing to the proposer
+    /// keeps blocks of different proposers with the same contents apart.
+    pub fn hash(&self, proposer: B256) -> B256 {
+        let mut hasher = Hasher::new();
         hasher.update(self.prev_hash.0);
+        hasher.update(proposer.0);
+        hasher.update(self.nonce.to_be_bytes());
+        hasher.update(self.timestamp.to_be_bytes());
 
         for tx in &self.transactions {
             hasher.update(tx.hash.0);
@@ -28,6 +64,25 @@ impl BlockData {
         let result = hasher.finalize();
         B256(result.into())
     }
+
+    /// Checks whether some transaction is included more than once.
+    pub fn has_duplicate_txs(&self) -> bool {
+        let mut hashes = HashSet::new();
+        !self.transactions.iter().all(|tx| hashes.insert(tx.hash))
+    }
+
+    /// Checks if the block carries no transactions.
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    /// Searches for a nonce making the hash of the proposer's block meet the
+    /// difficulty.
+    pub fn mine(&mut self, difficulty: u32, proposer: B256) {
+        while self.hash(proposer).leading_zeros() < difficulty {
+            self.nonce += 1;
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -40,10 +95,10 @@ pub struct Block {
 
 impl Block {
     /// Creates a new signed block.
-    pub fn new(data: BlockData, signer: &SigningKey) -> Self {
-        let hash = data.hash();
-        let signature = Signature::sign(signer, hash);
-        let proposer = B256::address_of(signer.verifying_key());
+    pub fn new(data: BlockData, signer: &impl Signer) -> Self {
+        let proposer = signer.address();
+        let hash = data.hash(proposer);
+        let signature = signer.sign_prehash(hash);
 
         Self {
             hash,
@@ -53,50 +108,189 @@ impl Block {
         }
     }
 
-    /// Check correctness of block signature.
+    /// Check correctness of block signature and size.
     pub fn verify(&self) -> Option<()> {
-        let expected_hash = self.data.hash();
+        if self.data.transactions.len() > MAX_BLOCK_TXS || self.serialized_size() > MAX_BLOCK_SIZE {
+            return None;
+        }
+
+        if self.data.has_duplicate_txs() {
+            return None;
+        }
+
+        let expected_hash = self.data.hash(self.proposer);
         if self.hash != expected_hash {
             return None;
         }
 
         let expectet_proposer = self.signature.recover(expected_hash)?;
-        if self.proposer != expectet_proposer {
+        if !self.proposer.ct_eq(&expectet_proposer) {
             return None;
         }
 
         Some(())
     }
 
-    /// Creates a new genesis block.
+    /// Length of the block serialized as JSON, in bytes.
+    pub fn serialized_size(&self) -> usize {
+        json_size(self)
+    }
+
+    /// Checks that the block has at most one coinbase transaction, paying
+    /// exactly the `reward` to the proposer.
+    pub fn verify_coinbase(&self, reward: u64) -> Option<()> {
+        let mut coinbases = self.data.transactions.iter().filter(|tx| tx.is_coinbase());
+        let Some(coinbase) = coinbases.next() else {
+            return Some(());
+        };
+        if coinbases.next().is_some() {
+            return None;
+        }
+
+        let expected = Transaction::coinbase(self.proposer, reward, self.data.number);
+        (coinbase.hash == expected.hash && coinbase.data.amount == reward).then_some(())
+    }
+
+    /// Checks that the block hash has at least `difficulty` leading zero bits.
+    pub fn verify_difficulty(&self, difficulty: u32) -> Option<()> {
+        (self.hash.leading_zeros() >= difficulty).then_some(())
+    }
+
+    /// Creates a new genesis block of the default `GenesisConfig`.
     pub fn new_genesis() -> Self {
-        Self::new(
+        GenesisConfig::default().block()
+    }
+}
+
+/// Initial state of a chain. All its nodes must start from the same config,
+/// as it is committed to by the genesis block.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenesisConfig {
+    /// Amounts credited to the accounts on top of the initial balance.
+    #[serde(default)]
+    pub allocations: BTreeMap<B256, u64>,
+    /// Addresses allowed to propose blocks. Empty lets anyone propose.
+    #[serde(default)]
+    pub validators: BTreeSet<B256>,
+}
+
+impl GenesisConfig {
+    /// Creates the genesis block. Allocations are its coinbase transactions,
+    /// and the validators are committed to by a zero coinbase to the hash of
+    /// their set. Blocks following genesis blocks of different configs don't
+    /// link to each other.
+    pub fn block(&self) -> Block {
+        let mut transactions: Vec<Transaction> = self
+            .allocations
+            .iter()
+            .map(|(to, amount)| Transaction::coinbase(*to, *amount, 0))
+            .collect();
+        if !self.validators.is_empty() {
+            transactions.push(Transaction::coinbase(self.validators_hash(), 0, 0));
+        }
+        Block::new(
             BlockData {
-                prev_hash: B256::default(),
+                prev_hash: GENESIS_PREV_HASH,
                 number: 0,
-                transactions: vec![],
+                nonce: 0,
+                timestamp: 0,
+                transactions,
             },
             &SigningKey::from_slice(&[42; 32]).unwrap(),
         )
     }
+
+    /// Checks if the address may propose blocks.
+    pub fn is_validator(&self, address: &B256) -> bool {
+        self.validators.is_empty() || self.validators.contains(address)
+    }
+
+    fn validators_hash(&self) -> B256 {
+        let mut hasher = Hasher::new();
+        hasher.update(b"validators");
+        for validator in &self.validators {
+            hasher.update(validator.0);
+        }
+        B256(hasher.finalize().into())
+    }
 }
 
 /// Data of a transaction.
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct TransactionData {
     pub to: B256,
     pub amount: u64,
+    /// Paid by the sender to the proposer of the block including the transaction.
+    #[serde(default)]
+    pub fee: u64,
+    /// Distinguishes otherwise equal transactions of the sender.
+    #[serde(default)]
+    pub nonce: u64,
+    /// Note attached by the sender, at most `MAX_MEMO_LEN` bytes.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub memo: Vec<u8>,
 }
 
 impl TransactionData {
     /// Calculates the hash of the transaction.
     pub fn hash(&self) -> B256 {
-        let mut hasher = k256::sha2::Sha256::new();
+        let mut hasher = Hasher::new();
         hasher.update(self.to.0);
         hasher.update(self.amount.to_be_bytes());
+        hasher.update(self.fee.to_be_bytes());
+        hasher.update(self.nonce.to_be_bytes());
+        hasher.update((self.memo.len() as u64).to_be_bytes());
+        hasher.update(&self.memo);
         let result = hasher.finalize();
         B256(result.into())
     }
+
+    /// Memo as text, if it's valid UTF-8.
+    pub fn memo_text(&self) -> Option<&str> {
+        std::str::from_utf8(&self.memo).ok()
+    }
+}
+
+/// Builds and signs a transaction. Unset fields are zero.
+#[derive(Debug, Default, Clone)]
+pub struct TransactionBuilder {
+    data: TransactionData,
+}
+
+impl TransactionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn to(mut self, to: B256) -> Self {
+        self.data.to = to;
+        self
+    }
+
+    pub fn amount(mut self, amount: u64) -> Self {
+        self.data.amount = amount;
+        self
+    }
+
+    pub fn fee(mut self, fee: u64) -> Self {
+        self.data.fee = fee;
+        self
+    }
+
+    pub fn nonce(mut self, nonce: u64) -> Self {
+        self.data.nonce = nonce;
+        self
+    }
+
+    pub fn memo(mut self, memo: impl Into<Vec<u8>>) -> Self {
+        self.data.memo = memo.into();
+        self
+    }
+
+    /// Signs the transaction by the sender.
+    pub fn sign(self, signer: &impl Signer) -> Transaction {
+        Transaction::new(self.data, signer)
+    }
 }
 
 /// Signed transaction.
@@ -110,10 +304,10 @@ pub struct Transaction {
 
 impl Transaction {
     /// Creates new signed transaction.
-    pub fn new(data: TransactionData, signer: &SigningKey) -> Self {
+    pub fn new(data: TransactionData, signer: &impl Signer) -> Self {
         let hash = data.hash();
-        let signature = Signature::sign(signer, hash);
-        let from = B256::address_of(signer.verifying_key());
+        let signature = signer.sign_prehash(hash);
+        let from = signer.address();
         Self {
             hash,
             from,
@@ -122,26 +316,108 @@ impl Transaction {
         }
     }
 
-    /// Check correctness of transaction signature.
+    /// Creates the coinbase transaction minting the block reward to the
+    /// proposer of the block `number`. It comes from `COINBASE` and isn't
+    /// signed, so its hash is bound to the block number to keep it unique.
+    pub fn coinbase(to: B256, amount: u64, number: u64) -> Self {
+        let data = TransactionData {
+            to,
+            amount,
+            ..Default::default()
+        };
+
+        let mut hasher = Hasher::new();
+        hasher.update(b"coinbase");
+        hasher.update(number.to_be_bytes());
+        hasher.update(data.hash().0);
+        let hash = B256(hasher.finalize().into());
+
+        Self {
+            hash,
+            from: COINBASE,
+            data,
+            signature: Signature {
+                r: B256::ZERO,
+                s: B256::ZERO,
+                recovery_id: 0,
+            },
+        }
+    }
+
+    /// Length of the transaction serialized as JSON, in bytes.
+    pub fn serialized_size(&self) -> usize {
+        json_size(self)
+    }
+
+    /// Checks if the transaction mints a block reward.
+    pub fn is_coinbase(&self) -> bool {
+        self.from == COINBASE
+    }
+
+    /// Check correctness of transaction signature and size of the memo.
     pub fn verify(&self) -> Option<()> {
+        if self.data.memo.len() > MAX_MEMO_LEN {
+            return None;
+        }
+
         let expected_hash = self.data.hash();
         if self.hash != expected_hash {
             return None;
         }
 
         let expected_from = self.signature.recover(expected_hash)?;
-        if self.from != expected_from {
+        if !self.from.ct_eq(&expected_from) {
             return None;
         }
 
         Some(())
     }
+
+    /// Address recovered from the signature of the data, whatever `from`
+    /// claims. Coinbase transactions aren't signed and recover nothing.
+    pub fn recover_sender(&self) -> Option<B256> {
+        self.signature.recover(self.data.hash())
+    }
 }
 
-/// A 32-bytes identifier.
-#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+/// Length of the JSON serialization of the value, counted without
+/// allocating it.
+fn json_size(value: &impl Serialize) -> usize {
+    struct Counter(usize);
+
+    impl std::io::Write for Counter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    serde_json::to_writer(&mut counter, value).expect("value should be serialized");
+    counter.0
+}
+
+/// A 32-bytes identifier. Serialized as a hex string.
+#[derive(Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct B256(pub [u8; 32]);
 
+impl Serialize for B256 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for B256 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        Self::from_hex_string(&s).map_err(serde::de::Error::custom)
+    }
+}
+
 impl std::fmt::Debug for B256 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "B256({})", hex::encode(self.0))
@@ -155,9 +431,24 @@ impl std::fmt::Display for B256 {
 }
 
 impl B256 {
-    /// Calculates the hash of the data using SHA256 algorithm.
+    /// All-zero value, used as a sentinel for "none", e.g. the genesis `prev_hash`.
+    pub const ZERO: Self = Self([0; 32]);
+
+    /// Compares in constant time, not leaking the position of the first
+    /// differing byte. Used to verify signatures and addresses, `==` is fine
+    /// elsewhere.
+    pub fn ct_eq(&self, other: &Self) -> bool {
+        self.0.ct_eq(&other.0).into()
+    }
+
+    /// Checks if this is the `ZERO` sentinel.
+    pub fn is_zero(&self) -> bool {
+        *self == Self::ZERO
+    }
+
+    /// Calculates the hash of the data using the configured `Hasher`.
     pub fn hash_of(data: impl AsRef<[u8]>) -> Self {
-        let mut hasher = k256::sha2::Sha256::new();
+        let mut hasher = Hasher::new();
         hasher.update(data);
         let result = hasher.finalize();
         Self(result.into())
@@ -170,7 +461,20 @@ impl B256 {
         Self::hash_of(data)
     }
 
-    /// Found difference between two hashes.
+    /// Number of leading zero bits.
+    pub fn leading_zeros(&self) -> u32 {
+        let mut zeros = 0;
+        for byte in self.0 {
+            zeros += byte.leading_zeros();
+            if byte != 0 {
+                break;
+            }
+        }
+        zeros
+    }
+
+    /// Found difference between two hashes. Two different hashes may be
+    /// equally distant from a third one, one on each side of it.
     pub fn distance(&self, other: B256) -> U256 {
         let self_num = U256::from_be_slice(&self.0);
         let other_num = U256::from_be_slice(&other.0);
@@ -181,48 +485,288 @@ impl B256 {
         }
     }
 
-    /// Creates Self from hex string.
-    pub fn from_hex_string(s: &str) -> Option<Self> {
-        let bytes = hex::decode(s).ok()?;
-        if bytes.len() != 32 {
-            return None;
+    /// Bitwise XOR of two hashes, the metric of Kademlia-style routing.
+    /// Unlike `distance`, no two different hashes are equally distant from
+    /// a third one.
+    pub fn xor_distance(&self, other: B256) -> U256 {
+        let mut xor = [0; 32];
+        for (byte, (a, b)) in xor.iter_mut().zip(self.0.iter().zip(other.0)) {
+            *byte = a ^ b;
         }
-        Some(Self(bytes.try_into().unwrap()))
+        U256::from_be_slice(&xor)
     }
+
+    /// Creates Self from hex string of exactly 64 characters.
+    pub fn from_hex_string(s: &str) -> Result<Self, ParseError> {
+        if s.len() != 64 {
+            return Err(ParseError::InvalidLength(s.len()));
+        }
+
+        let mut bytes = [0; 32];
+        hex::decode_to_slice(s, &mut bytes).map_err(|e| match e {
+            hex::FromHexError::InvalidHexCharacter { c, index } => {
+                ParseError::InvalidCharacter(c, index)
+            }
+            _ => ParseError::InvalidLength(s.len()),
+        })?;
+        Ok(Self(bytes))
+    }
+}
+
+/// Error of parsing a `B256` from a string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseError {
+    /// The string isn't 64 characters long.
+    InvalidLength(usize),
+    /// The string contains a non-hex character at the index.
+    InvalidCharacter(char, usize),
+    /// The signature string isn't 130 characters long.
+    InvalidSignatureLength(usize),
 }
 
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidLength(len) => {
+                write!(f, "expected 64 hex characters, got {}", len)
+            }
+            Self::InvalidCharacter(c, index) => {
+                write!(f, "invalid hex character {:?} at position {}", c, index)
+            }
+            Self::InvalidSignatureLength(len) => {
+                write!(f, "expected 130 hex characters, got {}", len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 /// Information about a node.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct NodeInfo {
     pub name: String,
     pub address: B256,
+    /// Key the `address` is derived from.
+    pub public_key: PublicKey,
     pub socket: SocketAddr,
+    /// Observers follow the chain but never propose blocks.
+    #[serde(default)]
+    pub observer: bool,
+}
+
+impl NodeInfo {
+    /// Calculates the hash of the info.
+    pub fn hash(&self) -> B256 {
+        let mut hasher = Hasher::new();
+        hasher.update(self.name.as_bytes());
+        hasher.update(self.address.0);
+        hasher.update(&self.public_key.0);
+        hasher.update(self.socket.to_string().as_bytes());
+        hasher.update([self.observer as u8]);
+        B256(hasher.finalize().into())
+    }
+
+    /// Signs the info by the node's key, proving it controls the address.
+    pub fn sign(&self, signer: &impl Signer) -> Signature {
+        signer.sign_prehash(self.hash())
+    }
+
+    /// Checks that the address is derived from the public key.
+    pub fn verify_address(&self) -> Option<()> {
+        self.public_key
+            .address()?
+            .ct_eq(&self.address)
+            .then_some(())
+    }
+
+    /// Checks that the name is at most `MAX_NAME_LEN` bytes and has no
+    /// control characters. Names are relayed in every hello, so they must
+    /// stay short and printable.
+    pub fn verify_name(&self) -> Option<()> {
+        let printable = !self.name.chars().any(char::is_control);
+        (self.name.len() <= MAX_NAME_LEN && printable).then_some(())
+    }
+
+    /// Checks that the info is signed by the owner of the address.
+    pub fn verify(&self, signature: &Signature) -> Option<()> {
+        self.verify_address()?;
+        signature.verify(self.hash(), self.address)
+    }
+}
+
+/// Balances of the accounts right after the block `height`. Lets a node
+/// start from the state instead of replaying the whole chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub height: u64,
+    /// Hash of the block at the height.
+    pub block_hash: B256,
+    /// Balances of the accounts touched by the blocks up to the height.
+    pub balances: BTreeMap<B256, u64>,
+}
+
+impl StateSnapshot {
+    /// Calculates the hash of the snapshot.
+    pub fn hash(&self) -> B256 {
+        let mut hasher = Hasher::new();
+        hasher.update(self.height.to_be_bytes());
+        hasher.update(self.block_hash.0);
+        for (address, balance) in &self.balances {
+            hasher.update(address.0);
+            hasher.update(balance.to_be_bytes());
+        }
+        B256(hasher.finalize().into())
+    }
+
+    /// Signs the snapshot, vouching for the state.
+    pub fn sign(&self, signer: &impl Signer) -> Signature {
+        signer.sign_prehash(self.hash())
+    }
+
+    /// Checks that the snapshot is signed by the `address`.
+    pub fn verify(&self, signature: &Signature, address: B256) -> Option<()> {
+        signature.verify(self.hash(), address)
+    }
+}
+
+/// SEC1-encoded verifying key. Serialized as a hex string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey(pub Vec<u8>);
+
+impl PublicKey {
+    pub fn from_key(key: &VerifyingKey) -> Self {
+        Self(key.to_encoded_point(true).as_bytes().to_vec())
+    }
+
+    pub fn to_key(&self) -> Option<VerifyingKey> {
+        VerifyingKey::from_sec1_bytes(&self.0).ok()
+    }
+
+    /// Address derived from the key, if the key is valid.
+    pub fn address(&self) -> Option<B256> {
+        Some(B256::address_of(&self.to_key()?))
+    }
+}
+
+impl Serialize for PublicKey {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicKey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        hex::decode(s.as_ref())
+            .map(Self)
+            .map_err(serde::de::Error::custom)
+    }
 }
 
 /// Recovering signature for some data.
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Signature {
     pub r: B256,
     pub s: B256,
     pub recovery_id: u8,
 }
-impl Signature {
-    /// Creates new signature.
-    fn sign(signer: &SigningKey, hash: B256) -> Self {
-        let (sig, ver) = signer
-            .sign_prehash(&hash.0)
-            .expect("prehash should be signed");
 
-        Self {
-            r: B256(sig.r().to_bytes().try_into().unwrap()),
-            s: B256(sig.s().to_bytes().try_into().unwrap()),
+/// Holder of a key signing blocks, transactions and other data, e.g. an
+/// in-memory `SigningKey` or a hardware wallet.
+pub trait Signer {
+    /// Address of the key.
+    fn address(&self) -> B256;
+
+    /// Signs the hash with a recoverable signature.
+    fn sign_prehash(&self, hash: B256) -> Signature;
+}
+
+impl Signer for SigningKey {
+    fn address(&self) -> B256 {
+        B256::address_of(self.verifying_key())
+    }
+
+    fn sign_prehash(&self, hash: B256) -> Signature {
+        let (sig, ver): (K256Signature, RecoveryId) =
+            PrehashSigner::sign_prehash(self, &hash.0).expect("prehash should be signed");
+
+        Signature {
+            r: B256(sig.r().to_bytes().into()),
+            s: B256(sig.s().to_bytes().into()),
             recovery_id: ver.to_byte(),
         }
     }
+}
+
+impl Signature {
+    /// Hex representation of `r`, `s` and the recovery id, 65 bytes in total.
+    pub fn to_hex(&self) -> String {
+        format!(
+            "{}{}{}",
+            hex::encode(self.r.0),
+            hex::encode(self.s.0),
+            hex::encode([self.recovery_id])
+        )
+    }
+
+    /// Parses the representation made by `to_hex`.
+    pub fn from_hex(s: &str) -> Result<Self, ParseError> {
+        if s.len() != 130 || !s.is_char_boundary(64) || !s.is_char_boundary(128) {
+            return Err(ParseError::InvalidSignatureLength(s.len()));
+        }
+        let shift = |e, offset| match e {
+            ParseError::InvalidCharacter(c, index) => {
+                ParseError::InvalidCharacter(c, index + offset)
+            }
+            e => e,
+        };
+        let r = B256::from_hex_string(&s[..64])?;
+        let s_part = B256::from_hex_string(&s[64..128]).map_err(|e| shift(e, 64))?;
+        let mut recovery_id = [0];
+        hex::decode_to_slice(&s[128..], &mut recovery_id).map_err(|e| match e {
+            hex::FromHexError::InvalidHexCharacter { c, index } => {
+                ParseError::InvalidCharacter(c, index + 128)
+            }
+            _ => ParseError::InvalidSignatureLength(s.len()),
+        })?;
+
+        Ok(Self {
+            r,
+            s: s_part,
+            recovery_id: recovery_id[0],
+        })
+    }
+
+    /// Compact `r || s || v` representation with big-endian `r` and `s` and
+    /// the recovery id as `v`.
+    pub fn to_bytes(&self) -> [u8; 65] {
+        let mut bytes = [0; 65];
+        bytes[..32].copy_from_slice(&self.r.0);
+        bytes[32..64].copy_from_slice(&self.s.0);
+        bytes[64] = self.recovery_id;
+        bytes
+    }
+
+    /// Parses the representation made by `to_bytes`. Returns `None` if the
+    /// recovery id or the scalars are invalid.
+    pub fn from_bytes(bytes: &[u8; 65]) -> Option<Self> {
+        let mut r = B256::ZERO;
+        let mut s = B256::ZERO;
+        r.0.copy_from_slice(&bytes[..32]);
+        s.0.copy_from_slice(&bytes[32..64]);
+        let signature = Self {
+            r,
+            s,
+            recovery_id: bytes[64],
+        };
+        signature.as_signature()?;
+        Some(signature)
+    }
 
     /// Recover the address of the signer.
     pub fn recover(&self, hash: B256) -> Option<B256> {
-        let (recoverable_sig, recovery_id) = self.as_signature();
+        let (recoverable_sig, recovery_id) = self.as_signature()?;
         let verify_key =
             VerifyingKey::recover_from_prehash(&hash.0, &recoverable_sig, recovery_id).ok()?;
 
@@ -233,45 +777,759 @@ impl Signature {
     /// Checks if the signature is created by the `address`.
     pub fn verify(&self, hash: B256, address: B256) -> Option<()> {
         let recovered = self.recover(hash)?;
-        (recovered == address).then_some(())
+        recovered.ct_eq(&address).then_some(())
     }
 
     /// Retrieves the recovery signature.
-    fn as_signature(&self) -> (K256Signature, RecoveryId) {
-        let recovery_id = RecoveryId::from_byte(self.recovery_id).unwrap();
+    fn as_signature(&self) -> Option<(K256Signature, RecoveryId)> {
+        let recovery_id = RecoveryId::from_byte(self.recovery_id)?;
         let r: &GenericArray<u8, U32> = GenericArray::from_slice(&self.r.0);
         let s: &GenericArray<u8, U32> = GenericArray::from_slice(&self.s.0);
-        let sig = K256Signature::from_scalars(*r, *s).unwrap();
-        (sig, recovery_id)
+        let sig = K256Signature::from_scalars(*r, *s).ok()?;
+        Some((sig, recovery_id))
     }
 }
 
 /// Message that node can process.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Message {
-    Hello(NodeInfo),
+    /// Introduction of a node signed by its key.
+    Hello(NodeInfo, Signature),
     Transaction(Transaction),
+    SubmitTransaction(ReplyTo, Transaction),
     Block(Block),
-    SyncBlock(B256, u64),
-    BalanceOf(SocketAddr, B256),
+    /// Requests the blocks from the number on, sent to the socket, so
+    /// clients that aren't peers can sync too.
+    SyncBlock(SocketAddr, u64),
+    /// Number of blocks in the sender's chain, sent after the sync blocks
+    /// so the requester can ask for more.
+    ChainHeight(B256, u64),
+    /// Commitment to the balances right after the block with the number,
+    /// to detect nodes diverging on the state.
+    StateRoot(u64, B256),
+    BalanceOf(ReplyTo, B256),
+    /// Requests the balance right after the block with the number.
+    BalanceOfAt(ReplyTo, B256, u64),
+    Subscribe(SocketAddr),
+    GetChain(ReplyTo),
+    /// Requests the mined transaction with the hash.
+    GetTransaction(ReplyTo, B256),
+    /// Requests the known peers, starting from the index.
+    GetPeerTable(ReplyTo, u64),
+    /// Requests the signed state right after the block with the number.
+    GetSnapshot(ReplyTo, u64),
+    /// Requests the block with the number along with its proposer.
+    GetBlockView(ReplyTo, u64),
+    /// Asks whether a transaction with the fee would make it into the next
+    /// block given the pending ones.
+    EstimateInclusion(ReplyTo, u64),
+    /// Requests the hash and number of the last block.
+    GetTip(ReplyTo),
+    /// Requests the accounts with a nonzero balance, starting from the index.
+    GetAccounts(ReplyTo, u64),
+    /// Proof that a proposer equivocated, gossiped to all nodes.
+    Evidence(SlashingEvidence),
+    /// Cheap liveness probe, e.g. for load balancers.
+    HealthCheck(ReplyTo),
+}
+
+/// Where and under which id to send the reply to a request.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ReplyTo {
+    pub socket: SocketAddr,
+    pub request_id: u64,
+}
+
+/// Reply of a node to a request.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Reply {
+    pub request_id: u64,
+    pub response: Response,
+}
+
+/// Response that node sends to a request.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Response {
+    /// Balance at the chain tip and balance according to the final blocks only.
+    Balance(u64, u64),
+    /// The node no longer keeps the state at the requested height, as its
+    /// block is pruned or precedes the snapshot the node started from.
+    BalancePruned(u64),
+    TransactionAck(B256, AckStatus),
+    /// JSON list of all blocks of the chain.
+    Chain(String),
+    /// Mined transaction and the number of its block, if it's found.
+    Transaction(Option<(Transaction, u64)>),
+    /// Total number of known peers and a page of them.
+    PeerTable(u64, Vec<NodeInfo>),
+    /// Snapshot signed by the node, if it has the block.
+    Snapshot(Option<(StateSnapshot, Signature)>),
+    /// Block with the requested number, if the node has it.
+    BlockView(Option<BlockView>),
+    /// Whether the transaction would be included in the next block.
+    Inclusion(bool),
+    /// Hash and number of the last block, if the node has any.
+    Tip(Option<(B256, u64)>),
+    /// Total number of accounts with a nonzero balance and a page of them
+    /// with their balances, sorted by address.
+    Accounts(u64, Vec<(B256, u64)>),
+    Health(HealthStatus),
+}
+
+/// State of a node answered to a health check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HealthStatus {
+    /// Number of blocks in the chain.
+    pub height: u64,
+    /// Number of known peers.
+    pub peers: u64,
+    /// Whether the node is catching up with its peers.
+    pub syncing: bool,
+}
+
+/// Proof that a proposer signed two different blocks with the same number.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SlashingEvidence {
+    pub first: Block,
+    pub second: Block,
+}
+
+impl SlashingEvidence {
+    /// Checks that both blocks are validly signed by the same proposer, have
+    /// the same number and differ.
+    pub fn verify(&self) -> Option<()> {
+        self.first.verify()?;
+        self.second.verify()?;
+        let conflicting = self.first.proposer == self.second.proposer
+            && self.first.data.number == self.second.data.number
+            && self.first.hash != self.second.hash;
+        conflicting.then_some(())
+    }
+
+    /// Identifies the evidence regardless of the order of the blocks.
+    pub fn id(&self) -> B256 {
+        let low = self.first.hash.min(self.second.hash);
+        let high = self.first.hash.max(self.second.hash);
+        B256::hash_of([low.0, high.0].concat())
+    }
+}
+
+/// Block with the details of its proposer, for explorers.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockView {
+    pub hash: B256,
+    pub number: u64,
+    pub prev_hash: B256,
+    pub nonce: u64,
+    pub proposer: B256,
+    /// Info of the proposer, if the node knows it.
+    pub proposer_info: Option<NodeInfo>,
+    pub transactions: Vec<Transaction>,
+}
+
+/// Outcome of a transaction submission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AckStatus {
+    Accepted,
+    Rejected(RejectReason),
+}
+
+/// Why a node refused to accept a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RejectReason {
+    InvalidSignature,
+    AlreadyMined,
+    InsufficientBalance,
+    /// The transaction was recently processed by the node.
+    AlreadySeen,
+    /// A pending transaction of the sender has the same nonce and
+    /// at least the same fee.
+    NonceConflict,
+    /// The sender already has the maximal number of pending transactions.
+    TooManyPending,
+}
+
+impl std::fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let reason = match self {
+            Self::InvalidSignature => "invalid signature",
+            Self::AlreadyMined => "already mined",
+            Self::InsufficientBalance => "insufficient balance",
+            Self::AlreadySeen => "already seen",
+            Self::NonceConflict => "pending transaction with the same nonce",
+            Self::TooManyPending => "too many pending transactions of the sender",
+        };
+        write!(f, "{}", reason)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use k256::ecdsa::SigningKey;
+    use std::cell::Cell;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use k256::U256;
+    use rand::Rng;
+
+    use crate::{
+        Block, BlockData, GenesisConfig, NodeInfo, ParseError, PublicKey, Signature, Signer,
+        StateSnapshot, Transaction, TransactionBuilder, TransactionData, B256, MAX_BLOCK_SIZE,
+        MAX_BLOCK_TXS, MAX_MEMO_LEN,
+    };
+
+    /// Signer standing in for a hardware wallet, counting the signed hashes.
+    struct MockSigner {
+        key: SigningKey,
+        signed: Cell<usize>,
+    }
+
+    impl Signer for MockSigner {
+        fn address(&self) -> B256 {
+            self.key.address()
+        }
+
+        fn sign_prehash(&self, hash: B256) -> Signature {
+            self.signed.set(self.signed.get() + 1);
+            self.key.sign_prehash(hash)
+        }
+    }
 
-    use crate::{Signature, B256};
+    fn block_with_txs(count: usize) -> Block {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let transactions = (0..count as u64)
+            .map(|amount| TransactionBuilder::new().amount(amount).sign(&signer))
+            .collect();
+
+        let data = BlockData {
+            prev_hash: B256::ZERO,
+            number: 1,
+            nonce: 0,
+            timestamp: 0,
+            transactions,
+        };
+        Block::new(data, &signer)
+    }
 
     #[test]
     fn sign_and_verify() {
         let signer = SigningKey::from_slice(&[42; 32]).unwrap();
 
-        let hash = B256::default();
-        let signature = Signature::sign(&signer, hash);
+        let hash = B256::ZERO;
+        let signature = signer.sign_prehash(hash);
         signature
             .verify(hash, B256::address_of(signer.verifying_key()))
             .unwrap();
 
-        assert!(signature.verify(hash, B256::default()).is_none());
+        assert!(signature.verify(hash, B256::ZERO).is_none());
+    }
+
+    #[test]
+    fn external_signer() {
+        let signer = MockSigner {
+            key: SigningKey::from_slice(&[42; 32]).unwrap(),
+            signed: Cell::new(0),
+        };
+        let tx = TransactionBuilder::new().amount(10).sign(&signer);
+        tx.verify().unwrap();
+        assert_eq!(tx.from, signer.address());
+
+        let data = BlockData {
+            prev_hash: B256::ZERO,
+            number: 1,
+            nonce: 0,
+            timestamp: 0,
+            transactions: vec![tx],
+        };
+        let block = Block::new(data, &signer);
+        block.verify().unwrap();
+        assert_eq!(block.proposer, signer.address());
+        assert_eq!(signer.signed.get(), 2);
+    }
+
+    #[test]
+    fn transaction_builder() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let data = TransactionData {
+            to: B256::hash_of("recipient"),
+            amount: 10,
+            fee: 2,
+            nonce: 7,
+            memo: vec![],
+        };
+        let manual = Transaction::new(data, &signer);
+
+        let built = TransactionBuilder::new()
+            .to(B256::hash_of("recipient"))
+            .amount(10)
+            .fee(2)
+            .nonce(7)
+            .sign(&signer);
+        assert_eq!(built.hash, manual.hash);
+        assert_eq!(built.from, manual.from);
+        built.verify().unwrap();
+
+        let other_nonce = TransactionBuilder::new()
+            .to(B256::hash_of("recipient"))
+            .amount(10)
+            .fee(2)
+            .sign(&signer);
+        assert_ne!(other_nonce.hash, manual.hash);
+    }
+
+    #[test]
+    fn recover_sender() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let mut tx = TransactionBuilder::new().amount(10).sign(&signer);
+        assert_eq!(tx.recover_sender(), Some(tx.from));
+
+        // The recovered sender doesn't depend on the claimed one.
+        let claimed = tx.from;
+        tx.from = B256::hash_of("someone else");
+        assert_eq!(tx.recover_sender(), Some(claimed));
+    }
+
+    #[test]
+    fn memo_size_limit() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let at_limit = TransactionBuilder::new()
+            .memo(vec![b'a'; MAX_MEMO_LEN])
+            .sign(&signer);
+        assert!(at_limit.verify().is_some());
+
+        let over_limit = TransactionBuilder::new()
+            .memo(vec![b'a'; MAX_MEMO_LEN + 1])
+            .sign(&signer);
+        assert!(over_limit.verify().is_none());
+    }
+
+    #[test]
+    fn memo_text() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let text = TransactionBuilder::new().memo("invoice 42").sign(&signer);
+        assert_eq!(text.data.memo_text(), Some("invoice 42"));
+        assert_ne!(text.hash, TransactionBuilder::new().sign(&signer).hash);
+
+        let binary = TransactionBuilder::new()
+            .memo(vec![0xff, 0xfe])
+            .sign(&signer);
+        assert!(binary.verify().is_some());
+        assert_eq!(binary.data.memo_text(), None);
+    }
+
+    #[test]
+    fn block_at_size_limit() {
+        assert!(block_with_txs(MAX_BLOCK_TXS).verify().is_some());
+    }
+
+    #[test]
+    fn block_over_size_limit() {
+        assert!(block_with_txs(MAX_BLOCK_TXS + 1).verify().is_none());
+    }
+
+    #[test]
+    fn block_over_byte_limit() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let transactions = (0..MAX_BLOCK_TXS as u64)
+            .map(|amount| {
+                TransactionBuilder::new()
+                    .amount(amount)
+                    .memo([0xff; MAX_MEMO_LEN])
+                    .sign(&signer)
+            })
+            .collect();
+        let data = BlockData {
+            prev_hash: B256::ZERO,
+            number: 1,
+            nonce: 0,
+            timestamp: 0,
+            transactions,
+        };
+        let block = Block::new(data, &signer);
+
+        assert!(block.serialized_size() > MAX_BLOCK_SIZE);
+        assert!(block.verify().is_none());
+    }
+
+    #[test]
+    fn serialized_size() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let tx = TransactionBuilder::new()
+            .amount(10)
+            .memo("note")
+            .sign(&signer);
+        assert_eq!(tx.serialized_size(), serde_json::to_vec(&tx).unwrap().len());
+
+        let block = block_with_txs(3);
+        assert_eq!(
+            block.serialized_size(),
+            serde_json::to_vec(&block).unwrap().len()
+        );
+    }
+
+    #[test]
+    fn block_with_duplicate_transactions() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let mut block = block_with_txs(2);
+        let duplicate = block.data.transactions[0].clone();
+        block.data.transactions.push(duplicate);
+        let block = Block::new(block.data, &signer);
+
+        assert!(block.verify().is_none());
+    }
+
+    #[test]
+    fn parse_hex_string() {
+        let s = "5e5c107330e6ab97ff2abc15b5c8ba14dd92709823e669b1ed4884e69a76fd60";
+        let parsed = B256::from_hex_string(s).unwrap();
+        assert_eq!(parsed.to_string(), s);
+    }
+
+    #[test]
+    fn parse_hex_string_errors() {
+        let valid = "5e5c107330e6ab97ff2abc15b5c8ba14dd92709823e669b1ed4884e69a76fd60";
+
+        let too_short = &valid[..62];
+        assert_eq!(
+            B256::from_hex_string(too_short),
+            Err(ParseError::InvalidLength(62))
+        );
+
+        let too_long = format!("{}00", valid);
+        assert_eq!(
+            B256::from_hex_string(&too_long),
+            Err(ParseError::InvalidLength(66))
+        );
+
+        let odd = &valid[..63];
+        assert_eq!(
+            B256::from_hex_string(odd),
+            Err(ParseError::InvalidLength(63))
+        );
+
+        let non_hex = format!("{}zz", &valid[..62]);
+        assert_eq!(
+            B256::from_hex_string(&non_hex),
+            Err(ParseError::InvalidCharacter('z', 62))
+        );
+    }
+
+    #[test]
+    fn mine_block() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let mut data = block_with_txs(1).data;
+        data.mine(8, signer.address());
+
+        let block = Block::new(data, &signer);
+        assert!(block.hash.leading_zeros() >= 8);
+        assert!(block.verify_difficulty(8).is_some());
+    }
+
+    #[test]
+    fn hash_commits_to_proposer() {
+        let block = block_with_txs(2);
+        let other = SigningKey::from_slice(&[43; 32]).unwrap();
+        let same_contents = Block::new(block.data.clone(), &other);
+        assert_ne!(block.hash, same_contents.hash);
+        assert!(same_contents.verify().is_some());
+
+        // Claiming another proposer changes the expected hash.
+        let mut stolen = same_contents;
+        stolen.proposer = block.proposer;
+        assert!(stolen.verify().is_none());
+    }
+
+    #[test]
+    fn public_key_address() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let public_key = PublicKey::from_key(signer.verifying_key());
+        assert_eq!(
+            public_key.address(),
+            Some(B256::address_of(signer.verifying_key()))
+        );
+
+        let json = serde_json::to_string(&public_key).unwrap();
+        assert_eq!(
+            serde_json::from_str::<PublicKey>(&json).unwrap(),
+            public_key
+        );
+
+        assert!(PublicKey(vec![1, 2, 3]).address().is_none());
+    }
+
+    #[test]
+    fn signed_node_info() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let info = NodeInfo {
+            name: "node".to_string(),
+            address: B256::address_of(signer.verifying_key()),
+            public_key: PublicKey::from_key(signer.verifying_key()),
+            socket: "127.0.0.1:50000".parse().unwrap(),
+            observer: false,
+        };
+        let signature = info.sign(&signer);
+        info.verify(&signature).unwrap();
+
+        let mut forged = info.clone();
+        forged.socket = "127.0.0.1:50001".parse().unwrap();
+        assert!(forged.verify(&signature).is_none());
+
+        let other = SigningKey::from_slice(&[43; 32]).unwrap();
+        assert!(info.verify(&info.sign(&other)).is_none());
+    }
+
+    #[test]
+    fn signed_snapshot() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let address = B256::address_of(signer.verifying_key());
+        let snapshot = StateSnapshot {
+            height: 3,
+            block_hash: B256::hash_of("block"),
+            balances: [(address, 900)].into(),
+        };
+        let signature = snapshot.sign(&signer);
+        snapshot.verify(&signature, address).unwrap();
+
+        let mut forged = snapshot.clone();
+        forged.balances.insert(address, 9000);
+        assert!(forged.verify(&signature, address).is_none());
+    }
+
+    #[test]
+    fn distance_symmetry() {
+        let a = B256::hash_of("a");
+        let b = B256::hash_of("b");
+        assert_eq!(a.distance(b), b.distance(a));
+        assert_eq!(a.xor_distance(b), b.xor_distance(a));
+        assert_eq!(a.distance(a), U256::ZERO);
+    }
+
+    #[test]
+    fn xor_distance() {
+        let a = B256::hash_of("a");
+        assert_eq!(a.xor_distance(a), U256::ZERO);
+        assert_eq!(a.xor_distance(B256::ZERO), U256::from_be_slice(&a.0));
+
+        let mut one = [0; 32];
+        one[31] = 1;
+        let mut three = [0; 32];
+        three[31] = 3;
+        assert_eq!(B256(one).xor_distance(B256(three)), U256::from_u8(2));
+    }
+
+    #[test]
+    fn signature_bytes() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let hash = B256::hash_of("data");
+        let signature = signer.sign_prehash(hash);
+
+        let bytes = signature.to_bytes();
+        assert_eq!(&bytes[..32], &signature.r.0);
+        assert_eq!(&bytes[32..64], &signature.s.0);
+        assert_eq!(bytes[64], signature.recovery_id);
+        assert_eq!(&bytes[..], &hex::decode(signature.to_hex()).unwrap()[..]);
+        let parsed = Signature::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, signature);
+        assert_eq!(parsed.recover(hash), Some(signer.address()));
+
+        let mut invalid = bytes;
+        invalid[64] = 27;
+        assert_eq!(Signature::from_bytes(&invalid), None);
+        assert_eq!(Signature::from_bytes(&[0; 65]), None);
+    }
+
+    #[test]
+    fn signature_hex() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let hash = B256::hash_of("data");
+        let signature = signer.sign_prehash(hash);
+
+        let hex = signature.to_hex();
+        assert_eq!(hex.len(), 130);
+        let parsed = Signature::from_hex(&hex).unwrap();
+        parsed
+            .verify(hash, B256::address_of(signer.verifying_key()))
+            .unwrap();
+
+        assert_eq!(
+            Signature::from_hex(&hex[..128]),
+            Err(ParseError::InvalidSignatureLength(128))
+        );
+        let mut invalid = hex.clone();
+        invalid.replace_range(100..101, "x");
+        assert_eq!(
+            Signature::from_hex(&invalid),
+            Err(ParseError::InvalidCharacter('x', 100))
+        );
+    }
+
+    #[test]
+    fn zero() {
+        assert!(B256::ZERO.is_zero());
+        assert_eq!(B256::ZERO, B256::default());
+        assert!(!B256::hash_of("data").is_zero());
+        assert!(!B256([1; 32]).is_zero());
+    }
+
+    #[test]
+    fn ct_eq_matches_eq() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..1000 {
+            let a = B256(rng.gen());
+            let mut b = a;
+            assert!(a.ct_eq(&b));
+
+            // Differing in a single byte, or possibly none.
+            b.0[rng.gen_range(0..32)] = rng.gen();
+            assert_eq!(a.ct_eq(&b), a == b);
+
+            let c = B256(rng.gen());
+            assert_eq!(a.ct_eq(&c), a == c);
+        }
+    }
+
+    #[test]
+    fn genesis_commits_to_config() {
+        let default = GenesisConfig::default();
+        assert_eq!(default.block().hash, Block::new_genesis().hash);
+
+        let address = B256::hash_of("validator");
+        let validators = GenesisConfig {
+            validators: BTreeSet::from([address]),
+            ..Default::default()
+        };
+        let allocations = GenesisConfig {
+            allocations: BTreeMap::from([(address, 100)]),
+            ..Default::default()
+        };
+        assert_ne!(validators.block().hash, default.block().hash);
+        assert_ne!(allocations.block().hash, default.block().hash);
+        assert_ne!(allocations.block().hash, validators.block().hash);
+        assert!(validators.block().verify().is_some());
+
+        assert!(validators.is_validator(&address));
+        assert!(!validators.is_validator(&B256::hash_of("other")));
+        assert!(default.is_validator(&B256::hash_of("other")));
+
+        let json = serde_json::to_string(&validators).unwrap();
+        assert_eq!(
+            serde_json::from_str::<GenesisConfig>(&json).unwrap(),
+            validators
+        );
+    }
+
+    #[test]
+    fn leading_zeros() {
+        let mut hash = B256::ZERO;
+        assert_eq!(hash.leading_zeros(), 256);
+
+        hash.0[1] = 0b0010_0000;
+        assert_eq!(hash.leading_zeros(), 10);
+    }
+
+    /// Hashes of a string, the genesis and a transaction with its sender.
+    fn hash_vectors() -> [String; 4] {
+        let tx = TransactionBuilder::new()
+            .amount(10)
+            .sign(&SigningKey::from_slice(&[42; 32]).unwrap());
+        [
+            B256::hash_of("abc"),
+            Block::new_genesis().hash,
+            tx.hash,
+            tx.from,
+        ]
+        .map(|hash| hash.to_string())
+    }
+
+    #[cfg(not(feature = "keccak"))]
+    #[test]
+    fn sha256_vectors() {
+        assert_eq!(
+            hash_vectors(),
+            [
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+                "7b8bc92ab3efe1ed227e3f62efbbb16f586fcd1305c88ddfe91aca2724216f01",
+                "12180dc959f4756fca1b5e4944657307cad3464fa0b0f66fa98624ed378dac5e",
+                "452016e333cfb8b405029c16a37d001c6d2524c3e600d4d126b69031c494cae2",
+            ]
+        );
+    }
+
+    #[cfg(feature = "keccak")]
+    #[test]
+    fn keccak256_vectors() {
+        assert_eq!(
+            hash_vectors(),
+            [
+                "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45",
+                "10e9709894857ee0f50c16a0b3dc001a7f7bc9eaa200fa6b7ebd20784c81054a",
+                "9cc63441139763f7fba23250eb093753ca345711407caff51a817c06b88c4eed",
+                "523430d358f7add4df6fb2cfc0475fd3082853e3b4eb068de6f09784c4abe6c8",
+            ]
+        );
+    }
+
+    #[test]
+    fn b256_serde_roundtrip() {
+        let hash = B256::hash_of("data");
+
+        let json = serde_json::to_string(&hash).unwrap();
+        assert_eq!(json, format!("\"{}\"", hash));
+        assert_eq!(serde_json::from_str::<B256>(&json).unwrap(), hash);
+
+        assert!(serde_json::from_str::<B256>("\"00\"").is_err());
+    }
+
+    #[test]
+    fn transaction_json_uses_hex() {
+        let block = block_with_txs(1);
+        let tx = &block.data.transactions[0];
+
+        let tx_json = serde_json::to_string(tx).unwrap();
+        assert!(tx_json.contains(&format!("\"{}\"", tx.hash)));
+
+        // Each hash used to be an array of 32 numbers.
+        let array_len = serde_json::to_string(&tx.hash.0).unwrap().len();
+        let hex_len = serde_json::to_string(&tx.hash).unwrap().len();
+        assert!(hex_len < array_len);
+        assert!(serde_json::to_string(&block).unwrap().len() < 1536);
+    }
+
+    #[test]
+    fn coinbase_verification() {
+        let signer = SigningKey::from_slice(&[42; 32]).unwrap();
+        let proposer = B256::address_of(signer.verifying_key());
+        let block_with = |transactions: Vec<Transaction>| {
+            let data = BlockData {
+                prev_hash: B256::ZERO,
+                number: 1,
+                nonce: 0,
+                timestamp: 0,
+                transactions,
+            };
+            Block::new(data, &signer)
+        };
+
+        let coinbase = Transaction::coinbase(proposer, 50, 1);
+        assert!(coinbase.verify().is_none());
+        assert!(block_with(vec![]).verify_coinbase(50).is_some());
+        assert!(block_with(vec![coinbase.clone()])
+            .verify_coinbase(50)
+            .is_some());
+
+        // Wrong amount.
+        assert!(block_with(vec![coinbase.clone()])
+            .verify_coinbase(100)
+            .is_none());
+
+        // Two coinbases.
+        let other = Transaction::coinbase(proposer, 50, 2);
+        assert!(block_with(vec![coinbase.clone(), other])
+            .verify_coinbase(50)
+            .is_none());
+
+        // Reward to someone else.
+        let foreign = Transaction::coinbase(B256::hash_of("someone"), 50, 1);
+        assert!(block_with(vec![foreign]).verify_coinbase(50).is_none());
     }
 }
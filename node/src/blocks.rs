@@ -0,0 +1,1572 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::Range;
+
+use k256::sha2::Digest;
+use k256::U256;
+use ledger_types::{
+    Block, GenesisConfig, Hasher, StateSnapshot, Transaction, B256, COINBASE, GENESIS_PREV_HASH,
+    MAX_BLOCK_SIZE, MAX_BLOCK_TXS,
+};
+
+/// Balance of an account before any transactions.
+const INITIAL_BALANCE: u64 = 1000;
+
+/// Consensus rules blocks must follow.
+#[derive(Debug, Clone, Default)]
+pub struct ChainConfig {
+    /// Required number of leading zero bits in block hashes.
+    pub difficulty: u32,
+    /// Amount minted to the proposer of each block by its coinbase transaction.
+    pub block_reward: u64,
+    /// Number of the latest blocks that can still be replaced by a reorg.
+    /// Older blocks are final. `None` allows reorgs of any depth.
+    pub finality_depth: Option<u64>,
+    /// Addresses allowed to propose blocks in the permissioned mode.
+    /// `None` lets anyone propose.
+    pub validators: Option<HashSet<B256>>,
+    /// Metric of closeness between proposers and blocks.
+    pub distance_metric: DistanceMetric,
+    /// Percentage by which a competing block's proposer must be closer than
+    /// the current one's to replace it, so near-tied blocks arriving in turns
+    /// don't keep reorging the tip. Zero reorgs to any better block.
+    ///
+    /// With a margin the fork choice depends on the arrival order: nodes
+    /// which received near-tied blocks in different orders keep different
+    /// tips, and the chain only converges if all nodes use the same margin
+    /// and receive the blocks in the same order. A margin trades this
+    /// guarantee for stability.
+    pub reorg_margin: u8,
+    /// Initial state the chain starts from. Its validators may propose
+    /// blocks, besides being `validators` in the permissioned mode.
+    pub genesis: GenesisConfig,
+}
+
+/// Metric deciding which address is the closest to a block hash, used to
+/// elect leaders and resolve forks. All nodes must use the same metric.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// Absolute difference of the numbers, see `B256::distance`.
+    #[default]
+    Numeric,
+    /// Bitwise XOR as in Kademlia, see `B256::xor_distance`.
+    Xor,
+}
+
+impl DistanceMetric {
+    pub fn distance(self, a: B256, b: B256) -> U256 {
+        match self {
+            Self::Numeric => a.distance(b),
+            Self::Xor => a.xor_distance(b),
+        }
+    }
+}
+
+impl ChainConfig {
+    /// Checks if the address may propose blocks.
+    pub fn is_validator(&self, address: &B256) -> bool {
+        self.genesis.is_validator(address)
+            && self
+                .validators
+                .as_ref()
+                .is_none_or(|validators| validators.contains(address))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Blocks {
+    hashes: Vec<B256>,
+    data: HashMap<B256, Block>,
+    /// Number of the block each transaction of the chain is mined in.
+    tx_index: HashMap<B256, u64>,
+    config: ChainConfig,
+    /// State the chain starts from instead of the genesis.
+    base: Option<StateSnapshot>,
+    /// State right after the last block whose body is pruned. Only the hashes
+    /// of the pruned blocks are kept.
+    pruned: Option<StateSnapshot>,
+    /// Balances at the tip of the accounts touched by the kept bodies, kept
+    /// up to date as blocks are appended so the tip state isn't replayed.
+    balances: BTreeMap<B256, u64>,
+    reorg_handler: ReorgHandler,
+}
+impl Blocks {
+    /// Creates an empty chain following the rules.
+    pub fn new(config: ChainConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a chain starting right after the snapshot. Only the blocks
+    /// following it are kept and can be appended.
+    pub fn from_snapshot(snapshot: StateSnapshot, config: ChainConfig) -> Self {
+        Self {
+            config,
+            base: Some(snapshot),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the function called on every reorg, replacing the previous one.
+    pub fn set_reorg_handler(&mut self, handler: impl FnMut(&Reorg) + 'static) {
+        self.reorg_handler = ReorgHandler(Some(Box::new(handler)));
+    }
+
+    pub fn append(&mut self, block: Block) -> BlockAppendResult {
+        if block.data.transactions.len() > MAX_BLOCK_TXS
+            || block.serialized_size() > MAX_BLOCK_SIZE
+            || block.data.has_duplicate_txs()
+        {
+            return BlockAppendResult::None;
+        }
+
+        let new_block_number = block.data.number;
+        if self.is_empty() && new_block_number == 0 {
+            // The genesis commits to the configured initial state.
+            if block.data.prev_hash != GENESIS_PREV_HASH
+                || block.hash != self.config.genesis.block().hash
+            {
+                return BlockAppendResult::None;
+            }
+            if self.append_unchecked(block).is_err() {
+                return BlockAppendResult::None;
+            }
+            return BlockAppendResult::Added;
+        }
+
+        if new_block_number == 0
+            || !self.config.is_validator(&block.proposer)
+            || block.verify_difficulty(self.config.difficulty).is_none()
+            || block.verify_coinbase(self.config.block_reward).is_none()
+        {
+            return BlockAppendResult::None;
+        }
+
+        // The number indexes the chain, so it must fit the address space.
+        if usize::try_from(new_block_number).is_err() {
+            return BlockAppendResult::None;
+        }
+
+        let next_block_number = self.len();
+        if new_block_number <= next_block_number && self.mines_known_tx(&block, new_block_number) {
+            return BlockAppendResult::None;
+        }
+
+        match new_block_number.cmp(&next_block_number) {
+            Ordering::Equal => {
+                let prev_block_hash = self.hash_by_number(new_block_number - 1);
+                if Some(block.data.prev_hash) != prev_block_hash {
+                    return BlockAppendResult::None;
+                }
+
+                if self.append_unchecked(block).is_err() {
+                    return BlockAppendResult::None;
+                }
+                BlockAppendResult::Added
+            }
+            Ordering::Greater => BlockAppendResult::NeedSync(next_block_number),
+            Ordering::Less => {
+                if new_block_number < self.final_len() {
+                    return BlockAppendResult::None;
+                }
+
+                // Only a sibling of the current block can replace it.
+                if self.hash_by_number(new_block_number - 1) != Some(block.data.prev_hash) {
+                    return BlockAppendResult::None;
+                }
+                let Ok(current_block) = self.data_by_number(new_block_number) else {
+                    return BlockAppendResult::None;
+                };
+
+                if self.wins_fork(&block, current_block) {
+                    let reverted = new_block_number..self.len();
+                    self.truncate(new_block_number);
+                    if self.append_unchecked(block.clone()).is_err() {
+                        return BlockAppendResult::None;
+                    }
+                    if let Some(handler) = &mut self.reorg_handler.0 {
+                        handler(&Reorg {
+                            reverted,
+                            applied: vec![block],
+                        });
+                    }
+                    return BlockAppendResult::NeedSync(new_block_number + 1);
+                }
+
+                BlockAppendResult::None
+            }
+        }
+    }
+
+    /// Checks if the block should replace its sibling. The block whose
+    /// proposer is closer to the parent hash wins. Equal distances go to the
+    /// lower proposer address, and then to the lower block hash, so all nodes
+    /// pick the same block regardless of the order they receive them in.
+    /// With a `reorg_margin` the block must be closer by more than it, and
+    /// the current block wins the ties.
+    fn wins_fork(&self, block: &Block, current: &Block) -> bool {
+        let metric = self.config.distance_metric;
+        let parent = block.data.prev_hash;
+        let new_distance = metric.distance(block.proposer, parent);
+        let current_distance = metric.distance(current.proposer, parent);
+        let margin = self.config.reorg_margin.min(100);
+        if margin > 0 {
+            let margin = current_distance
+                .wrapping_div(&U256::from_u8(100))
+                .wrapping_mul(&U256::from_u8(margin));
+            return new_distance < current_distance.wrapping_sub(&margin);
+        }
+        (new_distance, block.proposer, block.hash)
+            < (current_distance, current.proposer, current.hash)
+    }
+
+    /// Number of blocks in the chain.
+    pub fn len(&self) -> u64 {
+        self.first_number() + self.hashes.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of the first block kept by the chain.
+    fn first_number(&self) -> u64 {
+        self.base
+            .as_ref()
+            .map_or(0, |base| base.height.saturating_add(1))
+    }
+
+    /// Latest state the balances are computed from: the one after the pruned
+    /// blocks, or the snapshot the chain starts from.
+    fn base_state(&self) -> Option<&StateSnapshot> {
+        self.pruned.as_ref().or(self.base.as_ref())
+    }
+
+    /// Number of the first block which isn't pruned.
+    fn first_body(&self) -> u64 {
+        self.base_state()
+            .map_or(0, |state| state.height.saturating_add(1))
+    }
+
+    /// Blocks from the number up to the tip, skipped if the number is pruned.
+    fn bodies_from(&self, number: u64) -> impl Iterator<Item = &Block> {
+        let skip = number.saturating_sub(self.first_number());
+        let skip = usize::try_from(skip).unwrap_or(usize::MAX);
+        self.hashes.iter().skip(skip).map(|hash| &self.data[hash])
+    }
+
+    /// Hash of the block with the number, including the snapshot block.
+    fn hash_by_number(&self, number: u64) -> Option<B256> {
+        match &self.base {
+            Some(base) if base.height == number => Some(base.block_hash),
+            _ => {
+                let index = usize::try_from(number.checked_sub(self.first_number())?).ok()?;
+                self.hashes.get(index).copied()
+            }
+        }
+    }
+
+    pub fn get(&self, hash: &B256) -> Option<&Block> {
+        self.data.get(hash)
+    }
+
+    /// Serializes the ordered list of blocks, omitting the pruned ones and the
+    /// ones before the snapshot the chain starts from.
+    pub fn export_json(&self) -> String {
+        let blocks: Vec<&Block> = self.bodies_from(self.first_body()).collect();
+        serde_json::to_string(&blocks).expect("blocks should be serialized")
+    }
+
+    /// Builds a chain from the exported blocks, validating each of them.
+    /// Fails if any block can't be appended or overspends.
+    pub fn import_json(json: &str, config: ChainConfig) -> Result<Self, ImportError> {
+        let blocks: Vec<Block> = serde_json::from_str(json).map_err(ImportError::Json)?;
+        let chain = Self::import_blocks(blocks, config)?;
+        chain.verify_chain().map_err(ImportError::Chain)?;
+        Ok(chain)
+    }
+
+    /// Appends the blocks to a new chain, validating each of them, but not
+    /// the integrity of the result.
+    fn import_blocks(blocks: Vec<Block>, config: ChainConfig) -> Result<Self, ImportError> {
+        let mut chain = Self::new(config);
+        for block in blocks {
+            let number = block.data.number;
+            let txs_valid = block
+                .data
+                .transactions
+                .iter()
+                .all(|tx| tx.is_coinbase() || tx.verify().is_some());
+            if block.verify().is_none() || !txs_valid || number != chain.len() {
+                return Err(ImportError::InvalidBlock(number));
+            }
+            if chain.overspends(&block) {
+                return Err(ImportError::Overspend(number));
+            }
+            if !matches!(chain.append(block), BlockAppendResult::Added) {
+                return Err(ImportError::InvalidBlock(number));
+            }
+        }
+        Ok(chain)
+    }
+
+    /// Whether a transaction of the block spends more than its sender has
+    /// at that point, applying the block in the order of `apply_block`.
+    fn overspends(&self, block: &Block) -> bool {
+        let mut balances = BTreeMap::new();
+        for tx in &block.data.transactions {
+            for (address, credit) in [(tx.data.to, tx.data.amount), (block.proposer, tx.data.fee)] {
+                *balances
+                    .entry(address)
+                    .or_insert_with(|| self.balance_of(address)) += credit;
+            }
+            let from = balances
+                .entry(tx.from)
+                .or_insert_with(|| self.balance_of(tx.from));
+            let spent = tx.data.amount.saturating_add(tx.data.fee);
+            if !tx.is_coinbase() {
+                if *from < spent {
+                    return true;
+                }
+                *from -= spent;
+            }
+        }
+        false
+    }
+
+    /// Checks exported blocks offline, first for the integrity of the chain,
+    /// reporting the first inconsistent block, and then whether they import,
+    /// e.g. that no block overspends. Returns the imported chain.
+    pub fn verify_json(json: &str, config: ChainConfig) -> Result<Self, ImportError> {
+        let blocks: Vec<Block> = serde_json::from_str(json).map_err(ImportError::Json)?;
+
+        let mut unchecked = Self::new(config.clone());
+        for block in blocks.iter().cloned() {
+            // Checked up front, as `append_unchecked` treats it as a bug.
+            let number = unchecked.len();
+            if block.data.number != number {
+                return Err(ImportError::Chain(ChainError::WrongNumber(number)));
+            }
+            unchecked
+                .append_unchecked(block)
+                .map_err(ImportError::Chain)?;
+        }
+        unchecked.verify_chain().map_err(ImportError::Chain)?;
+        Self::import_blocks(blocks, config)
+    }
+
+    /// Checks that the blocks are linked and numbered sequentially, and that
+    /// all their signatures are valid. Reports the first inconsistent block.
+    /// Pruned blocks aren't checked.
+    pub fn verify_chain(&self) -> Result<(), ChainError> {
+        let mut prev_hash = self
+            .base_state()
+            .map_or(GENESIS_PREV_HASH, |state| state.block_hash);
+        let first_body = self.first_body();
+        for (number, block) in (first_body..).zip(self.bodies_from(first_body)) {
+            let hash = &block.hash;
+            if block.data.number != number {
+                return Err(ChainError::WrongNumber(number));
+            }
+            if block.data.prev_hash != prev_hash {
+                return Err(ChainError::BrokenLink(number));
+            }
+            if block.hash != *hash || block.verify().is_none() {
+                return Err(ChainError::InvalidBlock(number));
+            }
+            let txs_valid = block
+                .data
+                .transactions
+                .iter()
+                .all(|tx| tx.is_coinbase() || tx.verify().is_some());
+            if !txs_valid {
+                return Err(ChainError::InvalidTransaction(number));
+            }
+            prev_hash = block.hash;
+        }
+        Ok(())
+    }
+
+    /// Hash of the last block, or the genesis `prev_hash` if the chain is empty.
+    pub fn last_hash(&self) -> B256 {
+        match (self.hashes.last(), &self.base) {
+            (Some(hash), _) => *hash,
+            (None, Some(base)) => base.block_hash,
+            (None, None) => GENESIS_PREV_HASH,
+        }
+    }
+
+    /// Hash and number of the last block, if the chain has any.
+    pub fn tip(&self) -> Option<(B256, u64)> {
+        let height = self.len().checked_sub(1)?;
+        Some((self.last_hash(), height))
+    }
+
+    /// Appends the block without validation, except that its number must
+    /// be the next one. A mismatch is a bug, so it panics in debug builds.
+    pub fn append_unchecked(&mut self, block: Block) -> Result<(), ChainError> {
+        let number = self.len();
+        debug_assert_eq!(block.data.number, number, "block number out of sync");
+        if block.data.number != number {
+            return Err(ChainError::WrongNumber(block.data.number));
+        }
+        for tx in &block.data.transactions {
+            self.tx_index.insert(tx.hash, number);
+        }
+        let base = self.pruned.as_ref().or(self.base.as_ref());
+        apply_block(&mut self.balances, base, &block);
+        self.hashes.push(block.hash);
+        self.data.insert(block.hash, block);
+        Ok(())
+    }
+
+    /// Drops the blocks starting from the number off the chain.
+    fn truncate(&mut self, len: u64) {
+        let Ok(len) = usize::try_from(len.saturating_sub(self.first_number())) else {
+            return;
+        };
+        for hash in self.hashes.drain(len.min(self.hashes.len())..) {
+            for tx in &self.data[&hash].data.transactions {
+                self.tx_index.remove(&tx.hash);
+            }
+        }
+        self.balances = self.replay(self.len());
+    }
+
+    /// Reverts the chain so the block `height` becomes the tip, even if
+    /// the later blocks are final. Their transactions are unmined, and the
+    /// balances are as if the chain was built only up to the height. Returns
+    /// the numbers of the reverted blocks, or `None` if the block at the
+    /// height isn't kept in full.
+    pub fn rollback_to(&mut self, height: u64) -> Option<Range<u64>> {
+        self.data_by_number(height).ok()?;
+        let reverted = height + 1..self.len();
+        let start = usize::try_from(height + 1 - self.first_number()).ok()?;
+        for hash in self.hashes.drain(start..) {
+            if let Some(block) = self.data.remove(&hash) {
+                for tx in &block.data.transactions {
+                    self.tx_index.remove(&tx.hash);
+                }
+            }
+        }
+        self.balances = self.replay(self.len());
+        Some(reverted)
+    }
+
+    pub fn data_by_number(&self, number: u64) -> Result<&Block, MissingBlock> {
+        let index = number
+            .checked_sub(self.first_number())
+            .and_then(|index| usize::try_from(index).ok())
+            .ok_or(MissingBlock::Unknown)?;
+        let hash = self.hashes.get(index).ok_or(MissingBlock::Unknown)?;
+        self.data.get(hash).ok_or(MissingBlock::Pruned(*hash))
+    }
+
+    /// Drops the bodies of the final blocks except the latest `keep` blocks,
+    /// remembering the balances right after them. The last final block is
+    /// always kept in full as the parent of the blocks which may be reorged.
+    pub fn prune(&mut self, keep: u64) {
+        let prune_len = self
+            .len()
+            .saturating_sub(keep)
+            .min(self.final_len().saturating_sub(1));
+        let first_body = self.first_body();
+        if prune_len <= first_body {
+            return;
+        }
+        let Some(state) = self.snapshot_at(prune_len - 1) else {
+            return;
+        };
+
+        for number in first_body..prune_len {
+            if let Ok(block) = self.data_by_number(number) {
+                let hash = block.hash;
+                self.data.remove(&hash);
+            }
+        }
+        self.pruned = Some(state);
+    }
+
+    /// Puts back the body of a pruned block, e.g. fetched from a peer, so it
+    /// can be served again. Returns whether it matches the kept hash.
+    pub fn restore(&mut self, block: Block) -> bool {
+        let Err(MissingBlock::Pruned(hash)) = self.data_by_number(block.data.number) else {
+            return false;
+        };
+        if block.hash != hash || block.verify().is_none() {
+            return false;
+        }
+        self.data.insert(hash, block);
+        true
+    }
+
+    /// Number of the leading blocks that can't be reorged anymore.
+    pub fn final_len(&self) -> u64 {
+        match self.config.finality_depth {
+            Some(depth) => self.len().saturating_sub(depth),
+            None => 0,
+        }
+        .max(self.first_number())
+    }
+
+    /// Balance of the address at the tip, including provisional blocks.
+    pub fn balance_of(&self, address: B256) -> u64 {
+        match self.balances.get(&address) {
+            Some(balance) => *balance,
+            None => base_balance(self.base_state(), &address),
+        }
+    }
+
+    /// Balance of the address according to the final blocks only.
+    pub fn final_balance_of(&self, address: B256) -> u64 {
+        self.balance_in(address, self.final_len())
+    }
+
+    /// Balance of the address right after the block `height`. Heights beyond
+    /// the tip give the balance at the tip. Fails for heights whose state is
+    /// pruned or precedes the snapshot the chain starts from.
+    pub fn balance_of_at(&self, address: B256, height: u64) -> Result<u64, MissingBlock> {
+        let len = height.saturating_add(1).min(self.len());
+        self.state_kept(len)?;
+        Ok(self.balance_in(address, len))
+    }
+
+    /// Final balance of the address right after the block `height`.
+    pub fn final_balance_of_at(&self, address: B256, height: u64) -> Result<u64, MissingBlock> {
+        let len = height.saturating_add(1).min(self.final_len());
+        self.state_kept(len)?;
+        Ok(self.balance_in(address, len))
+    }
+
+    /// Checks that the state after the first `len` blocks is known, reporting
+    /// the last of the blocks otherwise.
+    fn state_kept(&self, len: u64) -> Result<(), MissingBlock> {
+        if len >= self.first_body() {
+            return Ok(());
+        }
+        match len
+            .checked_sub(1)
+            .and_then(|number| self.hash_by_number(number))
+        {
+            Some(hash) => Err(MissingBlock::Pruned(hash)),
+            None => Err(MissingBlock::Unknown),
+        }
+    }
+
+    /// State right after the block `height`, if the chain has it and the
+    /// block isn't pruned.
+    pub fn snapshot_at(&self, height: u64) -> Option<StateSnapshot> {
+        if height >= self.len() {
+            return None;
+        }
+        let base_state = self.base_state();
+        if let Some(state) = base_state.filter(|state| state.height == height) {
+            return Some(state.clone());
+        }
+        if height < self.first_body() {
+            return None;
+        }
+        let block_hash = self.data_by_number(height).ok()?.hash;
+
+        let mut balances = base_state
+            .map(|state| state.balances.clone())
+            .unwrap_or_default();
+        // Only the states before the tip are replayed.
+        if height + 1 == self.len() {
+            balances.extend(&self.balances);
+        } else {
+            balances.extend(self.replay(height + 1));
+        }
+        Some(StateSnapshot {
+            height,
+            block_hash,
+            balances,
+        })
+    }
+
+    /// Commitment to the balances right after the block with the number,
+    /// comparable between nodes. `None` if the block is unknown or pruned.
+    pub fn state_root_at(&self, height: u64) -> Option<B256> {
+        let snapshot = self.snapshot_at(height)?;
+        Some(state_root(&snapshot.balances))
+    }
+
+    /// Accounts with a nonzero balance at the tip, sorted by address. Only
+    /// the accounts touched by the chain are listed, as untouched ones merely
+    /// have the initial balance.
+    pub fn accounts(&self) -> Vec<(B256, u64)> {
+        let tip = self.len().checked_sub(1);
+        let Some(snapshot) = tip.and_then(|tip| self.snapshot_at(tip)) else {
+            return Vec::new();
+        };
+        snapshot
+            .balances
+            .into_iter()
+            .filter(|&(address, balance)| address != COINBASE && balance > 0)
+            .collect()
+    }
+
+    /// Commitment to the balances at the tip, see `state_root_at`.
+    pub fn state_root(&self) -> B256 {
+        self.len()
+            .checked_sub(1)
+            .and_then(|tip| self.state_root_at(tip))
+            .unwrap_or_else(|| state_root(&BTreeMap::new()))
+    }
+
+    /// Balances of the accounts touched by the kept bodies among the first
+    /// `len` blocks.
+    fn replay(&self, len: u64) -> BTreeMap<B256, u64> {
+        let mut balances = BTreeMap::new();
+        let first_body = self.first_body();
+        let count = usize::try_from(len.saturating_sub(first_body)).unwrap_or(usize::MAX);
+        for block in self.bodies_from(first_body).take(count) {
+            apply_block(&mut balances, self.base_state(), block);
+        }
+        balances
+    }
+
+    /// Balance of the address according to the first `len` blocks. Lengths
+    /// within the snapshot or the pruned blocks give the balance right after them.
+    fn balance_in(&self, address: B256, len: u64) -> u64 {
+        let mut balance = base_balance(self.base_state(), &address);
+        let first_body = self.first_body();
+        let count = usize::try_from(len.saturating_sub(first_body)).unwrap_or(usize::MAX);
+        for block in self.bodies_from(first_body).take(count) {
+            for transaction in &block.data.transactions {
+                if transaction.data.to == address {
+                    balance += transaction.data.amount;
+                }
+                if block.proposer == address {
+                    balance += transaction.data.fee;
+                }
+                if transaction.from == address && !transaction.is_coinbase() {
+                    let spent = transaction.data.amount.saturating_add(transaction.data.fee);
+                    balance = balance.saturating_sub(spent);
+                }
+            }
+        }
+        balance
+    }
+
+    /// Checks whether the block carries a transaction already mined in one of
+    /// the blocks preceding the `number`.
+    fn mines_known_tx(&self, block: &Block, number: u64) -> bool {
+        block.data.transactions.iter().any(|tx| {
+            self.tx_index
+                .get(&tx.hash)
+                .is_some_and(|mined_in| *mined_in < number)
+        })
+    }
+
+    pub fn contains_tx(&self, hash: B256) -> bool {
+        self.tx_index.contains_key(&hash)
+    }
+
+    /// Finds the mined transaction along with the number of its block.
+    pub fn find_transaction(&self, tx_hash: B256) -> Option<(Transaction, u64)> {
+        let number = *self.tx_index.get(&tx_hash)?;
+        let tx = self
+            .data_by_number(number)
+            .ok()?
+            .data
+            .transactions
+            .iter()
+            .find(|tx| tx.hash == tx_hash)?;
+        Some((tx.clone(), number))
+    }
+}
+
+/// Blocks replaced by a reorg, so indexes built from the chain can roll back
+/// and reapply them.
+#[derive(Debug)]
+pub struct Reorg {
+    /// Numbers of the blocks removed from the chain.
+    pub reverted: Range<u64>,
+    /// Blocks appended in their place, starting from `reverted.start`.
+    pub applied: Vec<Block>,
+}
+
+type OnReorg = Box<dyn FnMut(&Reorg)>;
+
+#[derive(Default)]
+struct ReorgHandler(Option<OnReorg>);
+
+impl std::fmt::Debug for ReorgHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = if self.0.is_some() { "set" } else { "unset" };
+        f.debug_tuple("ReorgHandler").field(&state).finish()
+    }
+}
+
+/// Hashes the `(address, balance)` pairs in the order of addresses, so equal
+/// balances give equal roots however they were collected.
+fn state_root(balances: &BTreeMap<B256, u64>) -> B256 {
+    let mut hasher = Hasher::new();
+    for (address, balance) in balances {
+        hasher.update(address.0);
+        hasher.update(balance.to_be_bytes());
+    }
+    B256(hasher.finalize().into())
+}
+
+/// Balance of the address in the state the kept bodies start from.
+fn base_balance(base: Option<&StateSnapshot>, address: &B256) -> u64 {
+    base.and_then(|state| state.balances.get(address).copied())
+        .unwrap_or(INITIAL_BALANCE)
+}
+
+/// Applies the block to the balances of the accounts it touches, the same
+/// way `Blocks::balance_in` does. Untouched accounts start from the base.
+fn apply_block(balances: &mut BTreeMap<B256, u64>, base: Option<&StateSnapshot>, block: &Block) {
+    fn balance<'a>(
+        balances: &'a mut BTreeMap<B256, u64>,
+        base: Option<&StateSnapshot>,
+        address: B256,
+    ) -> &'a mut u64 {
+        balances
+            .entry(address)
+            .or_insert_with(|| base_balance(base, &address))
+    }
+
+    balance(balances, base, block.proposer);
+    for tx in &block.data.transactions {
+        *balance(balances, base, tx.data.to) += tx.data.amount;
+        *balance(balances, base, block.proposer) += tx.data.fee;
+        let from = balance(balances, base, tx.from);
+        if !tx.is_coinbase() {
+            *from = from.saturating_sub(tx.data.amount.saturating_add(tx.data.fee));
+        }
+    }
+}
+
+/// Why a block with the number can't be returned.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MissingBlock {
+    /// The chain has no block with the number.
+    Unknown,
+    /// The body of the block with the hash is pruned.
+    Pruned(B256),
+}
+
+#[derive(Debug)]
+pub enum BlockAppendResult {
+    NeedSync(u64),
+    Added,
+    None,
+}
+
+/// Error of importing a chain.
+#[derive(Debug)]
+pub enum ImportError {
+    /// The input isn't a JSON list of blocks.
+    Json(serde_json::Error),
+    /// The block with the number can't be appended to the chain.
+    InvalidBlock(u64),
+    /// A transaction of the block with the number spends more than its
+    /// sender has.
+    Overspend(u64),
+    /// The imported chain is inconsistent.
+    Chain(ChainError),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "invalid chain JSON: {}", e),
+            Self::InvalidBlock(number) => write!(f, "invalid block {}", number),
+            Self::Overspend(number) => write!(f, "block {} overspends a balance", number),
+            Self::Chain(e) => write!(f, "inconsistent chain: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+/// Inconsistency of a chain, with the number of the offending block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainError {
+    /// The block number doesn't match its position.
+    WrongNumber(u64),
+    /// `prev_hash` doesn't point to the previous block.
+    BrokenLink(u64),
+    /// The block hash or signature is invalid.
+    InvalidBlock(u64),
+    /// A transaction hash or signature is invalid.
+    InvalidTransaction(u64),
+}
+
+impl std::fmt::Display for ChainError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::WrongNumber(number) => write!(f, "block {} has a wrong number", number),
+            Self::BrokenLink(number) => write!(f, "block {} isn't linked to the previous", number),
+            Self::InvalidBlock(number) => write!(f, "block {} has an invalid signature", number),
+            Self::InvalidTransaction(number) => {
+                write!(f, "block {} has an invalid transaction", number)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ChainError {}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::{BTreeMap, BTreeSet, HashSet};
+    use std::rc::Rc;
+
+    use k256::ecdsa::SigningKey;
+    use k256::U256;
+    use ledger_types::{
+        Block, BlockData, GenesisConfig, Transaction, TransactionBuilder, B256, GENESIS_PREV_HASH,
+    };
+
+    use crate::blocks::{
+        BlockAppendResult, Blocks, ChainConfig, ChainError, ImportError, MissingBlock,
+    };
+
+    pub fn signer(key: u8) -> SigningKey {
+        SigningKey::from_slice(&[key; 32]).unwrap()
+    }
+
+    /// Builds the next block of the chain with a transfer from the `key` owner.
+    pub fn next_block(blocks: &Blocks, key: u8, amount: u64) -> Block {
+        let tx = TransactionBuilder::new().amount(amount).sign(&signer(key));
+        let data = BlockData {
+            prev_hash: blocks.last_hash(),
+            number: blocks.len(),
+            nonce: 0,
+            timestamp: 0,
+            transactions: vec![tx],
+        };
+        Block::new(data, &signer(key))
+    }
+
+    /// Builds a chain of genesis and `len` more blocks.
+    pub fn chain(len: u64) -> Blocks {
+        let mut blocks = Blocks::default();
+        blocks.append(Block::new_genesis());
+        for i in 0..len {
+            let block = next_block(&blocks, 1, i + 1);
+            assert!(matches!(blocks.append(block), BlockAppendResult::Added));
+        }
+        blocks
+    }
+
+    #[test]
+    fn export_and_import() {
+        let blocks = chain(3);
+        let imported = Blocks::import_json(&blocks.export_json(), ChainConfig::default()).unwrap();
+
+        assert_eq!(imported.len(), 4);
+        assert_eq!(imported.last_hash(), blocks.last_hash());
+        assert_eq!(
+            imported.balance_of(B256::address_of(signer(1).verifying_key())),
+            994
+        );
+    }
+
+    #[test]
+    fn import_invalid_block() {
+        let blocks = chain(3);
+        let mut exported: Vec<Block> = serde_json::from_str(&blocks.export_json()).unwrap();
+        exported[2].data.transactions[0].data.amount = 500;
+        let json = serde_json::to_string(&exported).unwrap();
+
+        let imported = Blocks::import_json(&json, ChainConfig::default());
+        assert!(matches!(imported, Err(ImportError::InvalidBlock(2))));
+    }
+
+    #[test]
+    fn verify_exported_json() {
+        let blocks = chain(3);
+        let json = blocks.export_json();
+        let verified = Blocks::verify_json(&json, ChainConfig::default()).unwrap();
+        assert_eq!(verified.tip(), blocks.tip());
+
+        let exported: Vec<Block> = serde_json::from_str(&json).unwrap();
+        let tampered = |tamper: fn(&mut Vec<Block>)| {
+            let mut blocks = exported.clone();
+            tamper(&mut blocks);
+            let json = serde_json::to_string(&blocks).unwrap();
+            match Blocks::verify_json(&json, ChainConfig::default()) {
+                Err(ImportError::Chain(e)) => e,
+                _ => panic!("tampered chain is verified"),
+            }
+        };
+        assert_eq!(
+            tampered(|blocks| blocks[2].data.transactions[0].data.amount = 500),
+            ChainError::InvalidTransaction(2)
+        );
+        assert_eq!(
+            tampered(|blocks| blocks[3].data.prev_hash = B256::ZERO),
+            ChainError::BrokenLink(3)
+        );
+        assert_eq!(
+            tampered(|blocks| {
+                blocks.remove(1);
+            }),
+            ChainError::WrongNumber(1)
+        );
+        assert!(matches!(
+            Blocks::verify_json("not a chain", ChainConfig::default()),
+            Err(ImportError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn duplicate_transactions_in_block() {
+        let mut blocks = chain(1);
+        let mut block = next_block(&blocks, 1, 10);
+        let duplicate = block.data.transactions[0].clone();
+        block.data.transactions.push(duplicate);
+        let block = Block::new(block.data, &signer(1));
+
+        assert!(matches!(blocks.append(block), BlockAppendResult::None));
+        assert_eq!(blocks.len(), 2);
+    }
+
+    #[test]
+    fn transaction_mined_twice() {
+        let mut blocks = chain(1);
+        let mined = blocks.data_by_number(1).unwrap().data.transactions[0].clone();
+        let data = BlockData {
+            prev_hash: blocks.last_hash(),
+            number: blocks.len(),
+            nonce: 0,
+            timestamp: 0,
+            transactions: vec![mined],
+        };
+        let block = Block::new(data, &signer(1));
+
+        assert!(matches!(blocks.append(block), BlockAppendResult::None));
+        assert_eq!(blocks.len(), 2);
+    }
+
+    #[test]
+    fn historical_balance() {
+        let blocks = chain(3);
+        let sender = B256::address_of(signer(1).verifying_key());
+
+        assert_eq!(blocks.balance_of_at(sender, 0), Ok(1000));
+        assert_eq!(blocks.balance_of_at(sender, 1), Ok(999));
+        assert_eq!(blocks.balance_of_at(sender, 2), Ok(997));
+        assert_eq!(blocks.balance_of_at(sender, 3), Ok(994));
+        assert_eq!(
+            blocks.balance_of_at(sender, 100),
+            Ok(blocks.balance_of(sender))
+        );
+    }
+
+    #[test]
+    fn find_transaction() {
+        let blocks = chain(3);
+        let tx = blocks.data_by_number(2).unwrap().data.transactions[0].clone();
+
+        let (found, number) = blocks.find_transaction(tx.hash).unwrap();
+        assert_eq!(found.hash, tx.hash);
+        assert_eq!(number, 2);
+        assert!(blocks.find_transaction(B256::hash_of("unknown")).is_none());
+    }
+
+    #[test]
+    fn huge_block_number() {
+        let mut blocks = chain(2);
+        let mut block = next_block(&blocks, 1, 10);
+        block.data.number = u64::MAX;
+        let block = Block::new(block.data, &signer(1));
+
+        assert!(matches!(
+            blocks.append(block),
+            BlockAppendResult::NeedSync(3)
+        ));
+        assert_eq!(blocks.len(), 3);
+        assert!(blocks.data_by_number(u64::MAX).is_err());
+
+        let sender = B256::address_of(signer(1).verifying_key());
+        assert_eq!(
+            blocks.balance_of_at(sender, u64::MAX),
+            Ok(blocks.balance_of(sender))
+        );
+    }
+
+    #[test]
+    fn verify_valid_chain() {
+        assert_eq!(chain(3).verify_chain(), Ok(()));
+    }
+
+    #[test]
+    fn verify_broken_link() {
+        let mut blocks = chain(2);
+        let mut block = next_block(&blocks, 1, 10);
+        block.data.prev_hash = B256::hash_of("elsewhere");
+        blocks
+            .append_unchecked(Block::new(block.data, &signer(1)))
+            .unwrap();
+
+        assert_eq!(blocks.verify_chain(), Err(ChainError::BrokenLink(3)));
+    }
+
+    #[test]
+    fn verify_bad_signature() {
+        let mut blocks = chain(2);
+        let mut block = next_block(&blocks, 1, 10);
+        block.data.transactions[0].data.amount = 500;
+        let block = Block::new(block.data, &signer(1));
+        blocks.append_unchecked(block).unwrap();
+
+        assert_eq!(
+            blocks.verify_chain(),
+            Err(ChainError::InvalidTransaction(3))
+        );
+
+        let mut blocks = chain(2);
+        let mut block = next_block(&blocks, 1, 10);
+        block.signature = next_block(&blocks, 2, 10).signature;
+        blocks.append_unchecked(block).unwrap();
+
+        assert_eq!(blocks.verify_chain(), Err(ChainError::InvalidBlock(3)));
+    }
+
+    #[test]
+    fn genesis_prev_hash() {
+        let mut blocks = Blocks::default();
+        assert_eq!(blocks.last_hash(), GENESIS_PREV_HASH);
+
+        let data = BlockData {
+            prev_hash: B256::hash_of("not a genesis"),
+            number: 0,
+            nonce: 0,
+            timestamp: 0,
+            transactions: vec![],
+        };
+        let block = Block::new(data, &signer(1));
+        assert!(matches!(blocks.append(block), BlockAppendResult::None));
+
+        assert!(matches!(
+            blocks.append(Block::new_genesis()),
+            BlockAppendResult::Added
+        ));
+    }
+
+    #[test]
+    fn reorg_below_finality_depth() {
+        let config = ChainConfig {
+            finality_depth: Some(2),
+            ..Default::default()
+        };
+        let mut blocks = Blocks::new(config);
+        blocks.append(Block::new_genesis());
+        for i in 0..3 {
+            let block = next_block(&blocks, 1, i + 1);
+            assert!(matches!(blocks.append(block), BlockAppendResult::Added));
+        }
+        assert_eq!(blocks.final_len(), 2);
+
+        // A competing block 1 which would win the reorg by distance.
+        let prev_hash = blocks.data_by_number(0).unwrap().hash;
+        let current = blocks.data_by_number(1).unwrap().proposer;
+        let key = (2..=u8::MAX)
+            .find(|key| {
+                let proposer = B256::address_of(signer(*key).verifying_key());
+                proposer.distance(prev_hash) < current.distance(prev_hash)
+            })
+            .unwrap();
+        let data = BlockData {
+            prev_hash,
+            number: 1,
+            nonce: 0,
+            timestamp: 0,
+            transactions: vec![],
+        };
+        let block = Block::new(data, &signer(key));
+
+        assert!(matches!(blocks.append(block), BlockAppendResult::None));
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(blocks.data_by_number(1).unwrap().proposer, current);
+
+        let sender = B256::address_of(signer(1).verifying_key());
+        assert_eq!(blocks.balance_of(sender), 994);
+        assert_eq!(blocks.final_balance_of(sender), 999);
+    }
+
+    #[test]
+    fn snapshot_bootstrap_matches_replay() {
+        let mut full = chain(2);
+        for (key, amount) in [(2, 20), (3, 30), (1, 10), (2, 40)] {
+            let block = next_block(&full, key, amount);
+            assert!(matches!(full.append(block), BlockAppendResult::Added));
+        }
+
+        let snapshot = full.snapshot_at(3).unwrap();
+        assert_eq!(snapshot.block_hash, full.data_by_number(3).unwrap().hash);
+        let mut bootstrapped = Blocks::from_snapshot(snapshot, ChainConfig::default());
+        assert_eq!(bootstrapped.len(), 4);
+        for number in 4..full.len() {
+            let block = full.data_by_number(number).unwrap().clone();
+            assert!(matches!(
+                bootstrapped.append(block),
+                BlockAppendResult::Added
+            ));
+        }
+
+        assert_eq!(bootstrapped.last_hash(), full.last_hash());
+        bootstrapped.verify_chain().unwrap();
+        let tip = full.len() - 1;
+        assert_eq!(bootstrapped.snapshot_at(tip), full.snapshot_at(tip));
+        for key in 1..=3 {
+            let address = B256::address_of(signer(key).verifying_key());
+            assert_eq!(bootstrapped.balance_of(address), full.balance_of(address));
+        }
+    }
+
+    #[test]
+    fn identical_chains_state_root() {
+        let build = |amounts: [u64; 3]| {
+            let mut blocks = chain(2);
+            for (key, amount) in [2, 3, 1].into_iter().zip(amounts) {
+                let block = next_block(&blocks, key, amount);
+                assert!(matches!(blocks.append(block), BlockAppendResult::Added));
+            }
+            blocks
+        };
+        let (first, second) = (build([20, 30, 10]), build([20, 30, 10]));
+        assert_eq!(first.state_root(), second.state_root());
+        assert_eq!(first.state_root_at(2), second.state_root_at(2));
+        assert_ne!(first.state_root_at(2), first.state_root_at(3));
+
+        let diverged = build([20, 31, 10]);
+        assert_ne!(first.state_root(), diverged.state_root());
+    }
+
+    #[test]
+    fn snapshot_beyond_tip() {
+        let blocks = chain(2);
+        assert!(blocks.snapshot_at(2).is_some());
+        assert!(blocks.snapshot_at(3).is_none());
+
+        let bootstrapped =
+            Blocks::from_snapshot(blocks.snapshot_at(2).unwrap(), ChainConfig::default());
+        assert!(bootstrapped.snapshot_at(1).is_none());
+        assert!(bootstrapped.data_by_number(2).is_err());
+    }
+
+    /// Builds a chain of genesis and `len` more blocks from alternating
+    /// senders with the finality depth of 2.
+    fn final_chain(len: u64) -> Blocks {
+        let config = ChainConfig {
+            finality_depth: Some(2),
+            ..Default::default()
+        };
+        let mut blocks = Blocks::new(config);
+        blocks.append(Block::new_genesis());
+        for i in 0..len {
+            let block = next_block(&blocks, 1 + (i % 3) as u8, i + 1);
+            assert!(matches!(blocks.append(block), BlockAppendResult::Added));
+        }
+        blocks
+    }
+
+    #[test]
+    fn pruned_balances() {
+        let full = final_chain(8);
+        let mut pruned = final_chain(8);
+        pruned.prune(3);
+
+        // The final length is 7 and the last final block is kept.
+        let hash = full.data_by_number(5).unwrap().hash;
+        assert_eq!(
+            pruned.data_by_number(5).unwrap_err(),
+            MissingBlock::Pruned(hash)
+        );
+        assert!(pruned.data_by_number(6).is_ok());
+        assert_eq!(pruned.len(), full.len());
+        assert_eq!(pruned.last_hash(), full.last_hash());
+        pruned.verify_chain().unwrap();
+
+        for key in 1..=3 {
+            let address = B256::address_of(signer(key).verifying_key());
+            assert_eq!(pruned.balance_of(address), full.balance_of(address));
+            assert_eq!(
+                pruned.final_balance_of(address),
+                full.final_balance_of(address)
+            );
+            // The state right after the pruned blocks is kept, earlier ones aren't.
+            assert_eq!(
+                pruned.balance_of_at(address, 5),
+                full.balance_of_at(address, 5)
+            );
+        }
+        let address = B256::address_of(signer(1).verifying_key());
+        let hash = full.data_by_number(4).unwrap().hash;
+        assert_eq!(
+            pruned.balance_of_at(address, 4),
+            Err(MissingBlock::Pruned(hash))
+        );
+        assert_eq!(
+            pruned.final_balance_of_at(address, 0),
+            Err(MissingBlock::Pruned(full.data_by_number(0).unwrap().hash))
+        );
+
+        // Blocks appended after the pruning keep the balances correct.
+        let block = next_block(&pruned, 2, 100);
+        assert!(matches!(
+            pruned.append(block.clone()),
+            BlockAppendResult::Added
+        ));
+        let mut full = full;
+        assert!(matches!(full.append(block), BlockAppendResult::Added));
+        pruned.prune(3);
+        let address = B256::address_of(signer(2).verifying_key());
+        assert_eq!(pruned.balance_of(address), full.balance_of(address));
+    }
+
+    #[test]
+    fn restore_pruned_block() {
+        let full = final_chain(8);
+        let mut pruned = final_chain(8);
+        pruned.prune(3);
+
+        assert!(!pruned.restore(full.data_by_number(6).unwrap().clone()));
+        let mut forged = full.data_by_number(3).unwrap().clone();
+        forged.data.number = 2;
+        assert!(!pruned.restore(forged));
+
+        assert!(pruned.restore(full.data_by_number(2).unwrap().clone()));
+        assert_eq!(
+            pruned.data_by_number(2).unwrap().hash,
+            full.data_by_number(2).unwrap().hash
+        );
+    }
+
+    #[test]
+    fn equidistant_fork() {
+        // Siblings of the same proposer are equally distant from the parent.
+        let genesis = chain(0);
+        let a = next_block(&genesis, 1, 100);
+        let b = next_block(&genesis, 1, 200);
+        let winner = a.hash.min(b.hash);
+
+        let mut first = chain(0);
+        first.append(a.clone());
+        first.append(b.clone());
+        let mut second = chain(0);
+        second.append(b);
+        second.append(a);
+
+        assert_eq!(first.last_hash(), winner);
+        assert_eq!(second.last_hash(), winner);
+        assert_eq!(first.len(), 2);
+        assert_eq!(second.len(), 2);
+    }
+
+    #[test]
+    fn reorg_handler() {
+        let genesis = chain(0);
+        let a = next_block(&genesis, 1, 100);
+        let b = next_block(&genesis, 1, 200);
+        let (loser, winner) = if a.hash < b.hash { (b, a) } else { (a, b) };
+
+        let mut blocks = chain(0);
+        let reorgs = Rc::new(RefCell::new(Vec::new()));
+        let recorded = reorgs.clone();
+        blocks.set_reorg_handler(move |reorg| {
+            let applied: Vec<B256> = reorg.applied.iter().map(|block| block.hash).collect();
+            recorded
+                .borrow_mut()
+                .push((reorg.reverted.clone(), applied));
+        });
+        blocks.append(loser);
+        let block = next_block(&blocks, 1, 300);
+        assert!(matches!(blocks.append(block), BlockAppendResult::Added));
+        assert!(reorgs.borrow().is_empty());
+
+        blocks.append(winner.clone());
+        assert_eq!(*reorgs.borrow(), [(1..3, vec![winner.hash])]);
+    }
+
+    #[test]
+    fn tip_state_matches_replay() {
+        let blocks = chain(5);
+        for height in 0..5 {
+            let shorter = chain(height);
+            assert_eq!(blocks.state_root_at(height), Some(shorter.state_root()));
+            assert_eq!(
+                blocks.snapshot_at(height).unwrap().balances,
+                shorter.snapshot_at(height).unwrap().balances
+            );
+        }
+    }
+
+    #[test]
+    fn rollback() {
+        let mut blocks = chain(5);
+        let reverted = blocks.data_by_number(4).unwrap().clone();
+        let tx = &reverted.data.transactions[0];
+
+        assert_eq!(blocks.rollback_to(2), Some(3..6));
+        let expected = chain(2);
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks.last_hash(), expected.last_hash());
+        assert_eq!(blocks.state_root(), expected.state_root());
+        let sender = B256::address_of(signer(1).verifying_key());
+        assert_eq!(blocks.balance_of(sender), expected.balance_of(sender));
+        assert!(!blocks.contains_tx(tx.hash));
+        assert!(blocks.get(&reverted.hash).is_none());
+
+        // The chain grows again from the new tip.
+        let block = next_block(&blocks, 1, 3);
+        assert!(matches!(blocks.append(block), BlockAppendResult::Added));
+        assert_eq!(blocks.rollback_to(3), Some(4..4));
+        assert_eq!(blocks.rollback_to(10), None);
+    }
+
+    #[test]
+    #[cfg_attr(debug_assertions, should_panic(expected = "block number out of sync"))]
+    fn append_unchecked_wrong_number() {
+        let mut blocks = chain(2);
+        let mut block = next_block(&blocks, 1, 10);
+        block.data.number += 1;
+        let block = Block::new(block.data, &signer(1));
+
+        assert_eq!(
+            blocks.append_unchecked(block),
+            Err(ChainError::WrongNumber(4))
+        );
+        assert_eq!(blocks.len(), 3);
+    }
+
+    #[test]
+    fn accounts() {
+        let mut blocks = Blocks::default();
+        blocks.append(Block::new_genesis());
+        let (first, second) = (B256::hash_of("first"), B256::hash_of("second"));
+        let transactions = vec![
+            TransactionBuilder::new()
+                .to(second)
+                .amount(900)
+                .sign(&signer(1)),
+            TransactionBuilder::new()
+                .to(first)
+                .amount(100)
+                .sign(&signer(1)),
+        ];
+        let data = BlockData {
+            prev_hash: blocks.last_hash(),
+            number: 1,
+            nonce: 0,
+            timestamp: 0,
+            transactions,
+        };
+        let block = Block::new(data, &signer(2));
+        assert!(matches!(blocks.append(block), BlockAppendResult::Added));
+
+        // The sender spent everything, so it isn't listed.
+        let mut expected = vec![
+            (first, 1100),
+            (second, 1900),
+            (B256::address_of(signer(2).verifying_key()), 1000),
+            (Block::new_genesis().proposer, 1000),
+        ];
+        expected.sort();
+        assert_eq!(blocks.accounts(), expected);
+    }
+
+    #[test]
+    fn tip() {
+        assert_eq!(Blocks::default().tip(), None);
+
+        let blocks = chain(3);
+        assert_eq!(blocks.tip(), Some((blocks.last_hash(), 3)));
+    }
+
+    #[test]
+    fn reorg_margin_sticks_to_tip() {
+        let config = ChainConfig {
+            reorg_margin: 50,
+            ..Default::default()
+        };
+        let mut blocks = Blocks::new(config.clone());
+        blocks.append(Block::new_genesis());
+        let parent = blocks.last_hash();
+
+        // Proposers ordered by their distance to the parent.
+        let mut keys: Vec<u8> = (1..=50).collect();
+        let distance = |key: u8| {
+            config
+                .distance_metric
+                .distance(B256::address_of(signer(key).verifying_key()), parent)
+        };
+        keys.sort_by_key(|key| distance(*key));
+        let (far, near) = keys
+            .windows(2)
+            .rev()
+            .map(|pair| (pair[1], pair[0]))
+            .find(|(far, near)| {
+                let half = distance(*far).wrapping_div(&U256::from_u8(2));
+                distance(*near) > half
+            })
+            .expect("some proposers should be near-tied");
+
+        let current = next_block(&blocks, far, 10);
+        let near_tied = next_block(&blocks, near, 10);
+        let closest = next_block(&blocks, keys[0], 10);
+        blocks.append(current.clone());
+        for _ in 0..3 {
+            assert!(matches!(
+                blocks.append(near_tied.clone()),
+                BlockAppendResult::None
+            ));
+            assert!(matches!(
+                blocks.append(current.clone()),
+                BlockAppendResult::None
+            ));
+            assert_eq!(blocks.last_hash(), current.hash);
+        }
+
+        // A clearly closer proposer still wins.
+        assert!(matches!(
+            blocks.append(closest.clone()),
+            BlockAppendResult::NeedSync(2)
+        ));
+        assert_eq!(blocks.last_hash(), closest.hash);
+    }
+
+    #[test]
+    fn permissioned_proposers() {
+        let validator = B256::address_of(signer(1).verifying_key());
+        let config = ChainConfig {
+            validators: Some(HashSet::from([validator])),
+            ..Default::default()
+        };
+        let mut blocks = Blocks::new(config);
+        blocks.append(Block::new_genesis());
+
+        let block = next_block(&blocks, 2, 10);
+        assert!(matches!(blocks.append(block), BlockAppendResult::None));
+
+        let block = next_block(&blocks, 1, 10);
+        assert!(matches!(blocks.append(block), BlockAppendResult::Added));
+    }
+
+    #[test]
+    fn genesis_validators() {
+        let validator = B256::address_of(signer(1).verifying_key());
+        let allocated = B256::hash_of("allocated");
+        let genesis = GenesisConfig {
+            allocations: BTreeMap::from([(allocated, 500)]),
+            validators: BTreeSet::from([validator]),
+        };
+        let mut blocks = Blocks::new(ChainConfig {
+            genesis: genesis.clone(),
+            ..Default::default()
+        });
+        blocks.append(genesis.block());
+        assert_eq!(blocks.balance_of(allocated), 1500);
+
+        let block = next_block(&blocks, 2, 10);
+        assert!(matches!(blocks.append(block), BlockAppendResult::None));
+
+        let block = next_block(&blocks, 1, 10);
+        assert!(matches!(blocks.append(block), BlockAppendResult::Added));
+    }
+
+    #[test]
+    fn import_foreign_genesis() {
+        let genesis = GenesisConfig {
+            allocations: BTreeMap::from([(B256::hash_of("allocated"), 500)]),
+            ..Default::default()
+        };
+        let config = ChainConfig {
+            genesis: genesis.clone(),
+            ..Default::default()
+        };
+        let mut blocks = Blocks::new(config.clone());
+        assert!(matches!(
+            blocks.append(Block::new_genesis()),
+            BlockAppendResult::None
+        ));
+        assert!(matches!(
+            blocks.append(genesis.block()),
+            BlockAppendResult::Added
+        ));
+        let block = next_block(&blocks, 1, 10);
+        blocks.append(block);
+
+        let json = blocks.export_json();
+        let imported = Blocks::import_json(&json, ChainConfig::default());
+        assert!(matches!(imported, Err(ImportError::InvalidBlock(0))));
+        assert_eq!(Blocks::import_json(&json, config).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn permissionless_proposers() {
+        let mut blocks = chain(0);
+        let block = next_block(&blocks, 2, 10);
+        assert!(matches!(blocks.append(block), BlockAppendResult::Added));
+    }
+
+    #[test]
+    fn coinbase_credits_proposer() {
+        let config = ChainConfig {
+            block_reward: 50,
+            ..Default::default()
+        };
+        let mut blocks = Blocks::new(config);
+        blocks.append(Block::new_genesis());
+
+        let proposer = B256::address_of(signer(1).verifying_key());
+        let mut block = next_block(&blocks, 2, 10);
+        block
+            .data
+            .transactions
+            .insert(0, Transaction::coinbase(proposer, 50, 1));
+        let block = Block::new(block.data, &signer(1));
+        assert!(matches!(blocks.append(block), BlockAppendResult::Added));
+
+        assert_eq!(blocks.balance_of(proposer), 1050);
+    }
+
+    #[test]
+    fn fee_paid_to_proposer() {
+        let mut blocks = chain(0);
+        let tx = TransactionBuilder::new().amount(10).fee(3).sign(&signer(2));
+        let data = BlockData {
+            prev_hash: blocks.last_hash(),
+            number: blocks.len(),
+            nonce: 0,
+            timestamp: 0,
+            transactions: vec![tx],
+        };
+        let block = Block::new(data, &signer(1));
+        assert!(matches!(blocks.append(block), BlockAppendResult::Added));
+
+        let sender = B256::address_of(signer(2).verifying_key());
+        let proposer = B256::address_of(signer(1).verifying_key());
+        assert_eq!(blocks.balance_of(sender), 987);
+        assert_eq!(blocks.balance_of(proposer), 1003);
+    }
+
+    #[test]
+    fn coinbase_with_wrong_reward() {
+        let config = ChainConfig {
+            block_reward: 50,
+            ..Default::default()
+        };
+        let mut blocks = Blocks::new(config);
+        blocks.append(Block::new_genesis());
+
+        let proposer = B256::address_of(signer(1).verifying_key());
+        let mut block = next_block(&blocks, 2, 10);
+        block
+            .data
+            .transactions
+            .insert(0, Transaction::coinbase(proposer, 500, 1));
+        let block = Block::new(block.data, &signer(1));
+        assert!(matches!(blocks.append(block), BlockAppendResult::None));
+    }
+}
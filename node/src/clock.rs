@@ -0,0 +1,81 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Source of the current time, replaceable to test time-based logic.
+pub trait Clock: Send {
+    /// Milliseconds since the UNIX epoch.
+    fn now(&self) -> u64;
+}
+
+/// Time of the system.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("system time should be after the epoch");
+        since_epoch.as_millis() as u64
+    }
+}
+
+/// Time that is set explicitly, e.g. to the times of replayed messages.
+/// Clones share the time.
+#[derive(Debug, Default, Clone)]
+pub struct ReplayClock(Arc<AtomicU64>);
+
+impl ReplayClock {
+    pub fn set(&self, now: u64) {
+        self.0.store(now, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ReplayClock {
+    fn now(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Time that only moves when advanced. Clones share the time.
+#[cfg(test)]
+#[derive(Debug, Default, Clone)]
+pub struct MockClock(Arc<AtomicU64>);
+
+#[cfg(test)]
+impl MockClock {
+    pub fn advance(&self, by: Duration) {
+        self.0.fetch_add(by.as_millis() as u64, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Time passed from `since` to `now`, both in milliseconds of a clock.
+pub fn elapsed(now: u64, since: u64) -> Duration {
+    Duration::from_millis(now.saturating_sub(since))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::clock::{elapsed, Clock, MockClock};
+
+    #[test]
+    fn mock_clock_advances() {
+        let clock = MockClock::default();
+        let shared = clock.clone();
+        let start = clock.now();
+
+        shared.advance(Duration::from_secs(5));
+        assert_eq!(elapsed(clock.now(), start), Duration::from_secs(5));
+        assert_eq!(elapsed(start, clock.now()), Duration::ZERO);
+    }
+}
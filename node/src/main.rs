@@ -1,291 +1,634 @@
-use std::cmp::Ordering;
-use std::collections::{BTreeMap, HashMap};
-use std::net::SocketAddr;
-
-use clap::{arg, Parser};
+mod blocks;
+mod clock;
+mod metrics;
+mod network;
+mod node;
+mod rate_limit;
+mod recent;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+use std::fs::File;
+use std::io::BufReader;
+use std::net::{SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use blocks::{Blocks, ChainConfig, DistanceMetric};
+use clap::Parser;
 use k256::ecdsa::SigningKey;
-use ledger_transport::Transport;
-use ledger_types::{Block, BlockData, Message, NodeInfo, Transaction, B256};
+use ledger_transport::{network_key, TransportConfig};
+use ledger_types::{
+    GenesisConfig, NodeInfo, PublicKey, Signature, StateSnapshot, B256, MAX_NAME_LEN,
+};
+use network::{Network, NullNetwork};
+use node::{Config, Node, TxOrder};
+use sha2::{Digest, Sha256};
 
 /// Command line parameters of the simple-ledger node.
 #[derive(Debug, Parser)]
 struct Params {
-    /// Name of the node.
+    /// Name of the node. Derived from the address if the key is given, and
+    /// random otherwise.
     #[arg(short, long)]
     name: Option<String>,
 
-    /// Socket address of the node.
-    #[clap(short, long)]
-    socket: SocketAddr,
+    /// Hex representation of the node's signing key, keeping its address
+    /// across restarts. Random if not specified.
+    #[clap(long, value_parser = parse_key)]
+    key: Option<SigningKey>,
+
+    /// Seed the node's signing key is derived from, for reproducible local
+    /// clusters. Not secret enough for real deployments.
+    #[clap(long, conflicts_with = "key")]
+    seed: Option<u64>,
+
+    /// Socket address of the node, as `host:port`. Not needed to only
+    /// verify a chain.
+    #[clap(short, long, required_unless_present = "verify_chain")]
+    socket: Option<String>,
+
+    /// Socket address advertised to peers if it differs from the bound one,
+    /// e.g. behind NAT.
+    #[clap(long)]
+    announce_socket: Option<String>,
 
     /// Socket address of another working node.
     #[clap(short, long)]
-    other_node: Option<SocketAddr>,
+    other_node: Option<String>,
+
+    /// Prefer IPv6 addresses when a host name resolves to both versions.
+    #[clap(long)]
+    prefer_ipv6: bool,
+
+    /// Required number of leading zero bits in block hashes.
+    #[clap(short, long, default_value_t = 0)]
+    difficulty: u32,
+
+    /// Amount minted to the proposer of each block.
+    #[clap(long, default_value_t = 0)]
+    block_reward: u64,
+
+    /// Number of the latest blocks that can be reorged. Older blocks are final.
+    #[clap(long)]
+    finality_depth: Option<u64>,
+
+    /// Number of the latest blocks kept in full. Bodies of older final blocks
+    /// are dropped.
+    #[clap(long, requires = "finality_depth")]
+    prune_keep: Option<u64>,
+
+    /// Propose blocks even if there are no pending transactions.
+    #[clap(long)]
+    allow_empty_blocks: bool,
+
+    /// Maximal datagram size; larger messages are sent in chunks.
+    #[clap(long, default_value_t = TransportConfig::default().mtu)]
+    mtu: usize,
+
+    /// Bytes of the OS receive buffer of the socket. Busy nodes may need more
+    /// than the default to not drop datagrams.
+    #[clap(long)]
+    recv_buf: Option<usize>,
+
+    /// Bytes of the OS send buffer of the socket.
+    #[clap(long)]
+    send_buf: Option<usize>,
+
+    /// Compress large messages, e.g. sync responses. Compressed messages are
+    /// accepted either way.
+    #[clap(long)]
+    compress: bool,
+
+    /// Log a warning for every sent message exceeding the MTU, naming its
+    /// variant. Only for catching protocol size regressions.
+    #[clap(long)]
+    warn_over_mtu: bool,
+
+    /// Send pretty-printed JSON and log every sent and received message.
+    /// Only for debugging, as it inflates the messages.
+    #[clap(long)]
+    debug_wire: bool,
+
+    /// Secret shared by the nodes and clients of a private network. Messages
+    /// not authenticated by it are rejected.
+    #[clap(long)]
+    network_secret: Option<String>,
+
+    /// Path of an exported chain to start from instead of the genesis.
+    #[clap(long)]
+    import: Option<PathBuf>,
+
+    /// Path of a fetched state snapshot to start from instead of the genesis.
+    /// Only the blocks after it are synced.
+    #[clap(long, requires = "snapshot_signer", conflicts_with = "import")]
+    snapshot: Option<PathBuf>,
+
+    /// Number of the block to revert the chain to on start, e.g. to recover
+    /// from a bad block. Applied after the import or the snapshot.
+    #[clap(long)]
+    rollback_to: Option<u64>,
+
+    /// Address of the node trusted to sign the snapshot.
+    #[clap(long, value_parser = parse_address)]
+    snapshot_signer: Option<B256>,
+
+    /// Metric of closeness between proposers and blocks electing leaders and
+    /// resolving forks: `numeric` or `xor`. Must be the same on all nodes.
+    #[clap(long, value_parser = parse_distance_metric, default_value = "numeric")]
+    distance_metric: DistanceMetric,
+
+    /// Path of a JSON genesis config with the initial `allocations` and
+    /// `validators`. All nodes of the chain must use the same one.
+    #[clap(long)]
+    genesis: Option<PathBuf>,
+
+    /// Percentage by which a competing block's proposer must be closer to
+    /// replace the tip. Reduces reorgs, but nodes may keep different tips
+    /// if they receive near-tied blocks in different orders.
+    #[clap(long, default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=100))]
+    reorg_margin: u8,
+
+    /// Accept blocks only from the validators.
+    #[clap(long, requires = "validator")]
+    permissioned: bool,
+
+    /// Address of a validator allowed to propose blocks in the permissioned mode.
+    #[clap(long, value_parser = parse_address)]
+    validator: Vec<B256>,
+
+    /// Minimal milliseconds between block proposals, batching pending
+    /// transactions. Blocks are proposed on every transaction if not specified.
+    #[clap(long)]
+    block_interval: Option<u64>,
+
+    /// Seconds seen transactions and gossiped messages are remembered for.
+    /// Only their number is bounded if not specified.
+    #[clap(long)]
+    seen_ttl: Option<u64>,
+
+    /// Path of a file rejected transactions are appended to as JSON lines.
+    #[clap(long)]
+    reject_log: Option<PathBuf>,
+
+    /// Path of a file every received message is appended to as JSON lines,
+    /// to reproduce bugs with `--replay`.
+    #[clap(long, conflicts_with = "replay")]
+    record: Option<PathBuf>,
+
+    /// Path of a message record to feed to the node instead of the socket.
+    /// The node sends nothing, prints the resulting tip and exits. Replaying
+    /// needs the same key and initial state as the recorded run.
+    #[clap(long)]
+    replay: Option<PathBuf>,
+
+    /// Path of an exported chain to verify offline. Prints the first invalid
+    /// block or the tip of the valid chain and exits.
+    #[clap(long, conflicts_with = "replay")]
+    verify_chain: Option<PathBuf>,
+
+    /// Path of a file the pending transactions are saved to, restored from
+    /// on start. Restored transactions are validated against the chain again.
+    #[clap(long, conflicts_with = "replay")]
+    mempool: Option<PathBuf>,
+
+    /// Number of random peers each gossiped message is forwarded to.
+    /// Messages are broadcast to all peers if not specified.
+    #[clap(long)]
+    gossip_fanout: Option<usize>,
+
+    /// Maximal number of pending transactions of a single sender. Not
+    /// limited if not specified.
+    #[clap(long)]
+    max_pending_per_sender: Option<usize>,
+
+    /// Maximal number of broadcast messages sent to each peer per second.
+    /// Excess messages are dropped. Not limited if not specified.
+    #[clap(long)]
+    send_rate: Option<u64>,
+
+    /// Maximal milliseconds a block timestamp may be ahead of the node's
+    /// clock. Blocks further in the future are rejected.
+    #[clap(long, default_value_t = 15_000)]
+    max_future_skew: u64,
+
+    /// Maximal number of blocks sent in response to a single sync request.
+    #[clap(long, default_value_t = 100)]
+    max_sync_response: u64,
+
+    /// Order pending transactions are included into blocks in: `fee`,
+    /// `fifo` by arrival or `hash`.
+    #[clap(long, value_parser = parse_tx_order, default_value = "fee")]
+    tx_order: TxOrder,
+
+    /// Socket address to serve the Prometheus metrics on at `/metrics`.
+    #[clap(long)]
+    metrics_addr: Option<SocketAddr>,
+
+    /// Socket address to serve browser clients on over WebSocket.
+    #[cfg(feature = "websocket")]
+    #[clap(long)]
+    websocket_addr: Option<SocketAddr>,
+
+    /// Run as an observer which follows the chain without proposing blocks.
+    #[clap(long)]
+    observer: bool,
+
+    /// Run the node on the async tokio runtime.
+    #[cfg(feature = "tokio")]
+    #[clap(long)]
+    async_runtime: bool,
 }
 
 fn main() {
     let params = Params::parse();
 
-    let name = params
-        .name
-        .unwrap_or_else(|| names::Generator::default().next().unwrap());
+    let genesis: GenesisConfig = match &params.genesis {
+        Some(path) => {
+            let json = std::fs::read_to_string(path).expect("genesis file should be readable");
+            serde_json::from_str(&json).expect("genesis file should be valid")
+        }
+        None => GenesisConfig::default(),
+    };
+    let chain = ChainConfig {
+        difficulty: params.difficulty,
+        block_reward: params.block_reward,
+        finality_depth: params.finality_depth,
+        validators: params
+            .permissioned
+            .then(|| params.validator.iter().copied().collect()),
+        distance_metric: params.distance_metric,
+        reorg_margin: params.reorg_margin,
+        genesis,
+    };
 
-    let signer = SigningKey::random(&mut rand::thread_rng());
-    let address = B256::address_of(signer.verifying_key());
+    if let Some(path) = &params.verify_chain {
+        match verify_chain_file(path, chain) {
+            Ok(summary) => println!("{}", summary),
+            Err(e) => exit_with_error(&e),
+        }
+        return;
+    }
 
+    let socket = params.socket.as_deref().expect("socket should be required");
+    let socket = resolve_socket(socket, params.prefer_ipv6);
+    let announce_socket = params.announce_socket.as_ref().map(|s| {
+        let socket = resolve_socket(s, params.prefer_ipv6);
+        validate_announce_socket(socket).unwrap_or_else(|e| exit_with_error(&e))
+    });
+    let other_node = params
+        .other_node
+        .as_ref()
+        .map(|s| resolve_socket(s, params.prefer_ipv6));
+
+    // A given or seeded key keeps the address, and the derived name, across
+    // restarts. Otherwise a random key is generated.
+    let persisted = params.key.is_some() || params.seed.is_some();
+    let key = match (params.key, params.seed) {
+        (Some(key), _) => key,
+        (None, Some(seed)) => key_from_seed(seed),
+        (None, None) => SigningKey::random(&mut rand::thread_rng()),
+    };
+    let address = B256::address_of(key.verifying_key());
+    let name = match params.name {
+        Some(name) => name,
+        None if persisted => name_of(&address),
+        None => names::Generator::default().next().unwrap(),
+    };
     let node_info = NodeInfo {
         name,
         address,
-        socket: params.socket,
+        public_key: PublicKey::from_key(key.verifying_key()),
+        socket: announce_socket.unwrap_or(socket),
+        // An observer doesn't propose blocks, so its key is only an identity.
+        observer: params.observer,
     };
-
-    println!(
-        "Creating Node {} with socket {}",
-        node_info.name, node_info.socket
-    );
-    let node = Node::new(signer, node_info.clone());
-
-    if let Some(other_node_socket) = params.other_node {
-        node.transport
-            .send(other_node_socket, &Message::Hello(node_info))
-            .unwrap();
+    if node_info.verify_name().is_none() {
+        exit_with_error(&format!(
+            "the name must be at most {} printable bytes",
+            MAX_NAME_LEN
+        ));
     }
 
-    node.run();
-}
-
-struct Node {
-    info: NodeInfo,
-    transport: Transport,
-    signer: SigningKey,
-    others: BTreeMap<B256, NodeInfo>,
-    blocks: Blocks,
-    pending_transactions: HashMap<B256, Transaction>,
-}
-
-impl Node {
-    fn new(signer: SigningKey, info: NodeInfo) -> Self {
-        let transport = Transport::new(info.socket).expect("failed to create transport");
-        let others = BTreeMap::new();
-        let blocks = Blocks::default();
-        let pending_transactions = HashMap::new();
-
-        let mut node = Self {
-            transport,
-            info,
-            signer,
-            others,
-            blocks,
-            pending_transactions,
-        };
-
-        node.blocks.append(Block::new_genesis());
-        node
-    }
-
-    pub fn run(mut self) {
-        while let Some(message) = self.transport.receive() {
-            self.process_message(message)
+    println!("Creating Node {} with socket {}", node_info.name, socket);
+    let config = Config {
+        bind_socket: Some(socket),
+        chain,
+        allow_empty_blocks: params.allow_empty_blocks,
+        // The genesis is created on start, the chain is only empty once an
+        // empty chain is imported.
+        create_genesis: false,
+        transport: TransportConfig {
+            mtu: params.mtu,
+            debug_wire: params.debug_wire,
+            recv_buffer: params.recv_buf,
+            send_buffer: params.send_buf,
+            compress: params.compress,
+            warn_over_mtu: params.warn_over_mtu,
+            network_key: params.network_secret.as_deref().map(network_key),
+        },
+        gossip_fanout: params.gossip_fanout,
+        block_interval: params.block_interval.map(Duration::from_millis),
+        prune_keep: params.prune_keep,
+        max_pending_per_sender: params.max_pending_per_sender,
+        max_sync_response: Some(params.max_sync_response),
+        seen_ttl: params.seen_ttl.map(Duration::from_secs),
+        reject_log: params.reject_log,
+        tx_order: params.tx_order,
+        send_rate: params.send_rate,
+        rng_seed: None,
+        record: params.record,
+        max_future_skew: Some(Duration::from_millis(params.max_future_skew)),
+        mempool: params.mempool,
+    };
+    let snapshot = params.snapshot.zip(params.snapshot_signer);
+
+    if let Some(path) = params.replay {
+        let mut node = Node::with_network(key, node_info, config, NullNetwork);
+        restore_state(&mut node, params.import, snapshot, params.rollback_to);
+        let file = File::open(path).expect("message record should be readable");
+        let replayed = node
+            .replay(BufReader::new(file))
+            .unwrap_or_else(|e| exit_with_error(&e.to_string()));
+        println!("Replayed {} messages", replayed);
+        match node.tip() {
+            Some((hash, height)) => println!("Tip: {} at height {}", hash, height),
+            None => println!("The chain is empty"),
         }
+        return;
     }
 
-    fn process_message(&mut self, message: Message) {
-        match message {
-            Message::Hello(node_info) => self.process_hello(node_info),
-            Message::Transaction(tx) => self.process_transaction(tx),
-            Message::Block(block) => self.process_block(block),
-            Message::SyncBlock(sender, start) => self.process_sync_block(sender, start),
-            Message::BalanceOf(sender, address) => self.process_balance_of(sender, address),
-        }
+    #[cfg(feature = "websocket")]
+    let transport_config = config.transport;
+    let mut node = Node::new(key, node_info, config);
+    println!("Announcing socket {}", node.socket());
+    restore_state(&mut node, params.import, snapshot, params.rollback_to);
+    if let Err(e) = node.load_mempool() {
+        exit_with_error(&format!("failed to load the mempool: {}", e));
     }
 
-    fn process_hello(&mut self, node_info: NodeInfo) {
-        let replaced = self.others.insert(node_info.address, node_info.clone());
-
-        // If the node is new for us, let's say hi to it.
-        if replaced.is_none() && node_info.address != self.info.address {
-            println!("Got hello from {}", node_info.name);
-
-            self.transport
-                .send(node_info.socket, &Message::Hello(self.info.clone()));
-            self.send_to_others(Message::Hello(node_info));
-        }
+    if let Some(metrics_addr) = params.metrics_addr {
+        let listener = TcpListener::bind(metrics_addr).expect("metrics listener should be bound");
+        println!("Serving metrics on {}", metrics_addr);
+        let metrics = node.metrics();
+        std::thread::spawn(move || metrics::serve(listener, metrics));
     }
 
-    fn process_transaction(&mut self, tx: Transaction) {
-        if tx.verify().is_none() || self.blocks.contains_tx(tx.hash) {
-            return;
-        }
+    #[cfg(feature = "websocket")]
+    if let Some(websocket_addr) = params.websocket_addr {
+        let listener =
+            TcpListener::bind(websocket_addr).expect("WebSocket listener should be bound");
+        println!("Serving WebSocket clients on {}", websocket_addr);
+        let socket = node.socket();
+        std::thread::spawn(move || websocket::serve(listener, socket, transport_config));
+    }
 
-        println!("Got tx {}", tx.hash);
+    if let Some(other_node_socket) = other_node {
+        node.transport
+            .send(other_node_socket, &node.hello())
+            .unwrap();
+    }
 
-        if self.blocks.balance_of(tx.from) < tx.data.amount {
-            return;
-        }
+    #[cfg(feature = "tokio")]
+    if params.async_runtime {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("tokio runtime should be built");
+        runtime.block_on(node.run_async());
+        return;
+    }
 
-        let replaced = self.pending_transactions.insert(tx.hash, tx.clone());
+    node.run();
+}
 
-        // If the transaction is new for us, let's broadcast it.
-        if replaced.is_none() {
-            self.send_to_others(Message::Transaction(tx));
-            self.propose_block();
-        }
+/// Imports the chain or bootstraps from the snapshot signed by the trusted
+/// address, then rolls back to the height.
+fn restore_state<N: Network>(
+    node: &mut Node<N>,
+    import: Option<PathBuf>,
+    snapshot: Option<(PathBuf, B256)>,
+    rollback_to: Option<u64>,
+) {
+    if let Some(path) = import {
+        let json = std::fs::read_to_string(path).expect("chain file should be readable");
+        node.import_chain(&json)
+            .expect("chain file should be valid");
     }
 
-    fn process_block(&mut self, block: Block) {
-        if block.verify().is_none() || block.proposer == self.info.address {
-            return;
-        }
+    if let Some((path, signer)) = snapshot {
+        let json = std::fs::read_to_string(path).expect("snapshot file should be readable");
+        let (snapshot, signature): (StateSnapshot, Signature) =
+            serde_json::from_str(&json).expect("snapshot file should be valid");
+        node.bootstrap(snapshot, &signature, signer)
+            .expect("snapshot should be signed by the trusted signer");
+    }
 
-        println!("Got block {}", block.hash);
+    if let Some(height) = rollback_to {
+        node.rollback_to(height)
+            .expect("block to roll back to should be kept in full");
+    }
+}
 
-        let block_append_result = self.blocks.append(block.clone());
+/// Verifies the exported chain in the file, returning a summary of the valid
+/// chain or the first error.
+fn verify_chain_file(path: &Path, chain: ChainConfig) -> Result<String, String> {
+    let json =
+        std::fs::read_to_string(path).map_err(|e| format!("failed to read the chain: {}", e))?;
+    let chain = Blocks::verify_json(&json, chain).map_err(|e| e.to_string())?;
+    Ok(match chain.tip() {
+        Some((hash, height)) => format!("Chain is valid, height {} with tip {}", height, hash),
+        None => "Chain is valid and empty".to_string(),
+    })
+}
 
-        // If the block is new for us, let's broadcast it.
-        match block_append_result {
-            BlockAppendResult::NeedSync(start) => {
-                self.send_to_others(Message::SyncBlock(self.info.address, start))
-            }
-            BlockAppendResult::Added => self.send_to_others(Message::Block(block)),
-            BlockAppendResult::None => {}
+/// Derives the signing key deterministically from the seed, as SHA-256 of
+/// its big-endian bytes. A digest that isn't a valid key is hashed again.
+fn key_from_seed(seed: u64) -> SigningKey {
+    let mut digest = Sha256::digest(seed.to_be_bytes());
+    loop {
+        if let Ok(key) = SigningKey::from_slice(&digest) {
+            return key;
         }
+        digest = Sha256::digest(digest);
     }
+}
 
-    fn process_sync_block(&mut self, sender: B256, start: u64) {
-        let Some(sender_info) = self.others.get(&sender) else {
-            return;
-        };
+/// Parses a hex representation of a signing key.
+fn parse_key(s: &str) -> Result<SigningKey, String> {
+    let bytes = B256::from_hex_string(s).map_err(|e| format!("{}", e))?;
+    SigningKey::from_slice(&bytes.0).map_err(|_| "invalid signing key".to_string())
+}
 
-        println!("Got sync block from {}", sender_info.name);
+/// Human-readable name derived from the address, stable across restarts.
+fn name_of(address: &B256) -> String {
+    let pick = |words: &[&'static str], bytes: &[u8]| {
+        let index = u64::from_be_bytes(bytes.try_into().unwrap()) % words.len() as u64;
+        words[index as usize]
+    };
+    format!(
+        "{}-{}",
+        pick(names::ADJECTIVES, &address.0[..8]),
+        pick(names::NOUNS, &address.0[8..16])
+    )
+}
 
-        // Send blocks after the start to request sender.
-        for i in start..self.blocks.hashes.len() as u64 {
-            let block = self.blocks.data_by_number(i).unwrap();
-            println!("Sending sync block response {}", block.hash);
-            self.transport.send(sender_info.socket, block);
-        }
+/// Parses the name of a distance metric.
+fn parse_distance_metric(s: &str) -> Result<DistanceMetric, String> {
+    match s {
+        "numeric" => Ok(DistanceMetric::Numeric),
+        "xor" => Ok(DistanceMetric::Xor),
+        _ => Err("expected `numeric` or `xor`".to_string()),
     }
+}
 
-    fn propose_block(&mut self) {
-        let transactions = self.pending_transactions.drain();
-
-        let block = Block::new(
-            BlockData {
-                prev_hash: self.blocks.last_hash(),
-                number: self.blocks.hashes.len() as u64,
-                transactions: transactions.map(|(_, tx)| tx).collect(),
-            },
-            &self.signer,
-        );
-
-        self.blocks.append_unchecked(block.clone());
-        self.send_to_others(Message::Block(block));
+/// Parses the name of a transaction order.
+fn parse_tx_order(s: &str) -> Result<TxOrder, String> {
+    match s {
+        "fee" => Ok(TxOrder::Fee),
+        "fifo" => Ok(TxOrder::Fifo),
+        "hash" => Ok(TxOrder::Hash),
+        _ => Err("expected `fee`, `fifo` or `hash`".to_string()),
     }
+}
 
-    fn process_balance_of(&self, sender: SocketAddr, address: B256) {
-        println!("Processing balance_of from {}", address);
+/// Parses a hex address.
+fn parse_address(s: &str) -> Result<B256, String> {
+    B256::from_hex_string(s).map_err(|e| format!("{}", e))
+}
 
-        let balance = self.blocks.balance_of(address);
-        self.transport.send(sender, &balance);
-    }
+/// Resolves a `host:port` string, exiting if it can't be resolved.
+fn resolve_socket(s: &str, prefer_ipv6: bool) -> SocketAddr {
+    ledger_transport::resolve(s, prefer_ipv6)
+        .unwrap_or_else(|e| exit_with_error(&format!("can't resolve {}: {}", s, e)))
+}
 
-    fn send_to_others(&self, msg: Message) {
-        for other in self.others.values() {
-            self.transport.send(other.socket, &msg);
-        }
+/// Checks that an announced socket address can be reached by peers.
+fn validate_announce_socket(socket: SocketAddr) -> Result<SocketAddr, String> {
+    if socket.ip().is_unspecified() {
+        return Err("announced IP address should be specified".to_string());
+    }
+    if socket.port() == 0 {
+        return Err("announced port should be specified".to_string());
     }
+    Ok(socket)
 }
 
-#[derive(Debug, Default)]
-struct Blocks {
-    hashes: Vec<B256>,
-    data: HashMap<B256, Block>,
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(1)
 }
-impl Blocks {
-    pub fn append(&mut self, block: Block) -> BlockAppendResult {
-        let new_block_number = block.data.number;
-        if self.hashes.is_empty() && new_block_number == 0 {
-            self.append_unchecked(block);
-            return BlockAppendResult::Added;
-        }
 
-        if new_block_number == 0 {
-            return BlockAppendResult::None;
-        }
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
 
-        let next_block_number = self.hashes.len() as u64;
-        match new_block_number.cmp(&next_block_number) {
-            Ordering::Equal => {
-                let prev_block_hash = self.hashes[new_block_number as usize - 1];
-                if block.data.prev_hash != prev_block_hash {
-                    return BlockAppendResult::None;
-                }
-
-                self.append_unchecked(block);
-                BlockAppendResult::Added
-            }
-            Ordering::Greater => BlockAppendResult::NeedSync(next_block_number),
-            Ordering::Less => {
-                let current_hash = self.hashes[new_block_number as usize - 1];
-                let current_block = &self.data[&current_hash];
-
-                let prev_block_hash = self.hashes[new_block_number as usize - 1];
-                let current_distance = current_block.proposer.distance(prev_block_hash);
-                let new_distance = block.proposer.distance(prev_block_hash);
-                if current_distance > new_distance {
-                    self.hashes.truncate(new_block_number as usize);
-                    self.append_unchecked(block);
-                    return BlockAppendResult::NeedSync(new_block_number + 1);
-                }
-
-                BlockAppendResult::None
-            }
-        }
-    }
+    use clap::Parser;
+    use k256::ecdsa::SigningKey;
+    use ledger_types::{Block, BlockData, TransactionBuilder, B256};
 
-    pub fn last_hash(&self) -> B256 {
-        self.hashes.last().copied().unwrap_or_default()
-    }
+    use crate::blocks::{Blocks, ChainConfig};
+    use crate::{key_from_seed, name_of, validate_announce_socket, verify_chain_file, Params};
 
-    fn append_unchecked(&mut self, block: Block) {
-        self.hashes.push(block.hash);
-        self.data.insert(block.hash, block);
+    fn validate(s: &str) -> Result<SocketAddr, String> {
+        validate_announce_socket(s.parse().unwrap())
     }
 
-    pub fn data_by_number(&self, number: u64) -> Option<&Block> {
-        let hash = self.hashes.get(number as usize)?;
-        self.data.get(hash)
+    #[test]
+    fn name_from_address() {
+        let address = B256::hash_of("node");
+        let name = name_of(&address);
+        assert_eq!(name, name_of(&address));
+        assert_eq!(name.split('-').count(), 2);
+        assert_ne!(name, name_of(&B256::hash_of("other")));
     }
 
-    pub fn balance_of(&self, address: B256) -> u64 {
-        let transactions_iter = self
-            .hashes
-            .iter()
-            .flat_map(|hash| &self.data[hash].data.transactions);
-        let mut balance = 1000;
-        for transaction in transactions_iter {
-            if transaction.data.to == address {
-                balance += transaction.data.amount;
-            }
-            if transaction.from == address {
-                balance = balance.saturating_sub(transaction.data.amount);
-            }
-        }
-        balance
+    #[test]
+    fn seeded_key() {
+        let address = |seed| B256::address_of(key_from_seed(seed).verifying_key());
+        assert_eq!(address(7), address(7));
+        assert_ne!(address(7), address(8));
+        // Pinned, so seeded keys survive dependency upgrades.
+        let key = B256::from_hex_string(
+            "cd2662154e6d76b2b2b92e70c0cac3ccf534f9b74eb5b89819ec509083d00a50",
+        )
+        .unwrap();
+        assert_eq!(key_from_seed(1).to_bytes().as_slice(), key.0);
     }
 
-    pub fn contains_tx(&self, hash: B256) -> bool {
-        self.hashes
-            .iter()
-            .flat_map(|hash| &self.data[hash].data.transactions)
-            .find(|tx| tx.hash == hash)
-            .is_some()
+    #[test]
+    fn announce_socket_validation() {
+        assert!(validate("203.0.113.7:50000").is_ok());
+        assert!(validate("[2001:db8::1]:50000").is_ok());
+
+        assert!(validate("0.0.0.0:50000").is_err());
+        assert!(validate("[::]:50000").is_err());
+        assert!(validate("203.0.113.7:0").is_err());
     }
-}
 
-#[derive(Debug)]
-pub enum BlockAppendResult {
-    NeedSync(u64),
-    Added,
-    None,
+    #[test]
+    fn verify_chain_files() {
+        assert!(Params::try_parse_from(["node", "--verify-chain", "chain.json"]).is_ok());
+        assert!(Params::try_parse_from(["node"]).is_err());
+
+        let mut blocks = Blocks::default();
+        blocks.append(Block::new_genesis());
+        let signer = SigningKey::from_slice(&[1; 32]).unwrap();
+        let tx = TransactionBuilder::new().amount(10).sign(&signer);
+        let data = BlockData {
+            prev_hash: blocks.last_hash(),
+            number: 1,
+            nonce: 0,
+            timestamp: 0,
+            transactions: vec![tx],
+        };
+        blocks.append(Block::new(data, &signer));
+
+        let dir = std::env::temp_dir();
+        let valid = dir.join(format!("chain-valid-{}.json", std::process::id()));
+        std::fs::write(&valid, blocks.export_json()).unwrap();
+        let mut exported: Vec<Block> = serde_json::from_str(&blocks.export_json()).unwrap();
+        exported[1].data.transactions[0].data.amount = 500;
+        let tampered = dir.join(format!("chain-tampered-{}.json", std::process::id()));
+        std::fs::write(&tampered, serde_json::to_string(&exported).unwrap()).unwrap();
+
+        // Signed correctly, but spends more than the sender has.
+        let tx = TransactionBuilder::new()
+            .amount(5000)
+            .nonce(1)
+            .sign(&signer);
+        let data = BlockData {
+            prev_hash: blocks.last_hash(),
+            number: 2,
+            nonce: 0,
+            timestamp: 0,
+            transactions: vec![tx],
+        };
+        let mut overspending: Vec<Block> = serde_json::from_str(&blocks.export_json()).unwrap();
+        overspending.push(Block::new(data, &signer));
+        let overspent = dir.join(format!("chain-overspent-{}.json", std::process::id()));
+        std::fs::write(&overspent, serde_json::to_string(&overspending).unwrap()).unwrap();
+
+        let verified = verify_chain_file(&valid, ChainConfig::default());
+        let rejected = verify_chain_file(&tampered, ChainConfig::default());
+        let overspend = verify_chain_file(&overspent, ChainConfig::default());
+        std::fs::remove_file(&valid).unwrap();
+        std::fs::remove_file(&tampered).unwrap();
+        std::fs::remove_file(&overspent).unwrap();
+        assert_eq!(
+            verified,
+            Ok(format!(
+                "Chain is valid, height 1 with tip {}",
+                blocks.last_hash()
+            ))
+        );
+        assert_eq!(
+            rejected,
+            Err("inconsistent chain: block 1 has an invalid transaction".to_string())
+        );
+        assert_eq!(overspend, Err("block 2 overspends a balance".to_string()));
+        assert!(verify_chain_file(&valid, ChainConfig::default())
+            .unwrap_err()
+            .starts_with("failed to read the chain"));
+    }
 }
@@ -0,0 +1,131 @@
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Counters of the node shared with the metrics endpoint.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Number of blocks in the chain.
+    pub height: AtomicU64,
+    /// Number of known peers.
+    pub peers: AtomicU64,
+    /// Number of pending transactions.
+    pub pending_transactions: AtomicU64,
+    /// Number of blocks appended since the start.
+    pub blocks_appended: AtomicU64,
+}
+
+impl Metrics {
+    /// Formats the counters in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let metrics = [
+            ("ledger_height", "gauge", &self.height),
+            ("ledger_peers", "gauge", &self.peers),
+            (
+                "ledger_pending_transactions",
+                "gauge",
+                &self.pending_transactions,
+            ),
+            (
+                "ledger_blocks_appended_total",
+                "counter",
+                &self.blocks_appended,
+            ),
+        ];
+
+        let mut text = String::new();
+        for (name, kind, value) in metrics {
+            let value = value.load(Ordering::Relaxed);
+            writeln!(text, "# TYPE {} {}\n{} {}", name, kind, name, value)
+                .expect("metrics should be formatted");
+        }
+        text
+    }
+}
+
+/// Serves the metrics over HTTP at `/metrics` until the listener fails.
+pub fn serve(listener: TcpListener, metrics: Arc<Metrics>) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        if let Err(e) = respond(stream, &metrics) {
+            println!("Failed to serve metrics: {}", e);
+        }
+    }
+}
+
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> std::io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let path = request_line.split_whitespace().nth(1);
+    let (status, body) = match path {
+        Some("/metrics") => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", String::new()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::thread;
+
+    use crate::metrics::{serve, Metrics};
+
+    fn get(listener: &TcpListener, path: &str) -> String {
+        let mut stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn metrics_endpoint() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let metrics = Arc::new(Metrics::default());
+        metrics.height.store(5, Ordering::Relaxed);
+        metrics.peers.store(2, Ordering::Relaxed);
+        metrics.blocks_appended.store(4, Ordering::Relaxed);
+
+        let server = listener.try_clone().unwrap();
+        let served = metrics.clone();
+        thread::spawn(move || serve(server, served));
+
+        let response = get(&listener, "/metrics");
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK"));
+        let values: Vec<(&str, u64)> = body
+            .lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| {
+                let (name, value) = line.split_once(' ').unwrap();
+                (name, value.parse().unwrap())
+            })
+            .collect();
+        assert_eq!(
+            values,
+            [
+                ("ledger_height", 5),
+                ("ledger_peers", 2),
+                ("ledger_pending_transactions", 0),
+                ("ledger_blocks_appended_total", 4),
+            ]
+        );
+
+        assert!(get(&listener, "/other").starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,51 @@
+#[cfg(test)]
+use std::cell::RefCell;
+use std::net::SocketAddr;
+
+use ledger_transport::{Transport, TransportError};
+use serde::Serialize;
+
+/// Way a node sends messages to its peers and clients.
+pub trait Network {
+    fn send(&self, to: SocketAddr, msg: &impl Serialize) -> Result<(), TransportError>;
+}
+
+impl Network for Transport {
+    fn send(&self, to: SocketAddr, msg: &impl Serialize) -> Result<(), TransportError> {
+        Transport::send(self, to, msg).map(|_| ())
+    }
+}
+
+/// Network dropping all messages, for running a node offline.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NullNetwork;
+
+impl Network for NullNetwork {
+    fn send(&self, _to: SocketAddr, _msg: &impl Serialize) -> Result<(), TransportError> {
+        Ok(())
+    }
+}
+
+/// Network keeping the sent messages, so tests can deliver them in-process.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct MockNetwork {
+    sent: RefCell<Vec<(SocketAddr, serde_json::Value)>>,
+}
+
+#[cfg(test)]
+impl MockNetwork {
+    /// Takes the messages sent since the last call, in the order of sending.
+    pub fn take_sent(&self) -> Vec<(SocketAddr, serde_json::Value)> {
+        self.sent.take()
+    }
+}
+
+#[cfg(test)]
+impl Network for MockNetwork {
+    fn send(&self, to: SocketAddr, msg: &impl Serialize) -> Result<(), TransportError> {
+        let value = serde_json::to_value(msg).map_err(TransportError::Serialize)?;
+        self.sent.borrow_mut().push((to, value));
+        Ok(())
+    }
+}
@@ -0,0 +1,2551 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use k256::ecdsa::SigningKey;
+use ledger_transport::{Transport, TransportConfig};
+use ledger_types::{
+    AckStatus, Block, BlockData, BlockView, HealthStatus, Message, NodeInfo, RejectReason, Reply,
+    ReplyTo, Response, Signature, Signer, SlashingEvidence, StateSnapshot, Transaction,
+    TransactionBuilder, B256, MAX_BLOCK_SIZE, MAX_BLOCK_TXS,
+};
+use rand::rngs::StdRng;
+use rand::seq::IteratorRandom;
+use rand::SeedableRng;
+use serde::Serialize;
+
+use crate::blocks::{
+    BlockAppendResult, Blocks, ChainConfig, ChainError, DistanceMetric, ImportError, MissingBlock,
+};
+use crate::clock::{elapsed, Clock, SystemClock};
+use crate::metrics::Metrics;
+use crate::network::Network;
+use crate::rate_limit::RateLimiter;
+use crate::recent::RecentSet;
+
+#[cfg(test)]
+mod cluster;
+mod mempool;
+mod record;
+
+/// Tunable parameters of the node.
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub chain: ChainConfig,
+
+    /// Allows proposing blocks without transactions. Empty blocks are valid
+    /// and always accepted from peers, but by default the node doesn't grow
+    /// the chain with them.
+    pub allow_empty_blocks: bool,
+
+    /// Allows creating the genesis of the chain config when the chain is
+    /// empty. Otherwise the node waits for the genesis from its peers.
+    pub create_genesis: bool,
+
+    pub transport: TransportConfig,
+
+    /// Socket address to bind if it differs from the announced `NodeInfo::socket`.
+    pub bind_socket: Option<SocketAddr>,
+
+    /// Number of random peers each gossiped message is forwarded to.
+    /// `None` broadcasts to all of them.
+    pub gossip_fanout: Option<usize>,
+
+    /// Minimal time between proposals. Pending transactions are batched
+    /// until it passes. `None` proposes on every new transaction.
+    pub block_interval: Option<Duration>,
+
+    /// Number of the latest blocks whose bodies are kept. Older final blocks
+    /// are pruned, keeping only their hashes and the balances. `None` keeps
+    /// the whole chain.
+    pub prune_keep: Option<u64>,
+
+    /// Maximal number of pending transactions of a single sender. `None`
+    /// doesn't limit them.
+    pub max_pending_per_sender: Option<usize>,
+
+    /// Maximal number of blocks sent in response to a single sync request.
+    /// `None` sends all of them.
+    pub max_sync_response: Option<u64>,
+
+    /// How long seen transactions and gossiped messages are remembered,
+    /// besides the bound on their number. `None` only bounds the number.
+    pub seen_ttl: Option<Duration>,
+
+    /// File rejected transactions are appended to as JSON lines.
+    pub reject_log: Option<PathBuf>,
+
+    /// Seed of the node's randomness, e.g. the choice of gossip peers.
+    /// Random if not specified.
+    pub rng_seed: Option<u64>,
+
+    /// Order pending transactions are included into proposed blocks in.
+    pub tx_order: TxOrder,
+
+    /// Maximal number of broadcast messages sent to each peer per second.
+    /// Excess messages are dropped. `None` doesn't limit them.
+    pub send_rate: Option<u64>,
+
+    /// File every received message is appended to as JSON lines, to be
+    /// replayed with `Node::replay`.
+    pub record: Option<PathBuf>,
+
+    /// How far in the future of the node's clock a block timestamp may be,
+    /// tolerating peers with slightly different clocks. Blocks further in
+    /// the future are rejected. `None` doesn't check the timestamps.
+    pub max_future_skew: Option<Duration>,
+
+    /// File the pending transactions are saved to and restored from on
+    /// start with `Node::load_mempool`, so they survive restarts.
+    pub mempool: Option<PathBuf>,
+}
+
+/// Order of pending transactions in proposed blocks.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TxOrder {
+    /// The highest fees first, then the lowest nonces.
+    #[default]
+    Fee,
+    /// In the order of arrival, regardless of fees.
+    Fifo,
+    /// By hashes, which no sender controls cheaply.
+    Hash,
+}
+
+pub struct Node<N = Transport> {
+    info: NodeInfo,
+    /// Network the node sends its messages over, the UDP transport unless
+    /// tested in-process.
+    pub transport: N,
+    /// Key identifying the node and signing its proposed blocks. An observer
+    /// (see `NodeInfo::observer`) follows the chain and answers queries, but
+    /// never proposes blocks or relays transactions and blocks.
+    signer: SigningKey,
+    config: Config,
+    others: BTreeMap<B256, NodeInfo>,
+    blocks: Blocks,
+    pending_transactions: HashMap<B256, Transaction>,
+    /// Arrival sequence numbers of the pending transactions.
+    arrivals: HashMap<B256, u64>,
+    next_arrival: u64,
+    /// Subscribers with the time of their subscription.
+    subscribers: HashMap<SocketAddr, u64>,
+    socket_changes: HashMap<B256, u64>,
+    verified_txs: RecentSet,
+    gossiped: RecentSet,
+    /// Transactions recently admitted or mined, which aren't admitted again.
+    seen_txs: RecentSet,
+    /// Number of signature verifications actually performed.
+    verifications: u64,
+    rng: StdRng,
+    last_proposal: Option<u64>,
+    metrics: Arc<Metrics>,
+    clock: Box<dyn Clock>,
+    reject_log: Option<File>,
+    /// Outstanding sync. The node doesn't propose blocks meanwhile, as they
+    /// would build on a stale tip.
+    syncing: Option<SyncState>,
+    send_limiter: Option<RateLimiter>,
+    record: Option<File>,
+    /// First block seen from each proposer at each number near the tip.
+    proposals: HashMap<(B256, u64), Block>,
+    /// Equivocations of proposers near the tip, by proposer and number.
+    evidence: HashMap<(B256, u64), SlashingEvidence>,
+    /// Whether the pending transactions changed since they were last saved.
+    mempool_changed: bool,
+    mempool_saved_at: u64,
+}
+
+/// Sync up to the tip reported by a peer.
+#[derive(Debug, Clone, Copy)]
+struct SyncState {
+    /// Height the chain reaches once synced.
+    target: u64,
+    /// Time the sync started at.
+    since: u64,
+}
+
+/// Why the node didn't propose a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeclineReason {
+    /// Observers never propose blocks.
+    Observer,
+    /// There is no previous block to build on and the node doesn't create
+    /// the genesis.
+    EmptyChain,
+    /// No pending transactions and empty blocks aren't allowed.
+    NoTransactions,
+    /// The node is catching up with its peers.
+    Syncing,
+    /// The proposed block doesn't fit the chain of the node.
+    Inconsistent(ChainError),
+}
+
+/// How long a subscription lives without being renewed.
+const SUBSCRIPTION_TTL: Duration = Duration::from_secs(30);
+
+/// Interval of the periodic housekeeping.
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Interval of re-introducing the node to its peers in the async runtime.
+#[cfg(feature = "tokio")]
+const PING_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Maximal number of peers sent in a single peer table response.
+const PEER_TABLE_PAGE: usize = 64;
+
+/// Maximal number of accounts sent in a single accounts response.
+const ACCOUNTS_PAGE: usize = 256;
+
+/// Time after which an unfinished sync is abandoned and proposing resumes.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Minimal time between two changes of a peer's socket.
+const SOCKET_CHANGE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Number of blocks below and above the tip whose proposers are checked for
+/// equivocation.
+const EQUIVOCATION_WINDOW: u64 = 100;
+
+/// Maximal number of (proposer, number) pairs tracked for equivocation.
+const MAX_TRACKED_PROPOSALS: usize = 10_000;
+
+/// Number of transactions remembered as having a valid signature.
+const VERIFIED_TXS_CAPACITY: usize = 10_000;
+
+/// Number of recently processed transactions protected from replays.
+const SEEN_TXS_CAPACITY: usize = 10_000;
+
+/// Number of remembered broadcast messages.
+const GOSSIPED_CAPACITY: usize = 10_000;
+
+impl Node {
+    pub fn new(signer: SigningKey, mut info: NodeInfo, config: Config) -> Self {
+        let bind_socket = config.bind_socket.unwrap_or(info.socket);
+        let transport = Transport::with_config(bind_socket, config.transport)
+            .unwrap_or_else(|e| panic!("failed to create transport: {}", e));
+        // The system picks a port for port 0, announce the one it picked.
+        if info.socket.port() == 0 {
+            let local = transport
+                .local_addr()
+                .expect("bound socket should have an address");
+            info.socket.set_port(local.port());
+        }
+        Self::with_network(signer, info, config, transport)
+    }
+
+    pub fn run(mut self) {
+        // Wake up periodically even if there are no messages.
+        self.transport
+            .set_read_timeout(Some(TICK_INTERVAL))
+            .expect("read timeout should be set");
+        loop {
+            match self.transport.receive_from() {
+                Ok((message, source)) => self.process_message(message, source),
+                // Woken up to tick.
+                Err(e) if e.is_timeout() => {}
+                Err(e) => println!("Failed to receive: {}", e),
+            }
+            self.tick();
+        }
+    }
+
+    /// Runs the node on the tokio runtime. Besides handling messages, it
+    /// periodically drops expired subscriptions and pings the peers.
+    #[cfg(feature = "tokio")]
+    pub async fn run_async(mut self) {
+        let transport = self
+            .transport
+            .to_async()
+            .unwrap_or_else(|e| panic!("failed to create async transport: {}", e));
+        let mut ticks = tokio::time::interval(TICK_INTERVAL);
+        let mut pings = tokio::time::interval(PING_INTERVAL);
+        loop {
+            tokio::select! {
+                received = transport.receive_from() => match received {
+                    Ok((message, source)) => self.process_message(message, source),
+                    Err(e @ ledger_transport::TransportError::Deserialize(..)) => println!("Failed to receive: {}", e),
+                    Err(e) => {
+                        println!("Stopping, failed to receive: {}", e);
+                        break;
+                    }
+                },
+                _ = ticks.tick() => self.tick(),
+                _ = pings.tick() => self.ping_others(),
+            }
+        }
+    }
+}
+
+impl<N: Network> Node<N> {
+    /// Creates a node sending its messages over the network.
+    pub fn with_network(signer: SigningKey, info: NodeInfo, config: Config, transport: N) -> Self {
+        let others = BTreeMap::new();
+        let mut blocks = Blocks::new(config.chain.clone());
+        log_reorgs(&mut blocks);
+        let pending_transactions = HashMap::new();
+        let subscribers = HashMap::new();
+        let socket_changes = HashMap::new();
+        let verified_txs = RecentSet::new(VERIFIED_TXS_CAPACITY);
+        let gossiped = RecentSet::new(GOSSIPED_CAPACITY);
+        let seen_txs = RecentSet::new(SEEN_TXS_CAPACITY);
+        let reject_log = config.reject_log.as_ref().map(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .expect("failed to open the reject log")
+        });
+        let send_limiter = config.send_rate.map(RateLimiter::new);
+        let record = config.record.as_ref().map(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .expect("failed to open the message record")
+        });
+        let rng = match config.rng_seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        let mut node = Self {
+            transport,
+            info,
+            signer,
+            config,
+            others,
+            blocks,
+            pending_transactions,
+            arrivals: HashMap::new(),
+            next_arrival: 0,
+            subscribers,
+            socket_changes,
+            verified_txs,
+            gossiped,
+            seen_txs,
+            verifications: 0,
+            rng,
+            last_proposal: None,
+            metrics: Arc::default(),
+            clock: Box::new(SystemClock),
+            reject_log,
+            syncing: None,
+            send_limiter,
+            record,
+            proposals: HashMap::new(),
+            evidence: HashMap::new(),
+            mempool_changed: false,
+            mempool_saved_at: 0,
+        };
+
+        let genesis = node.config.chain.genesis.block();
+        node.blocks.append(genesis);
+        node.metrics
+            .height
+            .store(node.blocks.len(), Ordering::Relaxed);
+        node
+    }
+
+    /// Hash and number of the last block, if the chain has any.
+    pub fn tip(&self) -> Option<(B256, u64)> {
+        self.blocks.tip()
+    }
+
+    /// Socket the node announces to its peers.
+    pub fn socket(&self) -> SocketAddr {
+        self.info.socket
+    }
+
+    /// Counters of the node, updated as it runs.
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// Replaces the chain with the imported one and pushes its tip to subscribers.
+    pub fn import_chain(&mut self, json: &str) -> Result<(), ImportError> {
+        self.blocks = Blocks::import_json(json, self.config.chain.clone())?;
+        log_reorgs(&mut self.blocks);
+
+        let blocks = &self.blocks;
+        self.pending_transactions
+            .retain(|hash, _| !blocks.contains_tx(*hash));
+        let pending = &self.pending_transactions;
+        self.arrivals.retain(|hash, _| pending.contains_key(hash));
+        self.mempool_changed = true;
+        self.metrics
+            .height
+            .store(self.blocks.len(), Ordering::Relaxed);
+        self.update_pending_metrics();
+        if let Some(tip) = self.blocks.get(&self.blocks.last_hash()).cloned() {
+            self.notify_subscribers(&tip);
+        }
+        Ok(())
+    }
+
+    /// Replaces the chain with one starting right after the snapshot, which
+    /// must be signed by the trusted address. Later blocks are synced from peers.
+    pub fn bootstrap(
+        &mut self,
+        snapshot: StateSnapshot,
+        signature: &Signature,
+        trusted: B256,
+    ) -> Option<()> {
+        snapshot.verify(signature, trusted)?;
+        println!(
+            "Bootstrapping from snapshot {} at height {}",
+            snapshot.hash(),
+            snapshot.height
+        );
+        self.blocks = Blocks::from_snapshot(snapshot, self.config.chain.clone());
+        log_reorgs(&mut self.blocks);
+        Some(())
+    }
+
+    /// Reverts the chain so the block `height` becomes the tip, e.g. to
+    /// recover from a bad block. `None` if the block isn't kept in full.
+    pub fn rollback_to(&mut self, height: u64) -> Option<()> {
+        let reverted = self.blocks.rollback_to(height)?;
+        println!("Rolled back blocks {:?}", reverted);
+        self.metrics
+            .height
+            .store(self.blocks.len(), Ordering::Relaxed);
+        Some(())
+    }
+
+    /// Periodic housekeeping: drops the expired subscriptions and seen
+    /// hashes, and proposes a batch of pending transactions once the block
+    /// interval passes.
+    pub fn tick(&mut self) {
+        let now = self.clock.now();
+        self.subscribers
+            .retain(|_, subscribed_at| elapsed(now, *subscribed_at) < SUBSCRIPTION_TTL);
+        if let Some(ttl) = self.config.seen_ttl {
+            self.seen_txs.expire(now, ttl);
+            self.gossiped.expire(now, ttl);
+        }
+        if let Some(sync) = self.syncing {
+            if elapsed(now, sync.since) >= SYNC_TIMEOUT {
+                println!("Sync to height {} timed out", sync.target);
+                self.syncing = None;
+            }
+        }
+        self.save_mempool();
+
+        let Some(interval) = self.config.block_interval else {
+            return;
+        };
+        let due = self
+            .last_proposal
+            .is_none_or(|at| elapsed(now, at) >= interval);
+        if due && self.is_leader() {
+            self.last_proposal = Some(now);
+            if let Err(DeclineReason::EmptyChain) = self.propose_block() {
+                println!("Not proposing on an empty chain");
+            }
+        }
+    }
+
+    /// Proposes a block right away if the node is the leader, unless
+    /// proposals are batched by the block interval.
+    fn propose_if_leader(&mut self) {
+        if self.config.block_interval.is_none() && self.is_leader() {
+            if let Err(DeclineReason::EmptyChain) = self.propose_block() {
+                println!("Not proposing on an empty chain");
+            }
+        }
+    }
+
+    /// Introduces the node to the known peers again, so they keep its socket
+    /// up to date.
+    #[cfg(feature = "tokio")]
+    fn ping_others(&mut self) {
+        self.send_to_others(self.hello());
+    }
+
+    fn process_message(&mut self, message: Message, source: SocketAddr) {
+        self.record_message(&message, source);
+        match message {
+            Message::Hello(node_info, signature) => {
+                self.process_hello(node_info, signature, source)
+            }
+            Message::Transaction(tx) => self.process_transaction(tx),
+            Message::SubmitTransaction(reply_to, tx) => {
+                self.process_submit_transaction(reply_to, tx)
+            }
+            Message::Block(block) => self.process_block(block),
+            Message::SyncBlock(sender, start) => self.process_sync_block(sender, start, source),
+            Message::ChainHeight(sender, height) => self.process_chain_height(sender, height),
+            Message::StateRoot(height, root) => self.process_state_root(height, root, source),
+            Message::BalanceOf(reply_to, address) => self.process_balance_of(reply_to, address),
+            Message::BalanceOfAt(reply_to, address, height) => {
+                self.process_balance_of_at(reply_to, address, height)
+            }
+            Message::Subscribe(sender) => self.process_subscribe(sender),
+            Message::GetChain(reply_to) => self.process_get_chain(reply_to),
+            Message::GetTransaction(reply_to, hash) => self.process_get_transaction(reply_to, hash),
+            Message::GetPeerTable(reply_to, start) => self.process_get_peer_table(reply_to, start),
+            Message::GetSnapshot(reply_to, height) => self.process_get_snapshot(reply_to, height),
+            Message::GetBlockView(reply_to, number) => {
+                self.process_get_block_view(reply_to, number)
+            }
+            Message::GetTip(reply_to) => self.process_get_tip(reply_to),
+            Message::GetAccounts(reply_to, start) => self.process_get_accounts(reply_to, start),
+            Message::Evidence(evidence) => self.process_evidence(evidence),
+            Message::HealthCheck(reply_to) => self.process_health_check(reply_to),
+            Message::EstimateInclusion(reply_to, fee) => {
+                self.process_estimate_inclusion(reply_to, fee)
+            }
+        }
+    }
+
+    /// Signed introduction of this node.
+    pub fn hello(&self) -> Message {
+        Message::Hello(self.info.clone(), self.info.sign(&self.signer))
+    }
+
+    fn process_hello(&mut self, node_info: NodeInfo, signature: Signature, source: SocketAddr) {
+        // The name is signed, so it can't be truncated, only rejected.
+        if node_info.verify_name().is_none() {
+            println!("Rejected hello with an invalid name from {}", source);
+            return;
+        }
+        if node_info.verify(&signature).is_none() {
+            println!(
+                "Rejected hello not signed by the owner of {}",
+                node_info.address
+            );
+            return;
+        }
+
+        // A Hello relayed by another peer can't be trusted, so ask the
+        // advertised node to introduce itself directly.
+        if node_info.socket != source {
+            if !self.others.contains_key(&node_info.address)
+                && node_info.address != self.info.address
+            {
+                self.send(node_info.socket, &self.hello());
+            }
+            return;
+        }
+
+        // Don't let the address entry flip between sockets, whether it's a
+        // hijack attempt or a peer causing churn.
+        if let Some(known) = self.others.get(&node_info.address) {
+            let changed_recently = self
+                .socket_changes
+                .get(&node_info.address)
+                .is_some_and(|at| elapsed(self.clock.now(), *at) < SOCKET_CHANGE_INTERVAL);
+            if known.socket != node_info.socket && changed_recently {
+                println!("Rejected socket change of {}", node_info.name);
+                return;
+            }
+        }
+
+        let replaced = self.others.insert(node_info.address, node_info.clone());
+        let peers = self.others.len() as u64;
+        self.metrics.peers.store(peers, Ordering::Relaxed);
+        if replaced.as_ref().map(|known| known.socket) != Some(node_info.socket) {
+            self.socket_changes
+                .insert(node_info.address, self.clock.now());
+        }
+
+        // If the node is new for us, let's say hi to it.
+        if replaced.is_none() && node_info.address != self.info.address {
+            println!("Got hello from {}", node_info.name);
+
+            self.send(node_info.socket, &self.hello());
+            // Late joiners learn they are behind and sync.
+            let height = Message::ChainHeight(self.info.address, self.blocks.len());
+            self.send(node_info.socket, &height);
+            self.gossip(Message::Hello(node_info, signature));
+        }
+    }
+
+    fn process_transaction(&mut self, tx: Transaction) {
+        let (hash, from) = (tx.hash, tx.from);
+        if let Err(reason) = self.admit_transaction(tx) {
+            self.log_rejection(hash, from, reason);
+        }
+    }
+
+    fn process_submit_transaction(&mut self, reply_to: ReplyTo, tx: Transaction) {
+        let (hash, from) = (tx.hash, tx.from);
+        let status = match self.admit_transaction(tx) {
+            Ok(()) => AckStatus::Accepted,
+            Err(reason) => {
+                self.log_rejection(hash, from, reason);
+                AckStatus::Rejected(reason)
+            }
+        };
+        self.reply(reply_to, Response::TransactionAck(hash, status));
+    }
+
+    /// Appends the rejected transaction to the reject log, if there is one.
+    fn log_rejection(&mut self, hash: B256, from: B256, reason: RejectReason) {
+        let Some(file) = &mut self.reject_log else {
+            return;
+        };
+
+        let record = serde_json::json!({
+            "hash": hash,
+            "from": from,
+            "reason": reason,
+            "timestamp": self.clock.now(),
+        });
+        if let Err(e) = writeln!(file, "{}", record) {
+            println!("Failed to write the reject log: {}", e);
+        }
+    }
+
+    /// Validates the transaction, adds it to the pending ones and announces it.
+    fn admit_transaction(&mut self, tx: Transaction) -> Result<(), RejectReason> {
+        if self.pending_transactions.contains_key(&tx.hash) {
+            return Ok(());
+        }
+        self.insert_transaction(tx.clone())?;
+
+        // The transaction is new for us, let's broadcast it.
+        if !self.is_observer() {
+            self.gossip(Message::Transaction(tx));
+            self.propose_if_leader();
+        }
+
+        Ok(())
+    }
+
+    /// Validates the transaction and adds it to the pending ones, without
+    /// gossiping it or proposing a block.
+    fn insert_transaction(&mut self, tx: Transaction) -> Result<(), RejectReason> {
+        if self.pending_transactions.contains_key(&tx.hash) {
+            return Ok(());
+        }
+        if self.seen_txs.contains(&tx.hash) {
+            return Err(RejectReason::AlreadySeen);
+        }
+        if !self.verify_transaction(&tx) {
+            return Err(RejectReason::InvalidSignature);
+        }
+        if self.blocks.contains_tx(tx.hash) {
+            return Err(RejectReason::AlreadyMined);
+        }
+
+        println!("Got tx {}", tx.hash);
+
+        // A pending transaction with the same nonce is only replaced by
+        // a higher fee.
+        let replaced = self
+            .pending_transactions
+            .values()
+            .find(|pending| pending.from == tx.from && pending.data.nonce == tx.data.nonce);
+        let replaced_spend = match replaced {
+            Some(pending) if tx.data.fee <= pending.data.fee => {
+                return Err(RejectReason::NonceConflict);
+            }
+            Some(pending) => pending.data.amount.saturating_add(pending.data.fee),
+            None => 0,
+        };
+        let replaced = replaced.map(|pending| pending.hash);
+
+        // A replacement doesn't add to the sender's pending transactions.
+        if let (None, Some(max)) = (replaced, self.config.max_pending_per_sender) {
+            let pending = self
+                .pending_transactions
+                .values()
+                .filter(|pending| pending.from == tx.from)
+                .count();
+            if pending >= max {
+                return Err(RejectReason::TooManyPending);
+            }
+        }
+
+        let available = self
+            .pending_balance_of(tx.from)
+            .saturating_add(replaced_spend);
+        if available < tx.data.amount.saturating_add(tx.data.fee) {
+            return Err(RejectReason::InsufficientBalance);
+        }
+
+        if let Some(hash) = replaced {
+            println!("Replaced tx {} by {}", hash, tx.hash);
+            self.pending_transactions.remove(&hash);
+            self.arrivals.remove(&hash);
+        }
+        self.seen_txs.insert(tx.hash, self.clock.now());
+        self.pending_transactions.insert(tx.hash, tx.clone());
+        self.arrivals.insert(tx.hash, self.next_arrival);
+        self.next_arrival += 1;
+        self.mempool_changed = true;
+        self.update_pending_metrics();
+        Ok(())
+    }
+
+    /// Balance of the address at the tip minus what its pending transactions spend.
+    fn pending_balance_of(&self, address: B256) -> u64 {
+        let pending_spent = self
+            .pending_transactions
+            .values()
+            .filter(|tx| tx.from == address)
+            .fold(0u64, |spent, tx| {
+                spent
+                    .saturating_add(tx.data.amount)
+                    .saturating_add(tx.data.fee)
+            });
+        self.blocks
+            .balance_of(address)
+            .saturating_sub(pending_spent)
+    }
+
+    /// Verifies the transaction signature. Only the signature recovery is
+    /// cached, so the hash is checked against the data on every call.
+    fn verify_transaction(&mut self, tx: &Transaction) -> bool {
+        if tx.hash != tx.data.hash() {
+            return false;
+        }
+        // The hash commits to the data, so the key stands for the whole transaction.
+        let key = B256::hash_of(
+            [
+                tx.hash.0.as_slice(),
+                &tx.signature.to_bytes(),
+                tx.from.0.as_slice(),
+            ]
+            .concat(),
+        );
+        if self.verified_txs.contains(&key) {
+            return true;
+        }
+
+        self.verifications += 1;
+        if tx.verify().is_none() {
+            return false;
+        }
+        self.verified_txs.insert(key, self.clock.now());
+        true
+    }
+
+    fn is_observer(&self) -> bool {
+        self.info.observer
+    }
+
+    /// Checks if this node is the elected proposer of the next block.
+    /// Observers never take part in the election.
+    fn is_leader(&self) -> bool {
+        let chain = &self.config.chain;
+        if self.is_observer() || !chain.is_validator(&self.info.address) {
+            return false;
+        }
+
+        let candidates = self
+            .others
+            .values()
+            .filter(|info| !info.observer && chain.is_validator(&info.address))
+            .map(|info| info.address)
+            .chain(std::iter::once(self.info.address));
+        elect_leader(chain.distance_metric, self.blocks.last_hash(), candidates)
+            == Some(self.info.address)
+    }
+
+    fn process_block(&mut self, block: Block) {
+        if block.verify().is_none() || block.proposer == self.info.address {
+            return;
+        }
+        if !self.config.chain.is_validator(&block.proposer) {
+            println!("Rejected block {} from non-validator", block.hash);
+            return;
+        }
+        if let Some(skew) = self.config.max_future_skew {
+            let ahead = elapsed(block.data.timestamp, self.clock.now());
+            if ahead > skew {
+                println!(
+                    "Rejected block {} timestamped {} ms in the future",
+                    block.hash,
+                    ahead.as_millis()
+                );
+                return;
+            }
+        }
+        self.detect_equivocation(&block);
+        // The coinbase isn't signed, it is checked against the block reward.
+        if !block
+            .data
+            .transactions
+            .iter()
+            .filter(|tx| !tx.is_coinbase())
+            .all(|tx| self.verify_transaction(tx))
+        {
+            return;
+        }
+
+        println!("Got block {}", block.hash);
+
+        let number = block.data.number;
+        let block_append_result = self.append_block(block.clone());
+
+        // If the block is new for us, let's broadcast it.
+        match block_append_result {
+            BlockAppendResult::NeedSync(start) => {
+                self.start_sync(number.saturating_add(1));
+                self.send_to_others(Message::SyncBlock(self.info.socket, start))
+            }
+            BlockAppendResult::Added if !self.is_observer() => self.gossip(Message::Block(block)),
+            BlockAppendResult::Added => {}
+            BlockAppendResult::None => {
+                let hash = block.hash;
+                if self.blocks.restore(block) {
+                    println!("Restored pruned block {}", hash);
+                }
+            }
+        }
+
+        // The new tip may have made us the leader of the next block.
+        if !self.pending_transactions.is_empty() {
+            self.propose_if_leader();
+        }
+    }
+
+    /// Records evidence if the proposer already signed a different block
+    /// with the same number.
+    fn detect_equivocation(&mut self, block: &Block) {
+        let number = block.data.number;
+        if !self.near_tip(number) {
+            return;
+        }
+        let key = (block.proposer, number);
+        let Some(first) = self.proposals.get(&key) else {
+            if self.proposals.len() < MAX_TRACKED_PROPOSALS {
+                self.proposals.insert(key, block.clone());
+            }
+            return;
+        };
+        if first.hash != block.hash {
+            let evidence = SlashingEvidence {
+                first: first.clone(),
+                second: block.clone(),
+            };
+            self.record_evidence(evidence);
+        }
+    }
+
+    fn process_evidence(&mut self, evidence: SlashingEvidence) {
+        if evidence.verify().is_none() {
+            println!("Rejected invalid equivocation evidence");
+            return;
+        }
+        if !self.near_tip(evidence.first.data.number)
+            || !self.config.chain.is_validator(&evidence.first.proposer)
+        {
+            return;
+        }
+        self.record_evidence(evidence);
+    }
+
+    /// Logs the new evidence and gossips it, so every node learns about the
+    /// equivocation.
+    fn record_evidence(&mut self, evidence: SlashingEvidence) {
+        let key = (evidence.first.proposer, evidence.first.data.number);
+        if self.evidence.contains_key(&key) {
+            return;
+        }
+        println!(
+            "Proposer {} equivocated at height {} with blocks {} and {}",
+            key.0, key.1, evidence.first.hash, evidence.second.hash
+        );
+        self.evidence.insert(key, evidence.clone());
+        if !self.is_observer() {
+            self.gossip(Message::Evidence(evidence));
+        }
+    }
+
+    /// Whether the block number is within `EQUIVOCATION_WINDOW` of the tip.
+    fn near_tip(&self, number: u64) -> bool {
+        let len = self.blocks.len();
+        number.saturating_add(EQUIVOCATION_WINDOW) >= len
+            && number <= len.saturating_add(EQUIVOCATION_WINDOW)
+    }
+
+    fn process_sync_block(&mut self, sender: SocketAddr, start: u64, source: SocketAddr) {
+        println!("Got sync block from {}", source);
+
+        // The carried socket is unchecked, so the blocks only go back to the
+        // requester's host, not to whoever it names.
+        if sender.ip() != source.ip() {
+            println!("Ignored sync block from {} for {}", source, sender);
+            return;
+        }
+
+        // Send blocks after the start to request sender.
+        let height = self.blocks.len();
+        let end = match self.config.max_sync_response {
+            Some(max) => height.min(start.saturating_add(max)),
+            None => height,
+        };
+        for i in start..end {
+            let block = match self.blocks.data_by_number(i) {
+                Ok(block) => block,
+                Err(MissingBlock::Pruned(hash)) => {
+                    println!("Can't send pruned block {}", hash);
+                    return;
+                }
+                Err(MissingBlock::Unknown) => return,
+            };
+            println!("Sending sync block response {}", block.hash);
+            self.send(sender, &Message::Block(block.clone()));
+        }
+        // The requester asks for the rest if the response is capped.
+        self.send(sender, &Message::ChainHeight(self.info.address, height));
+    }
+
+    fn process_chain_height(&mut self, sender: B256, height: u64) {
+        let Some(sender_info) = self.others.get(&sender) else {
+            return;
+        };
+
+        let len = self.blocks.len();
+        if height > len {
+            println!("{} is ahead at height {}", sender_info.name, height);
+            let socket = sender_info.socket;
+            self.start_sync(height);
+            self.send(socket, &Message::SyncBlock(self.info.socket, len));
+        }
+    }
+
+    /// Suppresses proposing until the chain reaches the height.
+    fn start_sync(&mut self, target: u64) {
+        let target = match self.syncing {
+            Some(sync) => sync.target.max(target),
+            None => target,
+        };
+        self.syncing = Some(SyncState {
+            target,
+            since: self.clock.now(),
+        });
+    }
+
+    fn is_syncing(&self) -> bool {
+        self.syncing.is_some()
+    }
+
+    fn process_state_root(&self, height: u64, root: B256, source: SocketAddr) {
+        // Computing older roots replays the chain, so only peers are answered.
+        if !self.others.values().any(|peer| peer.socket == source) {
+            return;
+        }
+        // Roots of blocks we don't have can't be compared.
+        let Some(own_root) = self.blocks.state_root_at(height) else {
+            return;
+        };
+        if own_root != root {
+            println!(
+                "State root at {} differs from {}: {} instead of {}",
+                height, source, root, own_root
+            );
+        }
+    }
+
+    /// Proposes a block of the pending transactions on top of the tip and
+    /// returns its hash, or why the node declined to propose.
+    fn propose_block(&mut self) -> Result<B256, DeclineReason> {
+        if self.is_observer() {
+            return Err(DeclineReason::Observer);
+        }
+        if self.is_syncing() {
+            return Err(DeclineReason::Syncing);
+        }
+        if self.blocks.is_empty() {
+            if !self.config.create_genesis {
+                return Err(DeclineReason::EmptyChain);
+            }
+            // Peers accept only the configured genesis as the first block.
+            self.blocks.append(self.config.chain.genesis.block());
+            self.block_appended();
+        }
+        let number = self.blocks.len();
+        let mut transactions = self.coinbase(number);
+        let hashes = self.select_transactions(&transactions, self.pending_transactions.values());
+        transactions.extend(
+            hashes
+                .iter()
+                .filter_map(|hash| self.pending_transactions.remove(hash)),
+        );
+
+        // A block with just a coinbase is empty for our purposes.
+        if transactions.iter().all(|tx| tx.is_coinbase()) && !self.config.allow_empty_blocks {
+            return Err(DeclineReason::NoTransactions);
+        }
+        let mut data = BlockData {
+            prev_hash: self.blocks.last_hash(),
+            number,
+            nonce: 0,
+            timestamp: self.clock.now(),
+            transactions,
+        };
+        data.mine(self.config.chain.difficulty, self.signer.address());
+
+        let block = Block::new(data, &self.signer);
+        let hash = block.hash;
+
+        if let Err(e) = self.blocks.append_unchecked(block.clone()) {
+            println!("Proposed block doesn't fit the chain: {}", e);
+            let pending = block
+                .data
+                .transactions
+                .into_iter()
+                .filter(|tx| !tx.is_coinbase());
+            self.pending_transactions
+                .extend(pending.map(|tx| (tx.hash, tx)));
+            return Err(DeclineReason::Inconsistent(e));
+        }
+        self.block_appended();
+        self.notify_subscribers(&block);
+        self.gossip(Message::Block(block));
+        self.send_to_others(Message::StateRoot(number, self.blocks.state_root()));
+        Ok(hash)
+    }
+
+    /// Coinbase transaction of the block with the number proposed by this
+    /// node, if there is a block reward.
+    fn coinbase(&self, number: u64) -> Vec<Transaction> {
+        let reward = self.config.chain.block_reward;
+        if reward == 0 {
+            return Vec::new();
+        }
+        vec![Transaction::coinbase(self.info.address, reward, number)]
+    }
+
+    /// Picks the candidates for the next block after the `included`
+    /// transactions and returns their hashes in the configured order, ties
+    /// are resolved deterministically. Transactions beyond the block limit
+    /// stay pending for the next block.
+    fn select_transactions<'a>(
+        &self,
+        included: &[Transaction],
+        candidates: impl IntoIterator<Item = &'a Transaction>,
+    ) -> Vec<B256> {
+        let mut candidates: Vec<&Transaction> = candidates.into_iter().collect();
+        // Transactions which haven't arrived yet come last.
+        let arrival = |tx: &Transaction| self.arrivals.get(&tx.hash).copied().unwrap_or(u64::MAX);
+        candidates.sort_by(|a, b| match self.config.tx_order {
+            TxOrder::Fee => b
+                .data
+                .fee
+                .cmp(&a.data.fee)
+                .then(a.data.nonce.cmp(&b.data.nonce))
+                .then(a.hash.cmp(&b.hash)),
+            TxOrder::Fifo => arrival(a).cmp(&arrival(b)).then(a.hash.cmp(&b.hash)),
+            TxOrder::Hash => a.hash.cmp(&b.hash),
+        });
+        // The template with the largest nonce bounds the size of the block
+        // without the pending transactions.
+        let template = Block {
+            hash: B256::ZERO,
+            data: BlockData {
+                prev_hash: self.blocks.last_hash(),
+                number: self.blocks.len(),
+                nonce: u64::MAX,
+                timestamp: u64::MAX,
+                transactions: included.to_vec(),
+            },
+            proposer: self.info.address,
+            signature: Signature {
+                r: B256::ZERO,
+                s: B256::ZERO,
+                recovery_id: u8::MAX,
+            },
+        };
+        let mut size = template.serialized_size();
+        candidates
+            .into_iter()
+            .take(MAX_BLOCK_TXS - included.len())
+            .take_while(|tx| {
+                // Including the separating comma.
+                size += tx.serialized_size() + 1;
+                size <= MAX_BLOCK_SIZE
+            })
+            .map(|tx| tx.hash)
+            .collect()
+    }
+
+    /// Appends the block to the chain and pushes it to subscribers if it became the tip.
+    fn append_block(&mut self, block: Block) -> BlockAppendResult {
+        let hash = block.hash;
+        let result = self.blocks.append(block);
+        // A duplicate of the tip changes nothing and isn't pushed again.
+        if !matches!(result, BlockAppendResult::None) && self.blocks.last_hash() == hash {
+            let block = self.blocks.get(&hash).unwrap().clone();
+            for tx in &block.data.transactions {
+                self.pending_transactions.remove(&tx.hash);
+                self.seen_txs.insert(tx.hash, self.clock.now());
+            }
+            self.block_appended();
+            self.notify_subscribers(&block);
+        }
+        result
+    }
+
+    /// Housekeeping after a block is appended to the chain.
+    fn block_appended(&mut self) {
+        let pending = &self.pending_transactions;
+        self.arrivals.retain(|hash, _| pending.contains_key(hash));
+        if self
+            .syncing
+            .is_some_and(|sync| self.blocks.len() >= sync.target)
+        {
+            println!("Synced to height {}", self.blocks.len());
+            self.syncing = None;
+        }
+        if let Some(keep) = self.config.prune_keep {
+            self.blocks.prune(keep);
+        }
+        let oldest = self.blocks.len().saturating_sub(EQUIVOCATION_WINDOW);
+        self.proposals.retain(|&(_, number), _| number >= oldest);
+        self.evidence.retain(|&(_, number), _| number >= oldest);
+        self.metrics.blocks_appended.fetch_add(1, Ordering::Relaxed);
+        self.metrics
+            .height
+            .store(self.blocks.len(), Ordering::Relaxed);
+        self.mempool_changed = true;
+        self.update_pending_metrics();
+    }
+
+    fn update_pending_metrics(&self) {
+        let pending = self.pending_transactions.len() as u64;
+        self.metrics
+            .pending_transactions
+            .store(pending, Ordering::Relaxed);
+    }
+
+    fn process_subscribe(&mut self, sender: SocketAddr) {
+        println!("Got subscription from {}", sender);
+        self.subscribers.insert(sender, self.clock.now());
+    }
+
+    /// Pushes the block to subscribers, dropping the expired and unreachable ones.
+    fn notify_subscribers(&mut self, block: &Block) {
+        let message = Message::Block(block.clone());
+        let transport = &self.transport;
+        let now = self.clock.now();
+        self.subscribers.retain(|socket, subscribed_at| {
+            elapsed(now, *subscribed_at) < SUBSCRIPTION_TTL
+                && transport.send(*socket, &message).is_ok()
+        });
+    }
+
+    fn process_balance_of(&self, reply_to: ReplyTo, address: B256) {
+        println!("Processing balance_of from {}", address);
+
+        let balance = self.blocks.balance_of(address);
+        let final_balance = self.blocks.final_balance_of(address);
+        self.reply(reply_to, Response::Balance(balance, final_balance));
+    }
+
+    fn process_balance_of_at(&self, reply_to: ReplyTo, address: B256, height: u64) {
+        println!("Processing balance_of_at {} from {}", height, address);
+
+        let balance = self.blocks.balance_of_at(address, height);
+        let final_balance = self.blocks.final_balance_of_at(address, height);
+        let response = match (balance, final_balance) {
+            (Ok(balance), Ok(final_balance)) => Response::Balance(balance, final_balance),
+            _ => Response::BalancePruned(height),
+        };
+        self.reply(reply_to, response);
+    }
+
+    fn process_get_chain(&self, reply_to: ReplyTo) {
+        println!("Processing get_chain from {}", reply_to.socket);
+
+        self.reply(reply_to, Response::Chain(self.blocks.export_json()));
+    }
+
+    fn process_get_transaction(&self, reply_to: ReplyTo, hash: B256) {
+        println!(
+            "Processing get_transaction {} from {}",
+            hash, reply_to.socket
+        );
+
+        let found = self.blocks.find_transaction(hash);
+        self.reply(reply_to, Response::Transaction(found));
+    }
+
+    fn process_get_peer_table(&self, reply_to: ReplyTo, start: u64) {
+        println!("Processing get_peer_table from {}", reply_to.socket);
+
+        let page = self
+            .others
+            .values()
+            .skip(usize::try_from(start).unwrap_or(usize::MAX))
+            .take(PEER_TABLE_PAGE)
+            .cloned()
+            .collect();
+        let total = self.others.len() as u64;
+        self.reply(reply_to, Response::PeerTable(total, page));
+    }
+
+    /// Answers right away without walking the chain, so it's cheap to probe
+    /// often.
+    fn process_health_check(&self, reply_to: ReplyTo) {
+        let status = HealthStatus {
+            height: self.blocks.len(),
+            peers: self.others.len() as u64,
+            syncing: self.is_syncing(),
+        };
+        self.reply(reply_to, Response::Health(status));
+    }
+
+    fn process_get_accounts(&self, reply_to: ReplyTo, start: u64) {
+        println!("Processing get_accounts from {}", reply_to.socket);
+
+        let accounts = self.blocks.accounts();
+        let total = accounts.len() as u64;
+        let page = accounts
+            .into_iter()
+            .skip(usize::try_from(start).unwrap_or(usize::MAX))
+            .take(ACCOUNTS_PAGE)
+            .collect();
+        self.reply(reply_to, Response::Accounts(total, page));
+    }
+
+    fn process_get_snapshot(&self, reply_to: ReplyTo, height: u64) {
+        println!(
+            "Processing get_snapshot at {} from {}",
+            height, reply_to.socket
+        );
+
+        let signed = self.blocks.snapshot_at(height).map(|snapshot| {
+            let signature = snapshot.sign(&self.signer);
+            (snapshot, signature)
+        });
+        self.reply(reply_to, Response::Snapshot(signed));
+    }
+
+    fn process_get_block_view(&self, reply_to: ReplyTo, number: u64) {
+        println!(
+            "Processing get_block_view {} from {}",
+            number, reply_to.socket
+        );
+
+        let view = self.blocks.data_by_number(number).ok().map(|block| {
+            let proposer_info = if block.proposer == self.info.address {
+                Some(self.info.clone())
+            } else {
+                self.others.get(&block.proposer).cloned()
+            };
+            BlockView {
+                hash: block.hash,
+                number: block.data.number,
+                prev_hash: block.data.prev_hash,
+                nonce: block.data.nonce,
+                proposer: block.proposer,
+                proposer_info,
+                transactions: block.data.transactions.clone(),
+            }
+        });
+        // Large views are split into chunks by the transport.
+        self.reply(reply_to, Response::BlockView(view));
+    }
+
+    fn process_get_tip(&self, reply_to: ReplyTo) {
+        println!("Processing get_tip from {}", reply_to.socket);
+        self.reply(reply_to, Response::Tip(self.blocks.tip()));
+    }
+
+    fn process_estimate_inclusion(&self, reply_to: ReplyTo, fee: u64) {
+        println!(
+            "Processing estimate_inclusion {} from {}",
+            fee, reply_to.socket
+        );
+
+        // The probe loses all ties and has the largest amount, so the
+        // estimate errs on the side of exclusion.
+        let probe = TransactionBuilder::new()
+            .amount(u64::MAX)
+            .fee(fee)
+            .nonce(u64::MAX)
+            .sign(&self.signer);
+        let coinbase = self.coinbase(self.blocks.len());
+        let candidates = self
+            .pending_transactions
+            .values()
+            .chain(std::iter::once(&probe));
+        let included = self
+            .select_transactions(&coinbase, candidates)
+            .contains(&probe.hash);
+        self.reply(reply_to, Response::Inclusion(included));
+    }
+
+    fn reply(&self, reply_to: ReplyTo, response: Response) {
+        let reply = Reply {
+            request_id: reply_to.request_id,
+            response,
+        };
+        self.send(reply_to.socket, &reply);
+    }
+
+    /// Broadcasts the message unless it was already broadcast recently.
+    fn gossip(&mut self, msg: Message) {
+        if let Some(id) = gossip_id(&msg) {
+            if !self.gossiped.insert(id, self.clock.now()) {
+                return;
+            }
+        }
+        let Some(fanout) = self.config.gossip_fanout else {
+            self.send_to_others(msg);
+            return;
+        };
+
+        let peers: Vec<SocketAddr> = self
+            .others
+            .values()
+            .map(|peer| peer.socket)
+            .choose_multiple(&mut self.rng, fanout);
+        for peer in peers {
+            self.send_limited(peer, &msg);
+        }
+    }
+
+    fn send_to_others(&mut self, msg: Message) {
+        let others: Vec<SocketAddr> = self.others.values().map(|other| other.socket).collect();
+        for other in others {
+            self.send_limited(other, &msg);
+        }
+    }
+
+    /// Sends the message unless the peer's send rate is exceeded.
+    fn send_limited(&mut self, to: SocketAddr, msg: &Message) {
+        if let Some(limiter) = &mut self.send_limiter {
+            if !limiter.try_acquire(to, self.clock.now()) {
+                println!("Dropped message to {} over the send rate", to);
+                return;
+            }
+        }
+        self.send(to, msg);
+    }
+
+    /// Sends the message, logging a failure.
+    fn send(&self, to: SocketAddr, msg: &impl Serialize) {
+        if let Err(e) = self.transport.send(to, msg) {
+            println!("Failed to send to {}: {}", to, e);
+        }
+    }
+}
+
+/// Logs the blocks replaced by reorgs of the chain.
+fn log_reorgs(blocks: &mut Blocks) {
+    blocks.set_reorg_handler(|reorg| {
+        for block in &reorg.applied {
+            println!(
+                "Reorg reverted blocks {}..{} for {}",
+                reorg.reverted.start, reorg.reverted.end, block.hash
+            );
+        }
+    });
+}
+
+/// Identifies a gossiped message for deduplication.
+fn gossip_id(msg: &Message) -> Option<B256> {
+    match msg {
+        Message::Hello(info, _) => Some(B256::hash_of(
+            [
+                info.address.0.as_slice(),
+                info.socket.to_string().as_bytes(),
+            ]
+            .concat(),
+        )),
+        Message::Transaction(tx) => Some(tx.hash),
+        Message::Block(block) => Some(block.hash),
+        Message::Evidence(evidence) => Some(evidence.id()),
+        _ => None,
+    }
+}
+
+/// Elects the proposer of the block following `prev_hash`: the candidate
+/// whose address is the closest to the hash by the metric.
+pub fn elect_leader(
+    metric: DistanceMetric,
+    prev_hash: B256,
+    candidates: impl IntoIterator<Item = B256>,
+) -> Option<B256> {
+    candidates
+        .into_iter()
+        .min_by_key(|address| metric.distance(*address, prev_hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use k256::ecdsa::SigningKey;
+    use ledger_transport::{Transport, TransportConfig};
+    use ledger_types::{
+        AckStatus, Block, BlockData, Message, NodeInfo, PublicKey, RejectReason, Reply, ReplyTo,
+        Response, Signature, SlashingEvidence, StateSnapshot, Transaction, TransactionBuilder,
+        B256, MAX_BLOCK_SIZE, MAX_BLOCK_TXS, MAX_MEMO_LEN, MAX_NAME_LEN,
+    };
+
+    use crate::blocks::{BlockAppendResult, Blocks, DistanceMetric};
+    use crate::clock::{Clock, MockClock};
+    use crate::network::MockNetwork;
+    use crate::node::{
+        elect_leader, Config, DeclineReason, Node, TxOrder, PEER_TABLE_PAGE,
+        SOCKET_CHANGE_INTERVAL, SYNC_TIMEOUT,
+    };
+
+    fn node_info(name: &str, key: u8, socket: &str) -> NodeInfo {
+        let signer = SigningKey::from_slice(&[key; 32]).unwrap();
+        NodeInfo {
+            name: name.to_string(),
+            address: B256::address_of(signer.verifying_key()),
+            public_key: PublicKey::from_key(signer.verifying_key()),
+            socket: socket.parse().unwrap(),
+            observer: false,
+        }
+    }
+
+    /// Signs the info with the `key` owner's signing key.
+    fn sign(info: &NodeInfo, key: u8) -> Signature {
+        info.sign(&SigningKey::from_slice(&[key; 32]).unwrap())
+    }
+
+    fn test_node() -> Node {
+        let signer = SigningKey::from_slice(&[1; 32]).unwrap();
+        let info = node_info("test", 1, "127.0.0.1:0");
+        Node::new(signer, info, Config::default())
+    }
+
+    fn observer_node() -> Node {
+        let mut info = node_info("observer", 1, "127.0.0.1:0");
+        info.observer = true;
+        Node::new(
+            SigningKey::from_slice(&[1; 32]).unwrap(),
+            info,
+            Config::default(),
+        )
+    }
+
+    /// Sends the request, made for a fresh client's reply address, to the
+    /// node and returns the response the client receives.
+    fn query(node: &mut Node, request: impl FnOnce(ReplyTo) -> Message) -> Response {
+        let client = Transport::new("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(1)));
+        let reply_to = ReplyTo {
+            socket: client.local_addr().unwrap(),
+            request_id: 7,
+        };
+        node.process_message(request(reply_to), reply_to.socket);
+        let reply = client.receive::<Reply>().unwrap();
+        assert_eq!(reply.request_id, reply_to.request_id);
+        reply.response
+    }
+
+    #[test]
+    fn import_chain_updates_tip() {
+        let mut exporter = test_node();
+        let tx = TransactionBuilder::new().amount(10).sign(&exporter.signer);
+        // The only validator proposes the block right away.
+        exporter.admit_transaction(tx.clone()).unwrap();
+        assert_eq!(exporter.blocks.len(), 2);
+        let chain = exporter.blocks.export_json();
+
+        let mut info = node_info("observer", 1, "127.0.0.1:40000");
+        info.observer = true;
+        let signer = SigningKey::from_slice(&[1; 32]).unwrap();
+        let mut node = Node::with_network(signer, info, Config::default(), MockNetwork::default());
+        node.admit_transaction(tx.clone()).unwrap();
+        assert_eq!(
+            node.metrics().pending_transactions.load(Ordering::Relaxed),
+            1
+        );
+        let subscriber: SocketAddr = "127.0.0.1:40001".parse().unwrap();
+        node.subscribers.insert(subscriber, node.clock.now());
+        node.transport.take_sent();
+
+        node.import_chain(&chain).unwrap();
+        let metrics = node.metrics();
+        assert_eq!(metrics.height.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.pending_transactions.load(Ordering::Relaxed), 0);
+        assert!(node.pending_transactions.is_empty());
+        let sent = node.transport.take_sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, subscriber);
+        assert_eq!(
+            sent[0].1["Block"]["hash"],
+            serde_json::to_value(exporter.blocks.last_hash()).unwrap()
+        );
+    }
+
+    #[test]
+    fn tip_pushed_once() {
+        let info = node_info("test", 1, "127.0.0.1:40000");
+        let signer = SigningKey::from_slice(&[1; 32]).unwrap();
+        let mut node = Node::with_network(signer, info, Config::default(), MockNetwork::default());
+        let subscriber: SocketAddr = "127.0.0.1:40001".parse().unwrap();
+        node.subscribers.insert(subscriber, node.clock.now());
+
+        let proposer = SigningKey::from_slice(&[2; 32]).unwrap();
+        let tx = TransactionBuilder::new().amount(10).sign(&proposer);
+        let block = Block::new(
+            BlockData {
+                prev_hash: node.blocks.last_hash(),
+                number: 1,
+                nonce: 0,
+                timestamp: 0,
+                transactions: vec![tx],
+            },
+            &proposer,
+        );
+        node.process_block(block.clone());
+        node.process_block(block);
+
+        let pushed = node
+            .transport
+            .take_sent()
+            .into_iter()
+            .filter(|(to, msg)| *to == subscriber && msg.get("Block").is_some())
+            .count();
+        assert_eq!(pushed, 1);
+    }
+
+    #[test]
+    fn balance_before_snapshot_pruned() {
+        let info = node_info("test", 1, "127.0.0.1:40000");
+        let signer = SigningKey::from_slice(&[1; 32]).unwrap();
+        let mut node = Node::with_network(signer, info, Config::default(), MockNetwork::default());
+        let trusted = SigningKey::from_slice(&[2; 32]).unwrap();
+        let snapshot = StateSnapshot {
+            height: 5,
+            block_hash: B256::hash_of("block"),
+            balances: [(node.info.address, 50)].into(),
+        };
+        let signature = snapshot.sign(&trusted);
+        node.bootstrap(
+            snapshot,
+            &signature,
+            B256::address_of(trusted.verifying_key()),
+        )
+        .unwrap();
+
+        let reply_to = ReplyTo {
+            socket: "127.0.0.1:40001".parse().unwrap(),
+            request_id: 1,
+        };
+        let address = node.info.address;
+        for height in [5, 2] {
+            node.process_balance_of_at(reply_to, address, height);
+        }
+        let responses: Vec<Response> = node
+            .transport
+            .take_sent()
+            .into_iter()
+            .map(|(_, msg)| serde_json::from_value::<Reply>(msg).unwrap().response)
+            .collect();
+        assert!(matches!(
+            responses[..],
+            [Response::Balance(50, 50), Response::BalancePruned(2)]
+        ));
+    }
+
+    #[test]
+    fn bootstrap_from_trusted_snapshot() {
+        let mut node = test_node();
+        let trusted = SigningKey::from_slice(&[2; 32]).unwrap();
+        let snapshot = StateSnapshot {
+            height: 5,
+            block_hash: B256::hash_of("block"),
+            balances: [(node.info.address, 50)].into(),
+        };
+        let signature = snapshot.sign(&trusted);
+
+        let untrusted = node_info("untrusted", 3, "127.0.0.1:0").address;
+        assert!(node
+            .bootstrap(snapshot.clone(), &signature, untrusted)
+            .is_none());
+        assert_eq!(node.blocks.len(), 1);
+
+        node.bootstrap(
+            snapshot,
+            &signature,
+            B256::address_of(trusted.verifying_key()),
+        )
+        .unwrap();
+        assert_eq!(node.blocks.len(), 6);
+        assert_eq!(node.blocks.balance_of(node.info.address), 50);
+    }
+
+    #[test]
+    fn metrics_updated() {
+        let mut node = test_node();
+        let metrics = node.metrics();
+
+        let peer = node_info("peer", 2, "127.0.0.1:40001");
+        node.process_hello(peer.clone(), sign(&peer, 2), peer.socket);
+        assert_eq!(metrics.peers.load(Ordering::Relaxed), 1);
+
+        node.config.block_interval = Some(Duration::from_secs(60));
+        let tx = TransactionBuilder::new().amount(10).sign(&node.signer);
+        node.process_transaction(tx);
+        assert_eq!(metrics.pending_transactions.load(Ordering::Relaxed), 1);
+
+        node.propose_block().unwrap();
+        assert_eq!(metrics.height.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.blocks_appended.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.pending_transactions.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn hello_address_hijack() {
+        let mut node = test_node();
+        let clock = MockClock::default();
+        node.clock = Box::new(clock.clone());
+
+        let peer = node_info("peer", 2, "127.0.0.1:40001");
+        node.process_hello(peer.clone(), sign(&peer, 2), peer.socket);
+
+        let mut hijacker = peer.clone();
+        hijacker.name = "hijacker".to_string();
+        hijacker.socket = "127.0.0.1:40002".parse().unwrap();
+        node.process_hello(hijacker.clone(), sign(&hijacker, 2), hijacker.socket);
+
+        let known = &node.others[&peer.address];
+        assert_eq!(known.name, "peer");
+        assert_eq!(known.socket, peer.socket);
+
+        // Once the socket settles, it may change again.
+        clock.advance(SOCKET_CHANGE_INTERVAL);
+        node.process_hello(hijacker.clone(), sign(&hijacker, 2), hijacker.socket);
+        assert_eq!(node.others[&peer.address].socket, hijacker.socket);
+    }
+
+    #[test]
+    fn hello_with_mismatched_key() {
+        let mut node = test_node();
+
+        let mut peer = node_info("peer", 2, "127.0.0.1:40001");
+        peer.public_key = node_info("other", 3, "127.0.0.1:40001").public_key;
+        node.process_hello(peer.clone(), sign(&peer, 2), peer.socket);
+
+        assert!(!node.others.contains_key(&peer.address));
+    }
+
+    #[test]
+    fn hello_with_invalid_name() {
+        let mut node = test_node();
+
+        let long = node_info(&"a".repeat(MAX_NAME_LEN + 1), 2, "127.0.0.1:40001");
+        node.process_hello(long.clone(), sign(&long, 2), long.socket);
+        assert!(!node.others.contains_key(&long.address));
+
+        let unprintable = node_info("peer\n", 2, "127.0.0.1:40001");
+        node.process_hello(
+            unprintable.clone(),
+            sign(&unprintable, 2),
+            unprintable.socket,
+        );
+        assert!(!node.others.contains_key(&unprintable.address));
+
+        let peer = node_info(&"a".repeat(MAX_NAME_LEN), 2, "127.0.0.1:40001");
+        node.process_hello(peer.clone(), sign(&peer, 2), peer.socket);
+        assert!(node.others.contains_key(&peer.address));
+    }
+
+    #[test]
+    fn forged_hello() {
+        let mut node = test_node();
+
+        let peer = node_info("peer", 2, "127.0.0.1:40001");
+        node.process_hello(peer.clone(), sign(&peer, 3), peer.socket);
+
+        assert!(!node.others.contains_key(&peer.address));
+    }
+
+    #[test]
+    fn hello_with_forged_socket() {
+        let mut node = test_node();
+
+        let peer = node_info("peer", 2, "127.0.0.1:40001");
+        let source: SocketAddr = "127.0.0.1:40002".parse().unwrap();
+        node.process_hello(peer.clone(), sign(&peer, 2), source);
+
+        assert!(!node.others.contains_key(&peer.address));
+    }
+
+    #[test]
+    fn leader_election() {
+        let peers = [1, 2, 3].map(|key| node_info("peer", key, "127.0.0.1:40001").address);
+
+        for prev_hash in [B256::ZERO, B256([0xff; 32]), B256::hash_of("block")] {
+            let leader = elect_leader(DistanceMetric::Numeric, prev_hash, peers).unwrap();
+            for peer in peers {
+                assert!(leader.distance(prev_hash) <= peer.distance(prev_hash));
+            }
+
+            // The result doesn't depend on the candidates order.
+            let mut reversed = peers;
+            reversed.reverse();
+            assert_eq!(
+                elect_leader(DistanceMetric::Numeric, prev_hash, reversed),
+                Some(leader)
+            );
+        }
+
+        assert_eq!(elect_leader(DistanceMetric::Numeric, B256::ZERO, []), None);
+    }
+
+    #[test]
+    fn xor_leader_election() {
+        let prev_hash = B256::hash_of("block");
+        let peers: Vec<B256> = (2..10)
+            .map(|key| node_info("peer", key, "127.0.0.1:0").address)
+            .collect();
+
+        let leader = elect_leader(DistanceMetric::Xor, prev_hash, peers.clone()).unwrap();
+        for peer in peers {
+            assert!(leader.xor_distance(prev_hash) <= peer.xor_distance(prev_hash));
+        }
+    }
+
+    #[test]
+    fn only_leader_proposes() {
+        let mut node = test_node();
+        let peer = node_info("peer", 2, "127.0.0.1:40001");
+        node.process_hello(peer.clone(), sign(&peer, 2), peer.socket);
+
+        let candidates = [node.info.address, peer.address];
+        let leader =
+            elect_leader(DistanceMetric::Numeric, node.blocks.last_hash(), candidates).unwrap();
+        assert_eq!(node.is_leader(), leader == node.info.address);
+    }
+
+    #[test]
+    fn ephemeral_port_announced() {
+        let node = test_node();
+        assert_ne!(node.socket().port(), 0);
+        assert_eq!(node.transport.local_addr(), Some(node.socket()));
+    }
+
+    #[test]
+    fn no_empty_block_proposal() {
+        let mut node = test_node();
+        assert_eq!(node.propose_block(), Err(DeclineReason::NoTransactions));
+        assert_eq!(node.blocks.len(), 1);
+
+        node.config.allow_empty_blocks = true;
+        node.propose_block().unwrap();
+        assert_eq!(node.blocks.len(), 2);
+    }
+
+    #[test]
+    fn peer_table_pages() {
+        let mut node = test_node();
+        for i in 0..=PEER_TABLE_PAGE {
+            let info = node_info(&format!("peer{}", i), i as u8 + 2, "127.0.0.1:1");
+            node.others.insert(info.address, info);
+        }
+
+        let Response::PeerTable(total, first_page) =
+            query(&mut node, |reply_to| Message::GetPeerTable(reply_to, 0))
+        else {
+            panic!("unexpected response");
+        };
+        assert_eq!(total, PEER_TABLE_PAGE as u64 + 1);
+        assert_eq!(first_page.len(), PEER_TABLE_PAGE);
+
+        let Response::PeerTable(_, last_page) = query(&mut node, |reply_to| {
+            Message::GetPeerTable(reply_to, PEER_TABLE_PAGE as u64)
+        }) else {
+            panic!("unexpected response");
+        };
+        assert_eq!(last_page.len(), 1);
+        assert!(first_page
+            .iter()
+            .all(|peer| peer.address != last_page[0].address));
+    }
+
+    #[test]
+    fn block_view() {
+        let mut node = test_node();
+        node.config.block_interval = Some(Duration::from_secs(60));
+        for amount in 0..50 {
+            let tx = TransactionBuilder::new().amount(amount).sign(&node.signer);
+            node.pending_transactions.insert(tx.hash, tx);
+        }
+        node.propose_block().unwrap();
+
+        // The view exceeds the MTU, so it arrives in chunks.
+        let Response::BlockView(Some(view)) =
+            query(&mut node, |reply_to| Message::GetBlockView(reply_to, 1))
+        else {
+            panic!("unexpected response");
+        };
+        let block = node.blocks.data_by_number(1).unwrap();
+        assert_eq!(view.hash, block.hash);
+        assert_eq!(view.transactions.len(), 50);
+        assert_eq!(view.proposer_info.unwrap().name, "test");
+
+        assert!(matches!(
+            query(&mut node, |reply_to| Message::GetBlockView(reply_to, 2)),
+            Response::BlockView(None)
+        ));
+    }
+
+    #[test]
+    fn submitted_transaction_ack() {
+        let mut node = test_node();
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+        let tx = TransactionBuilder::new().amount(100).sign(&signer);
+        let hash = tx.hash;
+        assert!(matches!(
+            query(&mut node, |reply_to| Message::SubmitTransaction(reply_to, tx)),
+            Response::TransactionAck(acked, AckStatus::Accepted) if acked == hash
+        ));
+
+        let too_much = TransactionBuilder::new().amount(1_000_000).sign(&signer);
+        assert!(matches!(
+            query(&mut node, |reply_to| Message::SubmitTransaction(
+                reply_to, too_much
+            )),
+            Response::TransactionAck(_, AckStatus::Rejected(RejectReason::InsufficientBalance))
+        ));
+    }
+
+    #[test]
+    fn pending_double_spend() {
+        let mut node = test_node();
+        node.config.block_interval = Some(Duration::from_secs(60));
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+
+        let first = TransactionBuilder::new().amount(600).sign(&signer);
+        assert_eq!(node.admit_transaction(first), Ok(()));
+        let second = TransactionBuilder::new().amount(500).nonce(1).sign(&signer);
+        assert_eq!(
+            node.admit_transaction(second),
+            Err(RejectReason::InsufficientBalance)
+        );
+        let third = TransactionBuilder::new()
+            .amount(300)
+            .fee(100)
+            .nonce(2)
+            .sign(&signer);
+        assert_eq!(node.admit_transaction(third), Ok(()));
+    }
+
+    #[test]
+    fn pending_per_sender_limited() {
+        let mut node = test_node();
+        node.config.block_interval = Some(Duration::from_secs(60));
+        node.config.max_pending_per_sender = Some(3);
+        let flooder = SigningKey::from_slice(&[2; 32]).unwrap();
+        let other = SigningKey::from_slice(&[3; 32]).unwrap();
+
+        for nonce in 0..3 {
+            let tx = TransactionBuilder::new().nonce(nonce).sign(&flooder);
+            assert_eq!(node.admit_transaction(tx), Ok(()));
+        }
+        let surplus = TransactionBuilder::new().nonce(3).sign(&flooder);
+        assert_eq!(
+            node.admit_transaction(surplus),
+            Err(RejectReason::TooManyPending)
+        );
+
+        let tx = TransactionBuilder::new().amount(10).sign(&other);
+        assert_eq!(node.admit_transaction(tx), Ok(()));
+        assert_eq!(node.pending_transactions.len(), 4);
+    }
+
+    #[test]
+    fn replace_by_fee() {
+        let mut node = test_node();
+        node.config.block_interval = Some(Duration::from_secs(60));
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+
+        let stuck = TransactionBuilder::new().amount(900).fee(1).sign(&signer);
+        assert_eq!(node.admit_transaction(stuck.clone()), Ok(()));
+
+        // The replaced transaction's spending doesn't count against the balance.
+        let replacement = TransactionBuilder::new().amount(900).fee(2).sign(&signer);
+        assert_eq!(node.admit_transaction(replacement.clone()), Ok(()));
+        assert!(!node.pending_transactions.contains_key(&stuck.hash));
+        assert!(node.pending_transactions.contains_key(&replacement.hash));
+
+        let cheaper = TransactionBuilder::new().amount(100).fee(2).sign(&signer);
+        assert_eq!(
+            node.admit_transaction(cheaper),
+            Err(RejectReason::NonceConflict)
+        );
+        assert_eq!(node.pending_transactions.len(), 1);
+    }
+
+    #[test]
+    fn block_size_limited() {
+        let mut node = test_node();
+        node.config.block_interval = Some(Duration::from_secs(60));
+        for amount in 0..MAX_BLOCK_TXS as u64 {
+            let tx = TransactionBuilder::new()
+                .amount(amount)
+                .memo([0xff; MAX_MEMO_LEN])
+                .sign(&SigningKey::from_slice(&[2; 32]).unwrap());
+            node.pending_transactions.insert(tx.hash, tx);
+        }
+
+        node.propose_block().unwrap();
+        let block = node.blocks.data_by_number(1).unwrap();
+        assert!(block.serialized_size() <= MAX_BLOCK_SIZE);
+        block.verify().unwrap();
+        assert!(!node.pending_transactions.is_empty());
+        assert_eq!(
+            block.data.transactions.len() + node.pending_transactions.len(),
+            MAX_BLOCK_TXS
+        );
+    }
+
+    #[test]
+    fn inclusion_estimate() {
+        let mut node = test_node();
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+        for nonce in 0..MAX_BLOCK_TXS as u64 {
+            let tx = TransactionBuilder::new().fee(5).nonce(nonce).sign(&signer);
+            node.pending_transactions.insert(tx.hash, tx);
+        }
+
+        assert!(matches!(
+            query(&mut node, |reply_to| Message::EstimateInclusion(
+                reply_to, 5
+            )),
+            Response::Inclusion(false)
+        ));
+        assert!(matches!(
+            query(&mut node, |reply_to| Message::EstimateInclusion(
+                reply_to, 6
+            )),
+            Response::Inclusion(true)
+        ));
+    }
+
+    #[test]
+    fn highest_fees_proposed_first() {
+        let mut node = test_node();
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+        let low_fee: Vec<Transaction> = (0..MAX_BLOCK_TXS as u64)
+            .map(|nonce| TransactionBuilder::new().fee(1).nonce(nonce).sign(&signer))
+            .collect();
+        let high_fee: Vec<Transaction> = (0..10)
+            .map(|nonce| TransactionBuilder::new().fee(5).nonce(nonce).sign(&signer))
+            .collect();
+        for tx in low_fee.iter().chain(&high_fee) {
+            node.pending_transactions.insert(tx.hash, tx.clone());
+        }
+
+        node.propose_block().unwrap();
+
+        let block = node.blocks.data_by_number(1).unwrap();
+        assert_eq!(block.data.transactions.len(), MAX_BLOCK_TXS);
+        for tx in &high_fee {
+            assert!(block
+                .data
+                .transactions
+                .iter()
+                .any(|mined| mined.hash == tx.hash));
+        }
+        assert_eq!(node.pending_transactions.len(), 10);
+        assert!(node
+            .pending_transactions
+            .values()
+            .all(|tx| tx.data.fee == 1));
+    }
+
+    #[test]
+    fn tx_orders() {
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+        let txs: Vec<Transaction> = [(1, 0), (3, 1), (2, 2)]
+            .into_iter()
+            .map(|(fee, nonce)| {
+                TransactionBuilder::new()
+                    .fee(fee)
+                    .nonce(nonce)
+                    .sign(&signer)
+            })
+            .collect();
+        let selected = |tx_order| {
+            let mut node = test_node();
+            node.config.tx_order = tx_order;
+            node.config.block_interval = Some(Duration::from_secs(3600));
+            for tx in &txs {
+                node.process_transaction(tx.clone());
+            }
+            node.select_transactions(&[], node.pending_transactions.values())
+        };
+
+        let hashes = |indices: [usize; 3]| indices.map(|i| txs[i].hash).to_vec();
+        assert_eq!(selected(TxOrder::Fee), hashes([1, 2, 0]));
+        assert_eq!(selected(TxOrder::Fifo), hashes([0, 1, 2]));
+        let mut by_hash = hashes([0, 1, 2]);
+        by_hash.sort();
+        assert_eq!(selected(TxOrder::Hash), by_hash);
+    }
+
+    #[test]
+    fn mined_transaction_not_readmitted() {
+        let mut node = test_node();
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+        let tx = TransactionBuilder::new().amount(100).sign(&signer);
+
+        assert_eq!(node.admit_transaction(tx.clone()), Ok(()));
+        assert!(node.blocks.contains_tx(tx.hash));
+
+        // Even if the block gets dropped from the chain.
+        node.blocks = Blocks::default();
+        node.blocks.append(Block::new_genesis());
+        assert_eq!(node.admit_transaction(tx), Err(RejectReason::AlreadySeen));
+    }
+
+    #[test]
+    fn sync_response_capped() {
+        let mut node = test_node();
+        node.config.allow_empty_blocks = true;
+        node.config.max_sync_response = Some(10);
+        for _ in 0..99 {
+            node.propose_block().unwrap();
+        }
+        assert_eq!(node.blocks.len(), 100);
+
+        let client = Transport::new("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(Duration::from_millis(200)));
+        let peer = node_info("peer", 2, &client.local_addr().unwrap().to_string());
+        node.others.insert(peer.address, peer.clone());
+
+        node.process_sync_block(peer.socket, 0, peer.socket);
+        for i in 0..10 {
+            let Ok(Message::Block(block)) = client.receive::<Message>() else {
+                panic!("expected a block");
+            };
+            assert_eq!(block.data.number, i);
+        }
+        let Ok(Message::ChainHeight(_, height)) = client.receive::<Message>() else {
+            panic!("expected the chain height");
+        };
+        assert_eq!(height, 100);
+        assert!(client.receive::<Message>().is_err());
+    }
+
+    #[test]
+    fn compressed_sync() {
+        let mut node = test_node();
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+        for nonce in 0..50 {
+            let tx = TransactionBuilder::new()
+                .amount(1)
+                .nonce(nonce)
+                .sign(&signer);
+            node.process_transaction(tx);
+        }
+        assert_eq!(node.blocks.len(), 51);
+
+        // Bytes of the 50-block sync response sent with and without compression.
+        let sink = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sizes = [false, true].map(|compress| {
+            let config = TransportConfig {
+                compress,
+                ..Default::default()
+            };
+            let transport = Transport::with_config("127.0.0.1:0", config).unwrap();
+            (1..51)
+                .map(|i| {
+                    let block = node.blocks.data_by_number(i).unwrap().clone();
+                    let to = sink.local_addr().unwrap();
+                    transport.send(to, &Message::Block(block)).unwrap()
+                })
+                .sum::<usize>()
+        });
+        assert!(sizes[1] * 10 < sizes[0] * 7);
+
+        node.transport = Transport::with_config(
+            "127.0.0.1:0",
+            TransportConfig {
+                compress: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let client = Transport::new("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(Duration::from_millis(200)));
+        let peer = node_info("peer", 3, &client.local_addr().unwrap().to_string());
+        node.others.insert(peer.address, peer.clone());
+        node.process_sync_block(peer.socket, 1, peer.socket);
+        for i in 1..51 {
+            let Ok(Message::Block(block)) = client.receive::<Message>() else {
+                panic!("expected a block");
+            };
+            assert_eq!(block.hash, node.blocks.data_by_number(i).unwrap().hash);
+        }
+    }
+
+    #[test]
+    fn rejection_logged() {
+        let path = std::env::temp_dir().join(format!("reject-log-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let config = Config {
+            reject_log: Some(path.clone()),
+            ..Default::default()
+        };
+        let info = node_info("test", 1, "127.0.0.1:0");
+        let mut node = Node::new(SigningKey::from_slice(&[1; 32]).unwrap(), info, config);
+
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+        let tx = TransactionBuilder::new().amount(5000).sign(&signer);
+        node.process_transaction(tx.clone());
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 1);
+        let record: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(record["hash"], serde_json::to_value(tx.hash).unwrap());
+        assert_eq!(record["reason"], "InsufficientBalance");
+    }
+
+    #[test]
+    fn seen_transaction_expires() {
+        let mut node = test_node();
+        let ttl = Duration::from_secs(60);
+        node.config.seen_ttl = Some(ttl);
+        let clock = MockClock::default();
+        node.clock = Box::new(clock.clone());
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+        let tx = TransactionBuilder::new().amount(100).sign(&signer);
+        node.process_transaction(tx.clone());
+
+        clock.advance(ttl - Duration::from_millis(1));
+        node.tick();
+        assert!(node.seen_txs.contains(&tx.hash));
+
+        clock.advance(Duration::from_millis(1));
+        node.tick();
+        assert!(!node.seen_txs.contains(&tx.hash));
+    }
+
+    #[test]
+    fn transactions_batched_by_interval() {
+        let config = Config {
+            block_interval: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        let info = node_info("test", 1, "127.0.0.1:0");
+        let mut node = Node::new(SigningKey::from_slice(&[1; 32]).unwrap(), info, config);
+        let clock = MockClock::default();
+        node.clock = Box::new(clock.clone());
+        node.tick();
+
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+        for nonce in 0..5 {
+            let tx = TransactionBuilder::new().nonce(nonce).sign(&signer);
+            node.process_transaction(tx);
+        }
+        clock.advance(Duration::from_secs(30));
+        node.tick();
+        assert_eq!(node.blocks.len(), 1);
+
+        clock.advance(Duration::from_secs(30));
+        node.tick();
+        assert_eq!(node.blocks.len(), 2);
+        let block = node.blocks.data_by_number(1).unwrap();
+        assert_eq!(block.data.transactions.len(), 5);
+    }
+
+    #[test]
+    fn no_proposal_on_empty_chain() {
+        let mut node = test_node();
+        node.blocks = Blocks::default();
+        node.config.allow_empty_blocks = true;
+
+        assert_eq!(node.propose_block(), Err(DeclineReason::EmptyChain));
+        assert!(node.blocks.is_empty());
+    }
+
+    #[test]
+    fn sync_block_from_non_peer() {
+        let mut node = test_node();
+        node.config.allow_empty_blocks = true;
+        node.propose_block().unwrap();
+        assert!(node.others.is_empty());
+
+        let client = Transport::new("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(1)));
+        let socket = client.local_addr().unwrap();
+        node.process_sync_block(socket, 0, socket);
+        for number in 0..2 {
+            let Message::Block(block) = client.receive::<Message>().unwrap() else {
+                panic!("unexpected message");
+            };
+            assert_eq!(block.data.number, number);
+        }
+        let Message::ChainHeight(_, height) = client.receive::<Message>().unwrap() else {
+            panic!("unexpected message");
+        };
+        assert_eq!(height, 2);
+    }
+
+    #[test]
+    fn sync_block_for_other_host() {
+        let info = node_info("test", 1, "127.0.0.1:40000");
+        let signer = SigningKey::from_slice(&[1; 32]).unwrap();
+        let mut node = Node::with_network(signer, info, Config::default(), MockNetwork::default());
+
+        let victim: SocketAddr = "10.0.0.1:40001".parse().unwrap();
+        node.process_sync_block(victim, 0, "127.0.0.1:40002".parse().unwrap());
+        assert!(node.transport.take_sent().is_empty());
+
+        // Another port on the requester's host is answered.
+        let other_port: SocketAddr = "127.0.0.1:40003".parse().unwrap();
+        node.process_sync_block(other_port, 0, "127.0.0.1:40002".parse().unwrap());
+        let sent = node.transport.take_sent();
+        assert_eq!(sent.len(), 2);
+        assert!(sent.iter().all(|(to, _)| *to == other_port));
+    }
+
+    #[test]
+    fn health_check() {
+        let mut node = test_node();
+        let health = |node: &mut Node| {
+            let Response::Health(status) = query(node, Message::HealthCheck) else {
+                panic!("unexpected response");
+            };
+            status
+        };
+
+        let status = health(&mut node);
+        assert_eq!(status.height, 1);
+        assert!(!status.syncing);
+
+        let peer = node_info("peer", 2, "127.0.0.1:40001");
+        node.others.insert(peer.address, peer);
+        node.config.allow_empty_blocks = true;
+        node.propose_block().unwrap();
+        node.start_sync(10);
+        let status = health(&mut node);
+        assert_eq!(status.height, 2);
+        assert_eq!(status.peers, 1);
+        assert!(status.syncing);
+    }
+
+    #[test]
+    fn no_proposal_while_syncing() {
+        let mut node = test_node();
+        let clock = MockClock::default();
+        node.clock = Box::new(clock.clone());
+
+        // A block far ahead of the tip starts a sync.
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+        let ahead = Block::new(
+            BlockData {
+                prev_hash: B256::hash_of("unknown"),
+                number: 5,
+                nonce: 0,
+                timestamp: 0,
+                transactions: vec![],
+            },
+            &signer,
+        );
+        node.process_block(ahead);
+        assert!(node.is_syncing());
+
+        let height = node.blocks.len();
+        let tx = TransactionBuilder::new().amount(100).sign(&signer);
+        node.process_transaction(tx.clone());
+        assert_eq!(node.blocks.len(), height);
+        assert!(node.pending_transactions.contains_key(&tx.hash));
+        assert_eq!(node.propose_block(), Err(DeclineReason::Syncing));
+
+        // An abandoned sync doesn't block proposing forever.
+        clock.advance(SYNC_TIMEOUT);
+        node.tick();
+        assert!(!node.is_syncing());
+        assert!(node.propose_block().is_ok());
+        assert_eq!(node.blocks.len(), height + 1);
+    }
+
+    #[test]
+    fn propose_first_block() {
+        let mut node = test_node();
+        node.blocks = Blocks::default();
+        node.config.create_genesis = true;
+
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+        node.process_transaction(TransactionBuilder::new().amount(100).sign(&signer));
+
+        let genesis = node.blocks.data_by_number(0).unwrap().clone();
+        assert_eq!(genesis.hash, Block::new_genesis().hash);
+        let block = node.blocks.data_by_number(1).unwrap().clone();
+        assert_eq!(block.data.transactions.len(), 1);
+
+        let mut blocks = Blocks::default();
+        assert!(matches!(blocks.append(genesis), BlockAppendResult::Added));
+        assert!(matches!(blocks.append(block), BlockAppendResult::Added));
+    }
+
+    #[test]
+    fn observer_never_relays() {
+        let mut node = observer_node();
+
+        let peer = Transport::new("127.0.0.1:0").unwrap();
+        peer.set_read_timeout(Some(Duration::from_millis(200)));
+        let peer_info = node_info("peer", 2, &peer.local_addr().unwrap().to_string());
+        node.process_hello(peer_info.clone(), sign(&peer_info, 2), peer_info.socket);
+
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+        let tx = TransactionBuilder::new().amount(100).sign(&signer);
+        node.process_transaction(tx.clone());
+
+        let block = Block::new(
+            BlockData {
+                prev_hash: node.blocks.last_hash(),
+                number: 1,
+                nonce: 0,
+                timestamp: 0,
+                transactions: vec![tx],
+            },
+            &signer,
+        );
+        node.process_block(block.clone());
+
+        assert_eq!(node.blocks.last_hash(), block.hash);
+        assert!(node.pending_transactions.is_empty());
+        while let Ok(message) = peer.receive::<Message>() {
+            assert!(matches!(
+                message,
+                Message::Hello(..) | Message::ChainHeight(..)
+            ));
+        }
+    }
+
+    #[test]
+    fn transactions_verified_once() {
+        let mut node = test_node();
+
+        let signer = SigningKey::from_slice(&[2; 32]).unwrap();
+        let tx = TransactionBuilder::new().amount(100).sign(&signer);
+
+        for _ in 0..10 {
+            node.process_transaction(tx.clone());
+        }
+        let block = Block::new(
+            BlockData {
+                prev_hash: node.blocks.last_hash(),
+                number: node.blocks.len(),
+                nonce: 0,
+                timestamp: 0,
+                transactions: vec![tx],
+            },
+            &signer,
+        );
+        node.process_block(block);
+
+        assert_eq!(node.verifications, 1);
+    }
+
+    #[test]
+    fn forged_transaction_not_cached() {
+        let mut node = observer_node();
+        let victim = SigningKey::from_slice(&[2; 32]).unwrap();
+        let tx = TransactionBuilder::new().amount(100).sign(&victim);
+        node.process_transaction(tx.clone());
+        assert!(node.pending_transactions.contains_key(&tx.hash));
+
+        // Keeps the cached hash, sender and signature, but pays the thief.
+        let mut forged = tx;
+        forged.data.to = node_info("thief", 3, "127.0.0.1:0").address;
+        let thief = SigningKey::from_slice(&[3; 32]).unwrap();
+        let block = Block::new(
+            BlockData {
+                prev_hash: node.blocks.last_hash(),
+                number: node.blocks.len(),
+                nonce: 0,
+                timestamp: 0,
+                transactions: vec![forged],
+            },
+            &thief,
+        );
+        node.process_block(block);
+        assert_eq!(node.blocks.len(), 1);
+    }
+
+    #[test]
+    fn block_gossiped_once() {
+        let mut node = test_node();
+
+        let peer = Transport::new("127.0.0.1:0").unwrap();
+        peer.set_read_timeout(Some(Duration::from_millis(200)));
+        let peer_info = node_info("peer", 2, &peer.local_addr().unwrap().to_string());
+        node.process_hello(peer_info.clone(), sign(&peer_info, 2), peer_info.socket);
+
+        let block = Block::new_genesis();
+        node.gossip(Message::Block(block.clone()));
+        node.gossip(Message::Block(block));
+
+        let mut blocks = 0;
+        while let Ok(message) = peer.receive::<Message>() {
+            if matches!(message, Message::Block(_)) {
+                blocks += 1;
+            }
+        }
+        assert_eq!(blocks, 1);
+    }
+
+    #[test]
+    fn send_rate_limited() {
+        let config = Config {
+            send_rate: Some(5),
+            ..Default::default()
+        };
+        let info = node_info("test", 1, "127.0.0.1:40000");
+        let signer = SigningKey::from_slice(&[1; 32]).unwrap();
+        let mut node = Node::with_network(signer, info, config, MockNetwork::default());
+        let clock = MockClock::default();
+        node.clock = Box::new(clock.clone());
+        let peer = node_info("peer", 2, "127.0.0.1:40001");
+        node.others.insert(peer.address, peer);
+
+        let burst = |node: &mut Node<MockNetwork>| {
+            for height in 0..20 {
+                node.send_to_others(Message::ChainHeight(B256::ZERO, height));
+            }
+            node.transport.take_sent().len()
+        };
+        assert_eq!(burst(&mut node), 5);
+        clock.advance(Duration::from_millis(400));
+        assert_eq!(burst(&mut node), 2);
+
+        // Bursts every 100 milliseconds are capped at the rate.
+        let sent: usize = (0..10)
+            .map(|_| {
+                clock.advance(Duration::from_millis(100));
+                burst(&mut node)
+            })
+            .sum();
+        assert_eq!(sent, 5);
+    }
+
+    #[test]
+    fn future_timestamp_skew() {
+        let skew = Duration::from_secs(5);
+        let config = Config {
+            max_future_skew: Some(skew),
+            ..Default::default()
+        };
+        let info = node_info("test", 1, "127.0.0.1:40000");
+        let signer = SigningKey::from_slice(&[1; 32]).unwrap();
+        let mut node = Node::with_network(signer, info, config, MockNetwork::default());
+        let clock = MockClock::default();
+        clock.advance(Duration::from_secs(10));
+        node.clock = Box::new(clock.clone());
+
+        let peer = SigningKey::from_slice(&[2; 32]).unwrap();
+        let next_block = |node: &Node<MockNetwork>, ahead: Duration| {
+            let data = BlockData {
+                prev_hash: node.blocks.last_hash(),
+                number: node.blocks.len(),
+                nonce: 0,
+                timestamp: clock.now() + ahead.as_millis() as u64,
+                transactions: vec![],
+            };
+            Block::new(data, &peer)
+        };
+
+        let block = next_block(&node, skew);
+        node.process_block(block);
+        assert_eq!(node.blocks.len(), 2);
+
+        let block = next_block(&node, skew + Duration::from_millis(1));
+        node.process_block(block.clone());
+        assert_eq!(node.blocks.len(), 2);
+
+        // Accepted once the node's clock catches up.
+        clock.advance(Duration::from_millis(1));
+        node.process_block(block);
+        assert_eq!(node.blocks.len(), 3);
+    }
+
+    #[test]
+    fn equivocation_detected() {
+        let info = node_info("test", 1, "127.0.0.1:40000");
+        let signer = SigningKey::from_slice(&[1; 32]).unwrap();
+        let mut node = Node::with_network(signer, info, Config::default(), MockNetwork::default());
+        let peer = node_info("peer", 3, "127.0.0.1:40003");
+        node.others.insert(peer.address, peer);
+
+        let equivocator = SigningKey::from_slice(&[2; 32]).unwrap();
+        let conflicting = |nonce| {
+            let data = BlockData {
+                prev_hash: node.blocks.last_hash(),
+                number: 1,
+                nonce,
+                timestamp: 0,
+                transactions: vec![],
+            };
+            Block::new(data, &equivocator)
+        };
+        let (first, second) = (conflicting(0), conflicting(1));
+        node.process_block(first.clone());
+        node.process_block(first.clone());
+        assert!(node.evidence.is_empty());
+        node.process_block(second.clone());
+
+        let address = B256::address_of(equivocator.verifying_key());
+        let evidence = &node.evidence[&(address, 1)];
+        evidence.verify().unwrap();
+        assert_eq!(evidence.second.hash, second.hash);
+        let gossiped = node
+            .transport
+            .take_sent()
+            .into_iter()
+            .filter(|(_, msg)| msg.get("Evidence").is_some())
+            .count();
+        assert_eq!(gossiped, 1);
+
+        // Peers accept only evidence of two different blocks.
+        let info = node_info("other", 4, "127.0.0.1:40004");
+        let signer = SigningKey::from_slice(&[4; 32]).unwrap();
+        let mut other = Node::with_network(signer, info, Config::default(), MockNetwork::default());
+        let forged = SlashingEvidence {
+            first: first.clone(),
+            second: first,
+        };
+        other.process_evidence(forged);
+        assert!(other.evidence.is_empty());
+        other.process_evidence(evidence.clone());
+        assert!(other.evidence.contains_key(&(address, 1)));
+    }
+
+    #[test]
+    fn gossip_fanout() {
+        let config = Config {
+            gossip_fanout: Some(2),
+            rng_seed: Some(7),
+            ..Default::default()
+        };
+        let info = node_info("test", 1, "127.0.0.1:0");
+        let mut node = Node::new(SigningKey::from_slice(&[1; 32]).unwrap(), info, config);
+
+        let peers: Vec<Transport> = (0..5)
+            .map(|_| Transport::new("127.0.0.1:0").unwrap())
+            .collect();
+        for (i, peer) in peers.iter().enumerate() {
+            peer.set_read_timeout(Some(Duration::from_millis(200)));
+            let socket = peer.local_addr().unwrap().to_string();
+            let info = node_info("peer", i as u8 + 2, &socket);
+            node.others.insert(info.address, info);
+        }
+
+        node.gossip(Message::Block(Block::new_genesis()));
+
+        let received = peers
+            .iter()
+            .filter(|peer| peer.receive::<Message>().is_ok())
+            .count();
+        assert_eq!(received, 2);
+    }
+}
@@ -0,0 +1,188 @@
+use std::net::SocketAddr;
+
+use k256::ecdsa::SigningKey;
+use ledger_types::{Message, NodeInfo, PublicKey, Transaction, B256};
+
+use crate::network::{MockNetwork, Network};
+use crate::node::{Config, Node};
+
+/// Maximal number of delivery rounds before the cluster is considered stuck.
+const MAX_ROUNDS: usize = 1000;
+
+/// Nodes exchanging messages in-process through mock networks.
+pub struct Cluster {
+    pub nodes: Vec<Node<MockNetwork>>,
+}
+
+impl Cluster {
+    /// Creates `len` validators with the config, introduced to each other.
+    pub fn new(len: u8, config: Config) -> Self {
+        let nodes: Vec<Node<MockNetwork>> =
+            (1..=len).map(|key| node(key, config.clone())).collect();
+        let cluster = Self { nodes };
+        for node in &cluster.nodes {
+            for other in &cluster.nodes {
+                if other.info.address != node.info.address {
+                    node.transport
+                        .send(other.info.socket, &node.hello())
+                        .unwrap();
+                }
+            }
+        }
+        cluster.deliver_all()
+    }
+
+    /// Adds a validator introduced to the others, which says hello back.
+    pub fn join(mut self, key: u8, config: Config) -> Self {
+        let node = node(key, config);
+        for other in &self.nodes {
+            node.transport
+                .send(other.info.socket, &node.hello())
+                .unwrap();
+        }
+        self.nodes.push(node);
+        self.deliver_all()
+    }
+
+    /// Hands the transaction to the node as if a client sent it.
+    pub fn submit(&mut self, index: usize, tx: Transaction) {
+        let client = SocketAddr::from(([127, 0, 0, 1], 1));
+        self.nodes[index].process_message(Message::Transaction(tx), client);
+    }
+
+    /// Delivers the sent messages, and the messages sent in response, until
+    /// none are left. Messages to unknown sockets, like replies to clients,
+    /// are dropped.
+    pub fn deliver_all(mut self) -> Self {
+        for _ in 0..MAX_ROUNDS {
+            let sent: Vec<(SocketAddr, SocketAddr, serde_json::Value)> = self
+                .nodes
+                .iter()
+                .flat_map(|node| {
+                    let from = node.info.socket;
+                    node.transport
+                        .take_sent()
+                        .into_iter()
+                        .map(move |(to, msg)| (from, to, msg))
+                })
+                .collect();
+            if sent.is_empty() {
+                return self;
+            }
+
+            for (from, to, msg) in sent {
+                let Some(node) = self.nodes.iter_mut().find(|node| node.info.socket == to) else {
+                    continue;
+                };
+                if let Ok(msg) = serde_json::from_value::<Message>(msg) {
+                    node.process_message(msg, from);
+                }
+            }
+        }
+        panic!("cluster didn't settle in {} rounds", MAX_ROUNDS);
+    }
+
+    /// Checks that all nodes have the same chain and state, returning its height.
+    pub fn assert_converged(&self) -> u64 {
+        let first = &self.nodes[0];
+        for node in &self.nodes[1..] {
+            assert_eq!(node.blocks.len(), first.blocks.len(), "{}", node.info.name);
+            assert_eq!(node.blocks.last_hash(), first.blocks.last_hash());
+            assert_eq!(node.blocks.state_root(), first.blocks.state_root());
+        }
+        first.blocks.len()
+    }
+}
+
+fn node(key: u8, config: Config) -> Node<MockNetwork> {
+    let signer = SigningKey::from_slice(&[key; 32]).unwrap();
+    let info = NodeInfo {
+        name: format!("node-{}", key),
+        address: B256::address_of(signer.verifying_key()),
+        public_key: PublicKey::from_key(signer.verifying_key()),
+        socket: SocketAddr::from(([127, 0, 0, 1], 10_000 + u16::from(key))),
+        observer: false,
+    };
+    Node::with_network(signer, info, config, MockNetwork::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use k256::ecdsa::SigningKey;
+    use ledger_types::{TransactionBuilder, B256};
+
+    use crate::blocks::ChainConfig;
+    use crate::node::cluster::Cluster;
+    use crate::node::Config;
+
+    #[test]
+    fn transactions_converge() {
+        let mut cluster = Cluster::new(4, Config::default());
+        assert_eq!(cluster.assert_converged(), 1);
+
+        let sender = SigningKey::from_slice(&[9; 32]).unwrap();
+        let recipient = B256::hash_of("recipient");
+        for (nonce, index) in [0, 1, 2, 3, 0, 2].into_iter().enumerate() {
+            let tx = TransactionBuilder::new()
+                .to(recipient)
+                .amount(10)
+                .nonce(nonce as u64)
+                .sign(&sender);
+            cluster.submit(index, tx);
+            cluster = cluster.deliver_all();
+        }
+
+        let height = cluster.assert_converged();
+        assert!(height > 1);
+        for node in &cluster.nodes {
+            assert!(node.pending_transactions.is_empty());
+            assert_eq!(node.blocks.balance_of(recipient), 1060);
+        }
+    }
+
+    #[test]
+    fn late_node_syncs() {
+        let mut cluster = Cluster::new(3, Config::default());
+        let sender = SigningKey::from_slice(&[9; 32]).unwrap();
+        for nonce in 0..5 {
+            let tx = TransactionBuilder::new()
+                .amount(10)
+                .nonce(nonce)
+                .sign(&sender);
+            cluster.submit(0, tx);
+            cluster = cluster.deliver_all();
+        }
+
+        let mut cluster = cluster.join(4, Config::default());
+        let tx = TransactionBuilder::new().amount(10).nonce(5).sign(&sender);
+        cluster.submit(0, tx);
+        cluster.deliver_all().assert_converged();
+    }
+
+    #[test]
+    fn rewarded_blocks_converge() {
+        let config = Config {
+            chain: ChainConfig {
+                block_reward: 50,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut cluster = Cluster::new(3, config);
+        let sender = SigningKey::from_slice(&[9; 32]).unwrap();
+        for (nonce, index) in [0, 1, 2].into_iter().enumerate() {
+            let tx = TransactionBuilder::new()
+                .amount(10)
+                .nonce(nonce as u64)
+                .sign(&sender);
+            cluster.submit(index, tx);
+            cluster = cluster.deliver_all();
+        }
+
+        let height = cluster.assert_converged();
+        assert_eq!(height, 4);
+        for node in &cluster.nodes {
+            assert!(node.pending_transactions.is_empty());
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use std::io::{self, ErrorKind};
+use std::time::Duration;
+
+use ledger_types::Transaction;
+
+use crate::clock::elapsed;
+use crate::network::Network;
+use crate::node::Node;
+
+/// Minimal interval between writes of the changed pending transactions.
+const SAVE_INTERVAL: Duration = Duration::from_secs(1);
+
+impl<N: Network> Node<N> {
+    /// Restores the pending transactions saved to the mempool file by an
+    /// earlier run, if there is one. The ones mined or invalid meanwhile are
+    /// dropped. They aren't gossiped again, but are proposed as usual once
+    /// the node leads. Returns the number of restored transactions.
+    pub fn load_mempool(&mut self) -> io::Result<usize> {
+        let Some(path) = self.config.mempool.clone() else {
+            return Ok(0);
+        };
+        let json = match std::fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let saved: Vec<Transaction> =
+            serde_json::from_str(&json).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+
+        let total = saved.len();
+        let restored = saved
+            .into_iter()
+            .filter_map(|tx| self.insert_transaction(tx).ok())
+            .count();
+        println!(
+            "Restored {} of {} saved pending transactions",
+            restored, total
+        );
+        Ok(restored)
+    }
+
+    /// Writes the pending transactions to the mempool file in the order of
+    /// arrival if they changed, at most every `SAVE_INTERVAL`.
+    pub(super) fn save_mempool(&mut self) {
+        let Some(path) = &self.config.mempool else {
+            return;
+        };
+        let now = self.clock.now();
+        if !self.mempool_changed || elapsed(now, self.mempool_saved_at) < SAVE_INTERVAL {
+            return;
+        }
+
+        let mut pending: Vec<&Transaction> = self.pending_transactions.values().collect();
+        pending.sort_by_key(|tx| self.arrivals.get(&tx.hash));
+        let json = serde_json::to_string(&pending).expect("transactions should be serialized");
+        // Renaming a complete file, a crash never leaves a torn one behind.
+        let temporary = path.with_extension("tmp");
+        let written =
+            std::fs::write(&temporary, json).and_then(|()| std::fs::rename(&temporary, path));
+        if let Err(e) = written {
+            println!("Failed to save the mempool: {}", e);
+        }
+        self.mempool_changed = false;
+        self.mempool_saved_at = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::path::Path;
+    use std::time::Duration;
+
+    use k256::ecdsa::SigningKey;
+    use ledger_types::{NodeInfo, PublicKey, TransactionBuilder, B256};
+
+    use crate::clock::MockClock;
+    use crate::network::MockNetwork;
+    use crate::node::{Config, Node};
+
+    fn node(path: &Path) -> Node<MockNetwork> {
+        let signer = SigningKey::from_slice(&[1; 32]).unwrap();
+        let info = NodeInfo {
+            name: "restarted".to_string(),
+            address: B256::address_of(signer.verifying_key()),
+            public_key: PublicKey::from_key(signer.verifying_key()),
+            socket: SocketAddr::from(([127, 0, 0, 1], 10_001)),
+            observer: false,
+        };
+        let config = Config {
+            mempool: Some(path.to_path_buf()),
+            // Keeps the transactions pending instead of proposing them.
+            block_interval: Some(Duration::from_secs(60)),
+            ..Default::default()
+        };
+        Node::with_network(signer, info, config, MockNetwork::default())
+    }
+
+    #[test]
+    fn save_and_reload() {
+        let path = std::env::temp_dir().join(format!("mempool-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut node_before = node(&path);
+        assert_eq!(node_before.load_mempool().unwrap(), 0);
+        let clock = MockClock::default();
+        node_before.clock = Box::new(clock.clone());
+        // Not due to propose on ticks within the block interval.
+        node_before.last_proposal = Some(0);
+
+        let txs: Vec<_> = (0..3)
+            .map(|nonce| {
+                TransactionBuilder::new()
+                    .amount(10)
+                    .nonce(nonce)
+                    .sign(&node_before.signer)
+            })
+            .collect();
+        for tx in &txs {
+            node_before.admit_transaction(tx.clone()).unwrap();
+        }
+        // Saved only once the interval passes.
+        node_before.tick();
+        assert!(!path.exists());
+        clock.advance(Duration::from_secs(1));
+        node_before.tick();
+        assert!(path.exists());
+
+        // The first transaction is mined, but the node stops before saving.
+        let mined = node_before.blocks.len();
+        node_before
+            .pending_transactions
+            .retain(|hash, _| *hash == txs[0].hash);
+        node_before.propose_block().unwrap();
+        assert_eq!(node_before.blocks.len(), mined + 1);
+        let chain = node_before.blocks.export_json();
+        drop(node_before);
+
+        let mut restarted = node(&path);
+        restarted.import_chain(&chain).unwrap();
+        // Restoring neither gossips to peers nor proposes right away.
+        restarted.config.block_interval = None;
+        let peer = NodeInfo {
+            name: "peer".to_string(),
+            address: B256::hash_of("peer"),
+            socket: SocketAddr::from(([127, 0, 0, 1], 10_002)),
+            ..restarted.info.clone()
+        };
+        restarted.others.insert(peer.address, peer);
+        assert_eq!(restarted.load_mempool().unwrap(), 2);
+        assert!(restarted.transport.take_sent().is_empty());
+        assert_eq!(restarted.blocks.len(), mined + 1);
+        std::fs::remove_file(&path).unwrap();
+        assert!(!restarted.pending_transactions.contains_key(&txs[0].hash));
+        assert!(restarted.pending_transactions.contains_key(&txs[1].hash));
+        assert!(restarted.pending_transactions.contains_key(&txs[2].hash));
+
+        std::fs::write(&path, "not a mempool").unwrap();
+        assert!(restarted.load_mempool().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
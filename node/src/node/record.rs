@@ -0,0 +1,147 @@
+use std::io::{BufRead, Write};
+use std::net::SocketAddr;
+
+use ledger_types::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::clock::ReplayClock;
+use crate::network::Network;
+use crate::node::Node;
+
+/// Received message as it's written to the record.
+#[derive(Debug, Serialize, Deserialize)]
+struct Recorded<M> {
+    /// Time of the receipt in milliseconds of the node's clock.
+    time: u64,
+    source: SocketAddr,
+    message: M,
+}
+
+/// Error of replaying a message record.
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The record can't be read.
+    Io(std::io::Error),
+    /// The line with the number isn't a recorded message.
+    Invalid(usize, serde_json::Error),
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to read the record: {}", e),
+            Self::Invalid(line, e) => write!(f, "invalid message on line {}: {}", line, e),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl<N: Network> Node<N> {
+    /// Appends the received message to the record, if there is one.
+    pub(super) fn record_message(&mut self, message: &Message, source: SocketAddr) {
+        let Some(file) = &mut self.record else {
+            return;
+        };
+
+        let recorded = Recorded {
+            time: self.clock.now(),
+            source,
+            message,
+        };
+        let line = serde_json::to_string(&recorded).expect("message should be serialized");
+        if let Err(e) = writeln!(file, "{}", line) {
+            println!("Failed to write the message record: {}", e);
+        }
+    }
+
+    /// Feeds the recorded messages to the node in order, each followed by a
+    /// tick, with the clock set to the recorded times. Replaying a record on
+    /// the same initial state gives the same chain, except that the blocks
+    /// proposed by the node are timestamped with the times of the messages
+    /// triggering them. Returns the number of replayed messages.
+    pub fn replay(&mut self, record: impl BufRead) -> Result<usize, ReplayError> {
+        let clock = ReplayClock::default();
+        self.clock = Box::new(clock.clone());
+
+        let mut replayed = 0;
+        for (index, line) in record.lines().enumerate() {
+            let line = line.map_err(ReplayError::Io)?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let recorded: Recorded<Message> =
+                serde_json::from_str(&line).map_err(|e| ReplayError::Invalid(index + 1, e))?;
+            clock.set(recorded.time);
+            self.process_message(recorded.message, recorded.source);
+            self.tick();
+            replayed += 1;
+        }
+        Ok(replayed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use k256::ecdsa::SigningKey;
+    use ledger_types::{Message, NodeInfo, PublicKey, TransactionBuilder, B256};
+
+    use crate::clock::MockClock;
+    use crate::network::MockNetwork;
+    use crate::node::record::ReplayError;
+    use crate::node::{Config, Node};
+
+    fn node(config: Config) -> Node<MockNetwork> {
+        let signer = SigningKey::from_slice(&[1; 32]).unwrap();
+        let info = NodeInfo {
+            name: "replayed".to_string(),
+            address: B256::address_of(signer.verifying_key()),
+            public_key: PublicKey::from_key(signer.verifying_key()),
+            socket: SocketAddr::from(([127, 0, 0, 1], 10_001)),
+            observer: false,
+        };
+        Node::with_network(signer, info, config, MockNetwork::default())
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let path = std::env::temp_dir().join(format!("record-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut recorded = node(Config {
+            record: Some(path.clone()),
+            ..Default::default()
+        });
+        let clock = MockClock::default();
+        recorded.clock = Box::new(clock.clone());
+
+        let client = SocketAddr::from(([127, 0, 0, 1], 1));
+        let sender = SigningKey::from_slice(&[2; 32]).unwrap();
+        for nonce in 0..3 {
+            let tx = TransactionBuilder::new()
+                .amount(10)
+                .nonce(nonce)
+                .sign(&recorded.signer);
+            recorded.process_message(Message::Transaction(tx), client);
+            clock.advance(Duration::from_millis(10));
+        }
+        // Rejected for the balance, which must be rejected on replay too.
+        let tx = TransactionBuilder::new().amount(5000).sign(&sender);
+        recorded.process_message(Message::Transaction(tx), client);
+        assert_eq!(recorded.blocks.len(), 4);
+
+        let file = std::fs::File::open(&path).unwrap();
+        let mut replayed = node(Config::default());
+        assert_eq!(replayed.replay(BufReader::new(file)).unwrap(), 4);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replayed.blocks.len(), recorded.blocks.len());
+        assert_eq!(replayed.blocks.last_hash(), recorded.blocks.last_hash());
+        assert_eq!(replayed.blocks.state_root(), recorded.blocks.state_root());
+
+        let invalid = replayed.replay("\nnot a message\n".as_bytes());
+        assert!(matches!(invalid, Err(ReplayError::Invalid(2, _))));
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+/// Token buckets limiting the number of messages sent to each peer per
+/// second. A peer may receive a burst of a second's worth of messages.
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_second: u64,
+    buckets: HashMap<SocketAddr, Bucket>,
+}
+
+/// Tokens of a peer in thousandths, so they refill by the millisecond.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    milli_tokens: u64,
+    /// Time of the last refill in milliseconds of a clock.
+    refilled_at: u64,
+}
+
+impl RateLimiter {
+    pub fn new(per_second: u64) -> Self {
+        Self {
+            per_second,
+            buckets: HashMap::new(),
+        }
+    }
+
+    /// Takes a token for a message to the peer at the time `now` in
+    /// milliseconds of a clock. Returns `false` if the peer's tokens are
+    /// used up and the message should be dropped.
+    pub fn try_acquire(&mut self, peer: SocketAddr, now: u64) -> bool {
+        let capacity = self.per_second.saturating_mul(1000);
+        let bucket = self.buckets.entry(peer).or_insert(Bucket {
+            milli_tokens: capacity,
+            refilled_at: now,
+        });
+        let refill = now
+            .saturating_sub(bucket.refilled_at)
+            .saturating_mul(self.per_second);
+        bucket.milli_tokens = bucket.milli_tokens.saturating_add(refill).min(capacity);
+        bucket.refilled_at = now;
+
+        if bucket.milli_tokens < 1000 {
+            return false;
+        }
+        bucket.milli_tokens -= 1000;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use crate::rate_limit::RateLimiter;
+
+    #[test]
+    fn refills_over_time() {
+        let mut limiter = RateLimiter::new(4);
+        let peer = SocketAddr::from(([127, 0, 0, 1], 1));
+        let other = SocketAddr::from(([127, 0, 0, 1], 2));
+
+        let sent = (0..10).filter(|_| limiter.try_acquire(peer, 0)).count();
+        assert_eq!(sent, 4);
+        assert!(limiter.try_acquire(other, 0));
+
+        // A token per 250 milliseconds.
+        assert!(!limiter.try_acquire(peer, 249));
+        assert!(limiter.try_acquire(peer, 250));
+        assert!(!limiter.try_acquire(peer, 250));
+
+        // No more than a burst is saved up.
+        let sent = (0..10)
+            .filter(|_| limiter.try_acquire(peer, 60_000))
+            .count();
+        assert_eq!(sent, 4);
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::{HashSet, VecDeque};
+use std::time::Duration;
+
+use ledger_types::B256;
+
+use crate::clock::elapsed;
+
+/// Bounded set of recently inserted hashes. The oldest hash is evicted
+/// when the capacity is exceeded, or by `expire` once it gets too old.
+#[derive(Debug)]
+pub struct RecentSet {
+    capacity: usize,
+    /// Hashes with the time of their insertion, the oldest first.
+    order: VecDeque<(B256, u64)>,
+    items: HashSet<B256>,
+}
+
+impl RecentSet {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            order: VecDeque::new(),
+            items: HashSet::new(),
+        }
+    }
+
+    /// Inserts the hash at the time `now` in milliseconds of a clock.
+    /// Returns `false` if it is already present.
+    pub fn insert(&mut self, hash: B256, now: u64) -> bool {
+        if !self.items.insert(hash) {
+            return false;
+        }
+
+        self.order.push_back((hash, now));
+        if self.order.len() > self.capacity {
+            if let Some((oldest, _)) = self.order.pop_front() {
+                self.items.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    /// Evicts the hashes inserted at least `ttl` before `now`.
+    pub fn expire(&mut self, now: u64, ttl: Duration) {
+        while let Some(&(hash, inserted_at)) = self.order.front() {
+            if elapsed(now, inserted_at) < ttl {
+                break;
+            }
+            self.order.pop_front();
+            self.items.remove(&hash);
+        }
+    }
+
+    pub fn contains(&self, hash: &B256) -> bool {
+        self.items.contains(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use ledger_types::B256;
+
+    use crate::recent::RecentSet;
+
+    #[test]
+    fn evicts_oldest() {
+        let mut set = RecentSet::new(2);
+        let hashes = [1, 2, 3].map(|i| B256([i; 32]));
+        let now = 0;
+
+        assert!(set.insert(hashes[0], now));
+        assert!(!set.insert(hashes[0], now));
+        assert!(set.insert(hashes[1], now));
+        assert!(set.insert(hashes[2], now));
+
+        assert!(!set.contains(&hashes[0]));
+        assert!(set.contains(&hashes[1]));
+        assert!(set.contains(&hashes[2]));
+    }
+
+    #[test]
+    fn expires_after_ttl() {
+        let mut set = RecentSet::new(10);
+        let hashes = [1, 2].map(|i| B256([i; 32]));
+        let ttl = Duration::from_secs(60);
+
+        set.insert(hashes[0], 0);
+        set.insert(hashes[1], 30_000);
+        set.expire(59_000, ttl);
+        assert!(set.contains(&hashes[0]));
+
+        set.expire(60_000, ttl);
+        assert!(!set.contains(&hashes[0]));
+        assert!(set.contains(&hashes[1]));
+    }
+}
@@ -0,0 +1,241 @@
+use std::error::Error;
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use ledger_transport::{Transport, TransportConfig};
+use ledger_types::{Message, Reply, ReplyTo, B256};
+use serde::Deserialize;
+use tungstenite::{Message as WsMessage, WebSocket};
+
+/// How long the bridge waits for the browser and for the node in turn.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Interval of renewing a subscription before the node expires it.
+const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Request of a browser client, sent as JSON in a text frame. Responses are
+/// sent back as the JSON of `Response`, and pushed blocks as `Message::Block`.
+#[derive(Debug, Deserialize)]
+enum Request {
+    GetTip,
+    BalanceOf(B256),
+    /// Pushes the blocks appended by the node from now on.
+    Subscribe,
+}
+
+/// Serves WebSocket clients until the listener fails. Each connection talks
+/// to the node at `node` over its own socket of the config, like a client.
+pub fn serve(listener: TcpListener, node: SocketAddr, config: TransportConfig) {
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        std::thread::spawn(move || {
+            if let Err(e) = Bridge::accept(stream, node, config).and_then(Bridge::run) {
+                println!("WebSocket connection failed: {}", e);
+            }
+        });
+    }
+}
+
+/// Connection of a browser client bridged to the node.
+struct Bridge {
+    ws: WebSocket<TcpStream>,
+    transport: Transport,
+    node: SocketAddr,
+    next_request_id: u64,
+    /// Time of the last subscription, if the client subscribed.
+    subscribed_at: Option<Instant>,
+}
+
+impl Bridge {
+    fn accept(
+        stream: TcpStream,
+        node: SocketAddr,
+        config: TransportConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        let ws = tungstenite::accept(stream)?;
+        ws.get_ref().set_read_timeout(Some(POLL_INTERVAL))?;
+
+        // Replies must come back to an address the node can reach.
+        let ip = match node.ip() {
+            IpAddr::V4(ip) if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(ip) if ip.is_unspecified() => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            ip => ip,
+        };
+        let transport = Transport::with_config((ip, 0), config)?;
+        transport
+            .set_read_timeout(Some(POLL_INTERVAL))
+            .ok_or("read timeout should be set")?;
+        Ok(Self {
+            ws,
+            transport,
+            node: SocketAddr::new(ip, node.port()),
+            next_request_id: 0,
+            subscribed_at: None,
+        })
+    }
+
+    /// Forwards requests to the node and its responses and blocks back until
+    /// the client disconnects.
+    fn run(mut self) -> Result<(), Box<dyn Error>> {
+        loop {
+            match self.ws.read() {
+                Ok(WsMessage::Text(text)) => self.forward_request(&text)?,
+                Ok(WsMessage::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => {
+                    return Ok(())
+                }
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(e))
+                    if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e.into()),
+            }
+            self.forward_from_node()?;
+
+            if self
+                .subscribed_at
+                .is_some_and(|at| at.elapsed() >= RESUBSCRIBE_INTERVAL)
+            {
+                self.subscribe()?;
+            }
+        }
+    }
+
+    fn forward_request(&mut self, text: &str) -> Result<(), Box<dyn Error>> {
+        let request = match serde_json::from_str::<Request>(text) {
+            Ok(request) => request,
+            Err(e) => {
+                let error = serde_json::json!({ "Error": e.to_string() });
+                self.ws.send(WsMessage::text(error.to_string()))?;
+                return Ok(());
+            }
+        };
+
+        self.next_request_id += 1;
+        let reply_to = ReplyTo {
+            socket: self.socket()?,
+            request_id: self.next_request_id,
+        };
+        let message = match request {
+            Request::GetTip => Message::GetTip(reply_to),
+            Request::BalanceOf(address) => Message::BalanceOf(reply_to, address),
+            Request::Subscribe => return self.subscribe(),
+        };
+        self.transport.send(self.node, &message)?;
+        Ok(())
+    }
+
+    fn subscribe(&mut self) -> Result<(), Box<dyn Error>> {
+        self.transport
+            .send(self.node, &Message::Subscribe(self.socket()?))?;
+        self.subscribed_at = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Passes a response or a pushed block of the node to the client, if one
+    /// arrives in time.
+    fn forward_from_node(&mut self) -> Result<(), Box<dyn Error>> {
+        let value = match self.transport.receive::<serde_json::Value>() {
+            Ok(value) => value,
+            Err(e) if e.is_timeout() => return Ok(()),
+            Err(e) => {
+                println!("Failed to receive from the node: {}", e);
+                return Ok(());
+            }
+        };
+
+        let text = match serde_json::from_value::<Reply>(value.clone()) {
+            Ok(reply) => serde_json::to_string(&reply.response)?,
+            Err(_) if value.get("Block").is_some() => value.to_string(),
+            Err(_) => return Ok(()),
+        };
+        self.ws.send(WsMessage::text(text))?;
+        Ok(())
+    }
+
+    fn socket(&self) -> Result<SocketAddr, Box<dyn Error>> {
+        Ok(self
+            .transport
+            .local_addr()
+            .ok_or("bound socket should have an address")?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{SocketAddr, TcpListener, TcpStream};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use k256::ecdsa::SigningKey;
+    use ledger_transport::{Transport, TransportConfig};
+    use ledger_types::{Message, NodeInfo, PublicKey, TransactionBuilder, B256};
+    use tungstenite::{Message as WsMessage, WebSocket};
+
+    use crate::node::{Config, Node};
+    use crate::websocket::serve;
+
+    /// Runs a node on its own thread, returning its socket.
+    fn spawn_node() -> SocketAddr {
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let signer = SigningKey::from_slice(&[1; 32]).unwrap();
+            let info = NodeInfo {
+                name: "bridged".to_string(),
+                address: B256::address_of(signer.verifying_key()),
+                public_key: PublicKey::from_key(signer.verifying_key()),
+                socket: "127.0.0.1:0".parse().unwrap(),
+                observer: false,
+            };
+            let node = Node::new(signer, info, Config::default());
+            sender.send(node.socket()).unwrap();
+            node.run();
+        });
+        receiver.recv().unwrap()
+    }
+
+    fn receive(ws: &mut WebSocket<TcpStream>) -> serde_json::Value {
+        let WsMessage::Text(text) = ws.read().unwrap() else {
+            panic!("unexpected frame");
+        };
+        serde_json::from_str(&text).unwrap()
+    }
+
+    #[test]
+    fn pushes_blocks() {
+        let node = spawn_node();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || serve(listener, node, TransportConfig::default()));
+
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let (mut ws, _) = tungstenite::client(format!("ws://{}", addr), stream).unwrap();
+
+        // The tip is answered after the subscription is processed.
+        for text in [r#""Subscribe""#, r#""GetTip""#, "not a request"] {
+            ws.send(WsMessage::text(text)).unwrap();
+        }
+        assert_eq!(receive(&mut ws)["Tip"][1], 0);
+        assert!(receive(&mut ws).get("Error").is_some());
+
+        let sender = SigningKey::from_slice(&[2; 32]).unwrap();
+        let tx = TransactionBuilder::new().amount(10).sign(&sender);
+        let client = Transport::new("127.0.0.1:0").unwrap();
+        client
+            .send(node, &Message::Transaction(tx.clone()))
+            .unwrap();
+
+        let pushed = receive(&mut ws);
+        let block: ledger_types::Block = serde_json::from_value(pushed["Block"].clone()).unwrap();
+        assert_eq!(block.data.number, 1);
+        assert!(block
+            .data
+            .transactions
+            .iter()
+            .any(|mined| mined.hash == tx.hash));
+    }
+}